@@ -0,0 +1,515 @@
+//! texel tuning of the evaluation parameters
+//!
+//! usage: tune <dataset> [--params <file>] [--out <file>] [--epochs <n>] [--rate <r>]
+//!        [--threads <n>] [--k <k>]
+//!
+//! every dataset line holds a FEN followed by the game result from white's point of view,
+//! written as 1-0, 0-1, 1/2-1/2 or as 1.0, 0.5, 0.0, optionally in quotes or brackets
+//!
+//! positions of recognized endgames are left out since their evaluation does not depend on the
+//! parameters, and the endgame scale factor of every other position is kept as it is with the
+//! starting parameters. the king danger weights are not tuned, see FROZEN
+
+use std::thread;
+
+use mautlie_rs::eval::params::{self, EvalParams};
+use mautlie_rs::eval::psqt::{MAX_PHASE, Score};
+use mautlie_rs::eval::trace::Trace;
+use mautlie_rs::eval::{endgame, evaluate_terms, scaled_side};
+use mautlie_rs::state::{self, Color, fen};
+
+const DEFAULT_EPOCHS: usize = 1000;
+const DEFAULT_RATE: f64 = 1.0;
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+const EPSILON: f64 = 1e-8;
+/// epochs between progress reports and saves of the parameters
+const REPORT_INTERVAL: usize = 50;
+/// parameters of the king danger, which grows quadratically with the attack units and so is
+/// not linear in them, they keep their starting values and their part of every evaluation
+/// stays in the constant
+const FROZEN: &[&str] = &[
+    "king_attack_weights",
+    "king_min_attackers",
+    "king_max_danger",
+];
+
+struct Options {
+    dataset: String,
+    params: Option<String>,
+    out: String,
+    epochs: usize,
+    rate: f64,
+    threads: usize,
+    k: Option<f64>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        dataset: String::new(),
+        params: None,
+        out: "tuned.toml".to_string(),
+        epochs: DEFAULT_EPOCHS,
+        rate: DEFAULT_RATE,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        k: None,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("Missing value for {}", arg));
+        match arg.as_str() {
+            "--params" => options.params = Some(value()?.clone()),
+            "--out" => options.out = value()?.clone(),
+            "--epochs" => options.epochs = value()?.parse().map_err(|_| "Invalid epochs")?,
+            "--rate" => options.rate = value()?.parse().map_err(|_| "Invalid rate")?,
+            "--threads" => options.threads = value()?.parse().map_err(|_| "Invalid threads")?,
+            "--k" => options.k = Some(value()?.parse().map_err(|_| "Invalid k")?),
+            _ if options.dataset.is_empty() => options.dataset = arg.clone(),
+            _ => return Err(format!("Unknown argument {}", arg)),
+        }
+    }
+
+    if options.dataset.is_empty() {
+        return Err(
+            "Usage: tune <dataset> [--params <file>] [--out <file>] [--epochs <n>] \
+                    [--rate <r>] [--threads <n>] [--k <k>]"
+                .to_string(),
+        );
+    }
+    options.threads = options.threads.max(1);
+    Ok(options)
+}
+
+/// collects the terms of one position as white minus black counts per parameter
+#[derive(Default)]
+struct Coefficients {
+    terms: Vec<(usize, i32)>,
+}
+
+impl Trace for Coefficients {
+    fn add(&mut self, index: usize, color: Color, count: i32) {
+        let count = match color {
+            Color::White => count,
+            Color::Black => -count,
+        };
+        self.terms.push((index, count));
+    }
+}
+
+/// a labelled position reduced to what the error depends on
+struct Entry {
+    /// flat index of the middlegame value of a score parameter and its count
+    terms: Vec<(u16, i16)>,
+    /// the part of the evaluation that is not linear in the parameters
+    constant: (f32, f32),
+    /// share of the middlegame value in the tapered evaluation
+    mg_weight: f32,
+    /// factor applied to the endgame value, taken with the starting parameters even though
+    /// the side it favours may change during tuning
+    eg_scale: f32,
+    result: f32,
+}
+
+/// why a dataset line gives no entry
+#[derive(Debug, PartialEq)]
+enum Skip {
+    Unreadable,
+    /// a recognized endgame, whose evaluation replaces the tuned terms
+    Endgame,
+}
+
+fn parse_result(token: &str) -> Option<f32> {
+    match token.trim_matches(|c| matches!(c, '"' | '[' | ']' | ';' | '(' | ')')) {
+        "1-0" | "1.0" | "1" => Some(1.0),
+        "0-1" | "0.0" | "0" => Some(0.0),
+        "1/2-1/2" | "0.5" => Some(0.5),
+        _ => None,
+    }
+}
+
+/// splits a dataset line into a FEN the parser accepts and the result
+fn parse_line(line: &str) -> Option<(String, f32)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let result = parse_result(fields.last()?)?;
+    if fields.len() < 5 {
+        return None;
+    }
+    // keep the clocks only if they are there, some datasets put opcodes in their place
+    let mut fen: Vec<&str> = fields[..4].to_vec();
+    for field in &fields[4..fields.len().min(6)] {
+        if field.parse::<u16>().is_err() {
+            break;
+        }
+        fen.push(field);
+    }
+    Some((fen.join(" "), result))
+}
+
+fn make_entry(line: &str, params: &[i32]) -> Result<Entry, Skip> {
+    let (fen, result) = parse_line(line).ok_or(Skip::Unreadable)?;
+    let board = fen::fen_to_board(&fen).map_err(|_| Skip::Unreadable)?;
+    if endgame::probe_evaluation(&board).is_some() {
+        return Err(Skip::Endgame);
+    }
+
+    let mut coefficients = Coefficients::default();
+    let total = evaluate_terms(&board, &mut coefficients);
+
+    let mut terms = coefficients.terms;
+    terms.sort_unstable_by_key(|&(index, _)| index);
+    let mut merged: Vec<(u16, i16)> = Vec::with_capacity(terms.len());
+    for (index, count) in terms {
+        match merged.last_mut() {
+            Some(last) if last.0 as usize == index => last.1 += count as i16,
+            _ => merged.push((index as u16, count as i16)),
+        }
+    }
+    merged.retain(|&(_, count)| count != 0);
+
+    let linear = merged.iter().fold(Score::ZERO, |sum, &(index, count)| {
+        let index = index as usize;
+        sum + Score::new(params[index], params[index + 1]) * count as i32
+    });
+    let constant = total - linear;
+    let scale = endgame::probe_scale(&board, scaled_side(total));
+
+    Ok(Entry {
+        terms: merged,
+        constant: (constant.mg as f32, constant.eg as f32),
+        mg_weight: board.psqt.phase.clamp(0, MAX_PHASE) as f32 / MAX_PHASE as f32,
+        eg_scale: scale as f32 / endgame::SCALE_NORMAL as f32,
+        result,
+    })
+}
+
+fn load_dataset(path: &str, params: &[i32], threads: usize) -> Result<Vec<Entry>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+    let lines: Vec<&str> = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    let chunk = lines.len().div_ceil(threads).max(1);
+
+    let parsed: Vec<Vec<Result<Entry, Skip>>> = thread::scope(|scope| {
+        let handles: Vec<_> = lines
+            .chunks(chunk)
+            .map(|lines| {
+                scope.spawn(move || lines.iter().map(|line| make_entry(line, params)).collect())
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("dataset thread panicked"))
+            .collect()
+    });
+
+    let mut entries = Vec::with_capacity(lines.len());
+    let (mut unreadable, mut endgames) = (0, 0);
+    for parsed in parsed.into_iter().flatten() {
+        match parsed {
+            Ok(entry) => entries.push(entry),
+            Err(Skip::Unreadable) => unreadable += 1,
+            Err(Skip::Endgame) => endgames += 1,
+        }
+    }
+    if unreadable > 0 {
+        eprintln!("skipped {} unreadable lines", unreadable);
+    }
+    if endgames > 0 {
+        eprintln!("skipped {} recognized endgames", endgames);
+    }
+    if entries.is_empty() {
+        return Err("Dataset has no usable positions".to_string());
+    }
+    Ok(entries)
+}
+
+/// returns the tapered evaluation of the entry from white's point of view
+fn entry_eval(entry: &Entry, params: &[f64]) -> f64 {
+    let (mut mg, mut eg) = (entry.constant.0 as f64, entry.constant.1 as f64);
+    for &(index, count) in &entry.terms {
+        mg += params[index as usize] * count as f64;
+        eg += params[index as usize + 1] * count as f64;
+    }
+    let weight = entry.mg_weight as f64;
+    mg * weight + eg * entry.eg_scale as f64 * (1.0 - weight)
+}
+
+fn sigmoid(eval: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval / 400.0))
+}
+
+/// returns the mean squared error over the dataset
+fn error(entries: &[Entry], params: &[f64], k: f64, threads: usize) -> f64 {
+    let chunk = entries.len().div_ceil(threads);
+    let sum: f64 = thread::scope(|scope| {
+        let handles: Vec<_> = entries
+            .chunks(chunk)
+            .map(|entries| {
+                scope.spawn(move || {
+                    entries
+                        .iter()
+                        .map(|entry| {
+                            let diff = entry.result as f64 - sigmoid(entry_eval(entry, params), k);
+                            diff * diff
+                        })
+                        .sum::<f64>()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    });
+    sum / entries.len() as f64
+}
+
+/// returns the gradient of the mean squared error with respect to every parameter
+fn gradient(entries: &[Entry], params: &[f64], k: f64, threads: usize) -> Vec<f64> {
+    let chunk = entries.len().div_ceil(threads);
+    let partials: Vec<Vec<f64>> = thread::scope(|scope| {
+        let handles: Vec<_> = entries
+            .chunks(chunk)
+            .map(|entries| {
+                scope.spawn(move || {
+                    let mut gradient = vec![0.0; params.len()];
+                    for entry in entries {
+                        let s = sigmoid(entry_eval(entry, params), k);
+                        // derivative of (result - s)^2 with respect to the evaluation
+                        let d = -2.0
+                            * (entry.result as f64 - s)
+                            * s
+                            * (1.0 - s)
+                            * k
+                            * std::f64::consts::LN_10
+                            / 400.0;
+                        let mg = d * entry.mg_weight as f64;
+                        let eg = d * entry.eg_scale as f64 * (1.0 - entry.mg_weight as f64);
+                        for &(index, count) in &entry.terms {
+                            gradient[index as usize] += mg * count as f64;
+                            gradient[index as usize + 1] += eg * count as f64;
+                        }
+                    }
+                    gradient
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    let mut gradient = vec![0.0; params.len()];
+    for partial in partials {
+        for (sum, value) in gradient.iter_mut().zip(partial) {
+            *sum += value;
+        }
+    }
+    let count = entries.len() as f64;
+    gradient.iter_mut().for_each(|g| *g /= count);
+    for name in FROZEN {
+        let (offset, len) = EvalParams::range_of(name).expect("unknown frozen parameter");
+        gradient[offset..offset + len].fill(0.0);
+    }
+    gradient
+}
+
+/// finds the scaling constant that best maps the starting evaluation onto the results
+fn tune_k(entries: &[Entry], params: &[f64], threads: usize) -> f64 {
+    let (mut low, mut high) = (0.01, 5.0);
+    for _ in 0..40 {
+        let a = low + (high - low) / 3.0;
+        let b = high - (high - low) / 3.0;
+        if error(entries, params, a, threads) < error(entries, params, b, threads) {
+            high = b;
+        } else {
+            low = a;
+        }
+    }
+    (low + high) / 2.0
+}
+
+fn save(params: &[f64], path: &str) -> Result<(), String> {
+    let rounded: Vec<i32> = params.iter().map(|p| p.round() as i32).collect();
+    EvalParams::from_slice(&rounded)?.save(path)
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let start = match &options.params {
+        Some(path) => EvalParams::load(path)?,
+        None => EvalParams::default(),
+    };
+    let flat = start.to_vec();
+    // boards pick up the current parameters, which the constant parts are computed against
+    params::set_current(start);
+
+    let entries = load_dataset(&options.dataset, &flat, options.threads)?;
+    println!("loaded {} positions", entries.len());
+
+    let mut params: Vec<f64> = flat.iter().map(|&p| p as f64).collect();
+    let k = match options.k {
+        Some(k) => k,
+        None => tune_k(&entries, &params, options.threads),
+    };
+    println!(
+        "k = {:.4}, error = {:.6}",
+        k,
+        error(&entries, &params, k, options.threads)
+    );
+
+    let mut m = vec![0.0; params.len()];
+    let mut v = vec![0.0; params.len()];
+    for epoch in 1..=options.epochs {
+        let gradient = gradient(&entries, &params, k, options.threads);
+        for i in 0..params.len() {
+            m[i] = BETA1 * m[i] + (1.0 - BETA1) * gradient[i];
+            v[i] = BETA2 * v[i] + (1.0 - BETA2) * gradient[i] * gradient[i];
+            let m_hat = m[i] / (1.0 - BETA1.powi(epoch as i32));
+            let v_hat = v[i] / (1.0 - BETA2.powi(epoch as i32));
+            params[i] -= options.rate * m_hat / (v_hat.sqrt() + EPSILON);
+        }
+
+        if epoch % REPORT_INTERVAL == 0 || epoch == options.epochs {
+            println!(
+                "epoch {}, error = {:.6}",
+                epoch,
+                error(&entries, &params, k, options.threads)
+            );
+            save(&params, &options.out)?;
+        }
+    }
+
+    println!("wrote {}", options.out);
+    Ok(())
+}
+
+fn main() {
+    state::init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = parse_options(&args).and_then(run) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mautlie_rs::eval::evaluate;
+
+    const FENS: &[&str] = &[
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        "2r3k1/pp3ppp/4p3/3pP3/3P4/P4N2/1P3PPP/2R3K1 b - - 0 25",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        // opposite colored bishops scale the endgame part down
+        "8/4kb2/8/3p4/3P4/2B1K3/8/8 w - - 0 1",
+        "8/8/4k3/8/4P3/4K3/8/r6R b - - 0 1",
+    ];
+
+    fn entries(params: &[i32]) -> Vec<Entry> {
+        FENS.iter()
+            .zip(["1-0", "0-1", "1/2-1/2"].iter().cycle())
+            .map(|(fen, result)| make_entry(&format!("{} {}", fen, result), params).unwrap())
+            .collect()
+    }
+
+    fn float_params() -> Vec<f64> {
+        EvalParams::default()
+            .to_vec()
+            .iter()
+            .map(|&p| p as f64)
+            .collect()
+    }
+
+    #[test]
+    fn linear_evaluation_matches_evaluate() {
+        state::init();
+        let flat = EvalParams::default().to_vec();
+        let params = float_params();
+        for (fen, entry) in FENS.iter().zip(entries(&flat)) {
+            let board = fen::fen_to_board(fen).unwrap();
+            let white = match board.side_to_move {
+                Color::White => evaluate(&board),
+                Color::Black => -evaluate(&board),
+            };
+            let linear = entry_eval(&entry, &params);
+            // the evaluation rounds the scaled and the tapered value down, the tuner does not
+            assert!(
+                (linear - white as f64).abs() < 2.0,
+                "{}: traced {} but evaluated {}",
+                fen,
+                linear,
+                white
+            );
+        }
+        assert!(entries(&flat)[4].eg_scale < 1.0);
+    }
+
+    #[test]
+    fn recognized_endgames_are_skipped() {
+        state::init();
+        let flat = EvalParams::default().to_vec();
+        for fen in [
+            "8/8/8/4k3/8/8/8/KBN5 w - - 0 1",
+            "8/8/8/4k3/8/8/4P3/4K3 b - - 0 1",
+        ] {
+            let line = format!("{} 1-0", fen);
+            assert_eq!(make_entry(&line, &flat).err(), Some(Skip::Endgame));
+        }
+        assert_eq!(
+            make_entry("not a position 1-0", &flat).err(),
+            Some(Skip::Unreadable)
+        );
+    }
+
+    #[test]
+    fn gradient_matches_finite_differences() {
+        state::init();
+        let entries = entries(&EvalParams::default().to_vec());
+        let params = float_params();
+        let k = 1.2;
+        let gradient = gradient(&entries, &params, k, 2);
+
+        let mut indices: Vec<usize> = entries
+            .iter()
+            .flat_map(|entry| entry.terms.iter())
+            .flat_map(|&(index, _)| [index as usize, index as usize + 1])
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        assert!(indices.len() > 20);
+
+        let step = 0.5;
+        for &index in indices.iter().step_by(7) {
+            let mut shifted = params.clone();
+            shifted[index] = params[index] + step;
+            let above = error(&entries, &shifted, k, 1);
+            shifted[index] = params[index] - step;
+            let below = error(&entries, &shifted, k, 1);
+            let numeric = (above - below) / (2.0 * step);
+            assert!(
+                (numeric - gradient[index]).abs() <= 1e-9 + 1e-4 * numeric.abs(),
+                "parameter {}: analytic {} numeric {}",
+                index,
+                gradient[index],
+                numeric
+            );
+        }
+    }
+
+    #[test]
+    fn king_danger_weights_are_frozen() {
+        state::init();
+        let entries = entries(&EvalParams::default().to_vec());
+        let gradient = gradient(&entries, &float_params(), 1.0, 1);
+        for name in FROZEN {
+            let (offset, len) = EvalParams::range_of(name).unwrap();
+            assert!(gradient[offset..offset + len].iter().all(|&g| g == 0.0));
+            assert!(entries.iter().all(|entry| {
+                entry
+                    .terms
+                    .iter()
+                    .all(|&(index, _)| !(offset..offset + len).contains(&(index as usize)))
+            }));
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use strum::IntoEnumIterator;
+
+use crate::state::{Color, ColoredPiece, GenericPiece, board::Board, board_move_gen::Move};
+use crate::util::bithelpers::BitFunctions;
+
+use params::{OFFSETS, score_index};
+use pawns::{PawnEntry, PawnTable};
+use psqt::Score;
+use trace::{EvalTrace, NoTrace, TermCollector, Trace};
+
+pub mod endgame;
+pub mod king_safety;
+pub mod kpk;
+pub mod mobility;
+#[cfg(feature = "nnue")]
+pub mod nnue;
+pub mod params;
+pub mod pawns;
+pub mod psqt;
+pub mod trace;
+
+/// material value of each generic piece in centipawns, indexed by GenericPiece, used where a
+/// single value per piece is needed such as move ordering
+pub const PIECE_VALUES: [i32; 6] = [100, 320, 330, 500, 900, 0];
+
+/// returns the value of the given generic piece in centipawns
+pub fn piece_value(piece: GenericPiece) -> i32 {
+    PIECE_VALUES[piece as usize]
+}
+
+/// evaluates positions with caches that make repeated evaluation during a search cheap, each
+/// search thread owns one
+///
+/// with the nnue feature and a network set, positions are evaluated by the network and the
+/// search has to report every move through make_move and unmake_move, otherwise the hand
+/// written evaluation is used
+#[derive(Default)]
+pub struct Evaluator {
+    pawn_table: PawnTable,
+    #[cfg(feature = "nnue")]
+    nnue: Option<nnue::NnueState>,
+}
+
+impl Evaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.pawn_table.clear();
+    }
+
+    /// switches to evaluating with the given network, or back to the hand written evaluation
+    #[cfg(feature = "nnue")]
+    pub fn set_network(&mut self, network: Option<std::sync::Arc<nnue::Network>>) {
+        self.nnue = network.map(nnue::NnueState::new);
+    }
+
+    /// prepares for a search from the given position
+    #[cfg_attr(not(feature = "nnue"), allow(unused_variables))]
+    pub fn reset(&mut self, board: &Board) {
+        #[cfg(feature = "nnue")]
+        if let Some(nnue) = &mut self.nnue {
+            nnue.reset(board);
+        }
+    }
+
+    /// follows a move the board has just made
+    #[cfg_attr(not(feature = "nnue"), allow(unused_variables))]
+    pub fn make_move(&mut self, board: &Board, m: Move) {
+        #[cfg(feature = "nnue")]
+        if let Some(nnue) = &mut self.nnue {
+            nnue.make_move(board, m);
+        }
+    }
+
+    pub fn make_null_move(&mut self) {
+        #[cfg(feature = "nnue")]
+        if let Some(nnue) = &mut self.nnue {
+            nnue.make_null_move();
+        }
+    }
+
+    /// takes back the last move or null move
+    pub fn unmake_move(&mut self) {
+        #[cfg(feature = "nnue")]
+        if let Some(nnue) = &mut self.nnue {
+            nnue.unmake_move();
+        }
+    }
+
+    /// returns the evaluation of the position from the perspective of the side to move
+    pub fn evaluate(&mut self, board: &Board) -> i32 {
+        #[cfg(feature = "nnue")]
+        if let Some(nnue) = &self.nnue {
+            // exact endgame knowledge is trusted over the network
+            if let Some(score) = endgame::probe_evaluation(board) {
+                return for_side_to_move(board, score);
+            }
+            return nnue.evaluate(board);
+        }
+
+        let pawns = self.pawn_table.probe(board);
+        evaluate_with_pawns(board, &pawns)
+    }
+}
+
+/// returns the evaluation of the position from the perspective of the side to move, without
+/// using any cache
+pub fn evaluate(board: &Board) -> i32 {
+    evaluate_with_pawns(board, &pawns::evaluate_pawns(board, &mut NoTrace))
+}
+
+/// returns the untapered evaluation from white's point of view, reporting every linear term to
+/// the trace including the material and piece-square values
+pub fn evaluate_terms<T: Trace>(board: &Board, trace: &mut T) -> Score {
+    for piece in ColoredPiece::iter() {
+        let generic = piece.generic() as usize;
+        let color = piece.color();
+        board.pieces[piece as usize].bit_for_each(|square| {
+            // the tables are written from white's point of view with a8 first
+            let square = match color {
+                Color::White => square ^ 56,
+                Color::Black => square,
+            };
+            trace.add(score_index(OFFSETS.material, generic), color, 1);
+            trace.add(
+                score_index(OFFSETS.pst, generic * 64 + square as usize),
+                color,
+                1,
+            );
+        });
+    }
+
+    let pawns = pawns::evaluate_pawns(board, trace);
+    board.psqt.score + pawns.score + positional_terms(board, &pawns, trace)
+}
+
+/// rows of the evaluation trace, named after the parameters they are made of except for the
+/// king danger which is not linear in its parameters
+const TRACE_TERMS: &[&str] = &[
+    "material",
+    "pst",
+    "doubled",
+    "isolated",
+    "backward",
+    "connected",
+    "passed",
+    "passed_blocked",
+    "candidate",
+    "passed_enemy_king_distance",
+    "passed_own_king_distance",
+    "mobility",
+    "king_danger",
+    "shield_near",
+    "shield_far",
+    "storm",
+    "king_semi_open_file",
+    "king_open_file",
+];
+
+/// returns the contribution of every term of the hand written evaluation to each side, along
+/// with the phase used to blend them
+pub fn eval_trace(board: &Board) -> EvalTrace {
+    let params = &board.psqt.params;
+    let mut collector = TermCollector::new(params, TRACE_TERMS);
+    let total = evaluate_terms(board, &mut collector);
+
+    let mut trace = EvalTrace {
+        terms: collector.into_terms(),
+        phase: board.psqt.phase,
+        scale: endgame::SCALE_NORMAL,
+        endgame: endgame::probe_evaluation(board),
+        side_to_move: board.side_to_move,
+    };
+    debug_assert_eq!(trace.total(), total, "evaluation trace misses a term");
+    trace.scale = endgame::probe_scale(board, scaled_side(total));
+    debug_assert_eq!(trace.score(), evaluate(board), "evaluation trace disagrees");
+    trace
+}
+
+fn evaluate_with_pawns(board: &Board, pawns: &PawnEntry) -> i32 {
+    let score = match endgame::probe_evaluation(board) {
+        Some(score) => score,
+        None => {
+            let score =
+                board.psqt.score + pawns.score + positional_terms(board, pawns, &mut NoTrace);
+            scaled(board, score).taper(board.psqt.phase)
+        }
+    };
+    for_side_to_move(board, score)
+}
+
+/// turns a score from white's point of view into one from the side to move's
+fn for_side_to_move(board: &Board, score: i32) -> i32 {
+    match board.side_to_move {
+        Color::White => score,
+        Color::Black => -score,
+    }
+}
+
+/// returns the score with its endgame part scaled by the knowledge about the side ahead
+fn scaled(board: &Board, score: Score) -> Score {
+    let scale = endgame::probe_scale(board, scaled_side(score));
+    Score::new(score.mg, score.eg * scale / endgame::SCALE_NORMAL)
+}
+
+/// returns the side the endgame part of the score favours, whose winning chances get scaled
+pub fn scaled_side(score: Score) -> Color {
+    if score.eg >= 0 {
+        Color::White
+    } else {
+        Color::Black
+    }
+}
+
+/// returns the terms that depend on more than material and pawns, from white's point of view
+fn positional_terms<T: Trace>(board: &Board, pawns: &PawnEntry, trace: &mut T) -> Score {
+    let white_passed = pawns.passed[Color::White as usize];
+    let black_passed = pawns.passed[Color::Black as usize];
+    let mut score = pawns::passed_pawn_extras(board, white_passed, Color::White, trace);
+    score -= pawns::passed_pawn_extras(board, black_passed, Color::Black, trace);
+
+    let (white_mobility, white_attack) = mobility::evaluate_mobility(board, Color::White, trace);
+    let (black_mobility, black_attack) = mobility::evaluate_mobility(board, Color::Black, trace);
+    score += white_mobility - black_mobility;
+    // each side's danger comes from the other side's attack
+    let params = &board.psqt.params;
+    let white_danger = black_attack.danger(params);
+    let black_danger = white_attack.danger(params);
+    trace.king_danger(Color::White, white_danger);
+    trace.king_danger(Color::Black, black_danger);
+    score += white_danger - black_danger;
+    score += king_safety::king_shelter(board, Color::White, trace);
+    score -= king_safety::king_shelter(board, Color::Black, trace);
+
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::fen::fen_to_board;
+
+    #[test]
+    fn trace_terms_sum_to_the_evaluation() {
+        crate::state::init();
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "6k1/5ppp/8/8/5n1q/8/5PPP/6K1 w - - 0 1",
+            "2r3k1/pp3ppp/4p3/3pP3/3P4/P4N2/1P3PPP/2R3K1 b - - 0 25",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            // scaled endgames
+            "8/4kb2/8/3p4/3P4/2B1K3/8/8 w - - 0 1",
+            "8/8/4k3/8/4P3/4K3/8/r6R b - - 0 1",
+            // recognized endgames
+            "8/8/8/4k3/8/8/8/KBN5 w - - 0 1",
+            "8/8/8/4k3/8/8/4P3/4K3 b - - 0 1",
+        ] {
+            let board = fen_to_board(fen).unwrap();
+            let trace = eval_trace(&board);
+            assert_eq!(
+                trace.total(),
+                evaluate_terms(&board, &mut NoTrace),
+                "{}",
+                fen
+            );
+            assert_eq!(trace.score(), evaluate(&board), "{}", fen);
+            match trace.endgame {
+                Some(score) => assert_eq!(for_side_to_move(&board, score), evaluate(&board)),
+                None => assert_eq!(
+                    for_side_to_move(&board, trace.blended()),
+                    evaluate(&board),
+                    "{}",
+                    fen
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,486 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::sync::LazyLock;
+
+use crate::state::game_constants::FILE_A;
+use crate::state::hash::{material_count, material_key_unit};
+use crate::state::{Color, ColoredPiece, GenericPiece, board::Board};
+use crate::util::bithelpers::BitFunctions;
+
+use super::pawns::{distance, passed_pawns};
+use super::{kpk, piece_value};
+
+/// score of a position the strong side is known to win, well above any normal evaluation but
+/// below mate scores
+pub const KNOWN_WIN: i32 = 10_000;
+/// scale factor that leaves the endgame part of the evaluation unchanged
+pub const SCALE_NORMAL: i32 = 64;
+pub const SCALE_DRAW: i32 = 0;
+
+/// an evaluation replacing the hand written one, from the given strong side's point of view
+pub type EndgameFn = fn(&Board, Color) -> i32;
+/// a factor out of SCALE_NORMAL for the endgame part of the evaluation when the given strong
+/// side is ahead, or None if the position is not one the function knows about
+pub type ScaleFn = fn(&Board, Color) -> Option<i32>;
+
+/// every generic piece but the king, in GenericPiece order
+const PIECES: [GenericPiece; 5] = [
+    GenericPiece::Pawn,
+    GenericPiece::Knight,
+    GenericPiece::Bishop,
+    GenericPiece::Rook,
+    GenericPiece::Queen,
+];
+
+/// returns the number of each generic piece but the king the color has, indexed by
+/// GenericPiece
+fn counts(board: &Board, color: Color) -> [u32; 5] {
+    PIECES.map(|generic| {
+        material_count(
+            board.state.material_key,
+            ColoredPiece::from_parts(color, generic),
+        )
+    })
+}
+
+fn piece_bb(board: &Board, color: Color, generic: GenericPiece) -> u64 {
+    board.pieces[ColoredPiece::from_parts(color, generic) as usize]
+}
+
+/// returns the square as seen by the color, so every function can treat the strong side as
+/// white
+fn relative(square: u32, color: Color) -> u32 {
+    match color {
+        Color::White => square,
+        Color::Black => square ^ 56,
+    }
+}
+
+fn king(board: &Board, color: Color) -> u32 {
+    board
+        .king_square(color)
+        .expect("endgame evaluation without a king")
+}
+
+fn square_color(square: u32) -> u32 {
+    (square / 8 + square % 8) & 1
+}
+
+/// bonus for driving the king towards the edge of the board
+fn push_to_edge(square: u32) -> i32 {
+    let file = (square % 8).min(7 - square % 8) as i32;
+    let rank = (square / 8).min(7 - square / 8) as i32;
+    90 - (7 * file * file / 2 + 7 * rank * rank / 2)
+}
+
+/// bonus for bringing the two kings together
+fn push_close(a: u32, b: u32) -> i32 {
+    140 - 20 * distance(a, b)
+}
+
+/// bonus for driving the king towards the a1 and h8 corners
+fn push_to_corner(square: u32) -> i32 {
+    (7 - (square / 8) as i32 - (square % 8) as i32).abs()
+}
+
+fn material(board: &Board, color: Color) -> i32 {
+    counts(board, color)
+        .iter()
+        .enumerate()
+        .map(|(generic, &count)| piece_value(PIECES[generic]) * count as i32)
+        .sum()
+}
+
+/// material too weak to mate against a bare king
+pub fn draw(_board: &Board, _strong: Color) -> i32 {
+    0
+}
+
+/// king and a heavy piece against a bare king, won by driving the king to the edge
+pub fn kxk(board: &Board, strong: Color) -> i32 {
+    let strong_king = king(board, strong);
+    let weak_king = king(board, strong.opposite());
+    KNOWN_WIN
+        + material(board, strong)
+        + push_to_edge(weak_king)
+        + push_close(strong_king, weak_king)
+}
+
+/// king, bishop and knight against a bare king, won by driving the king into a corner the
+/// bishop controls
+pub fn kbnk(board: &Board, strong: Color) -> i32 {
+    let strong_king = king(board, strong);
+    let weak_king = king(board, strong.opposite());
+    let bishop = piece_bb(board, strong, GenericPiece::Bishop).get_lsb();
+    // a1 is dark, so a light squared bishop needs the a8 and h1 corners instead
+    let corner_king = if square_color(bishop) == square_color(0) {
+        weak_king
+    } else {
+        weak_king ^ 7
+    };
+    KNOWN_WIN
+        + material(board, strong)
+        + push_close(strong_king, weak_king)
+        + 420 * push_to_corner(corner_king)
+}
+
+/// king and pawn against king, looked up in the bitbase
+pub fn kpk(board: &Board, strong: Color) -> i32 {
+    let strong_king = relative(king(board, strong), strong);
+    let weak_king = relative(king(board, strong.opposite()), strong);
+    let pawn = relative(
+        piece_bb(board, strong, GenericPiece::Pawn).get_lsb(),
+        strong,
+    );
+    let side_to_move = if board.side_to_move == strong {
+        Color::White
+    } else {
+        Color::Black
+    };
+
+    if !kpk::probe(side_to_move, strong_king, pawn, weak_king) {
+        return 0;
+    }
+    KNOWN_WIN + piece_value(GenericPiece::Pawn) + (pawn / 8) as i32
+}
+
+/// king, bishop and rook pawns against a bare king, drawn when the bishop does not control the
+/// promotion square and the defending king reaches it
+pub fn kbpk(board: &Board, strong: Color) -> Option<i32> {
+    let pawns = piece_bb(board, strong, GenericPiece::Pawn);
+    let file = pawns.get_lsb() % 8;
+    let file_bb = FILE_A << file;
+    if (file != 0 && file != 7) || pawns & !file_bb != 0 {
+        return None;
+    }
+
+    let queening = relative(56 + file, strong);
+    let bishop = piece_bb(board, strong, GenericPiece::Bishop).get_lsb();
+    let weak_king = king(board, strong.opposite());
+    if square_color(queening) != square_color(bishop) && distance(weak_king, queening) <= 1 {
+        return Some(SCALE_DRAW);
+    }
+    None
+}
+
+/// king, rook and pawn against king and rook, drawn when the defence reaches one of the
+/// Philidor setups
+pub fn krpkr(board: &Board, strong: Color) -> Option<i32> {
+    let weak = strong.opposite();
+    let strong_king = relative(king(board, strong), strong);
+    let weak_king = relative(king(board, weak), strong);
+    let strong_rook = relative(
+        piece_bb(board, strong, GenericPiece::Rook).get_lsb(),
+        strong,
+    );
+    let weak_rook = relative(piece_bb(board, weak, GenericPiece::Rook).get_lsb(), strong);
+    let pawn = relative(
+        piece_bb(board, strong, GenericPiece::Pawn).get_lsb(),
+        strong,
+    );
+
+    let rank = pawn / 8;
+    let queening = 56 + pawn % 8;
+    let tempo = (board.side_to_move == strong) as u32;
+    let defends_queening = distance(weak_king, queening) <= 1;
+
+    // third rank defence: the rook cuts the king off until the pawn advances, then checks from
+    // behind
+    if rank <= 4
+        && defends_queening
+        && strong_king / 8 <= 4
+        && (weak_rook / 8 == 5 || (rank <= 2 && strong_rook / 8 != 5))
+    {
+        return Some(SCALE_DRAW);
+    }
+    // the pawn has advanced with the attacking king behind it, so the rook checks from behind
+    if rank == 5
+        && defends_queening
+        && strong_king / 8 + tempo <= 5
+        && (weak_rook / 8 == 0 || (tempo == 0 && (weak_rook % 8).abs_diff(pawn % 8) >= 3))
+    {
+        return Some(SCALE_DRAW);
+    }
+    if rank >= 5
+        && weak_king == queening
+        && weak_rook / 8 == 0
+        && (tempo == 0 || distance(strong_king, pawn) >= 2)
+    {
+        return Some(SCALE_DRAW);
+    }
+    None
+}
+
+/// bishops of opposite colors are hard to win with when they are all that is left besides the
+/// pawns, or with just one more rook or knight each, scaled by how many passed pawns the strong
+/// side has
+pub fn opposite_bishops(board: &Board, strong: Color) -> Option<i32> {
+    let weak = strong.opposite();
+    let strong_bishops = piece_bb(board, strong, GenericPiece::Bishop);
+    let weak_bishops = piece_bb(board, weak, GenericPiece::Bishop);
+    if strong_bishops.count_set_bits() != 1
+        || weak_bishops.count_set_bits() != 1
+        || square_color(strong_bishops.get_lsb()) == square_color(weak_bishops.get_lsb())
+    {
+        return None;
+    }
+
+    let [_, knights, _, rooks, queens] = counts(board, strong);
+    let [_, weak_knights, _, weak_rooks, weak_queens] = counts(board, weak);
+    let pure = knights + rooks + queens + weak_knights + weak_rooks + weak_queens == 0;
+    // the extra pieces have to match, otherwise the side with more is not held by the bishops
+    let near_pure = queens + weak_queens == 0
+        && knights + rooks == 1
+        && knights == weak_knights
+        && rooks == weak_rooks;
+    if !pure && !near_pure {
+        return None;
+    }
+
+    let own = piece_bb(board, strong, GenericPiece::Pawn);
+    let enemy = piece_bb(board, weak, GenericPiece::Pawn);
+    let passed = passed_pawns(own, enemy, strong).count_set_bits() as i32;
+    let base = if pure { 18 } else { 36 };
+    Some((base + 4 * passed).min(SCALE_NORMAL))
+}
+
+/// returns the material key of a signature such as "KBNK", the pieces before the second king
+/// belonging to strong
+fn signature_key(signature: &str, strong: Color) -> u64 {
+    let weak_start = signature[1..]
+        .find('K')
+        .map(|index| index + 1)
+        .expect("endgame signature without a second king");
+    let mut key = 0;
+    for (index, c) in signature.chars().enumerate() {
+        let color = if index < weak_start {
+            strong
+        } else {
+            strong.opposite()
+        };
+        let generic = match c {
+            'P' => GenericPiece::Pawn,
+            'N' => GenericPiece::Knight,
+            'B' => GenericPiece::Bishop,
+            'R' => GenericPiece::Rook,
+            'Q' => GenericPiece::Queen,
+            'K' => GenericPiece::King,
+            _ => panic!("invalid piece in endgame signature {}", signature),
+        };
+        key += material_key_unit(ColoredPiece::from_parts(color, generic));
+    }
+    key
+}
+
+/// hashes a material key with a single multiplication, the registry being probed on every
+/// evaluation
+#[derive(Default)]
+pub struct MaterialKeyHasher(u64);
+
+impl Hasher for MaterialKeyHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, _bytes: &[u8]) {
+        unreachable!("material keys are hashed as u64");
+    }
+
+    fn write_u64(&mut self, key: u64) {
+        self.0 = key.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+}
+
+type MaterialMap<V> = HashMap<u64, V, BuildHasherDefault<MaterialKeyHasher>>;
+
+/// endgame knowledge keyed by material, found with a single lookup of the material key
+pub struct Endgames {
+    evaluations: MaterialMap<(EndgameFn, Color)>,
+    /// scaling functions for each side being the strong one, indexed by Color
+    scales: MaterialMap<[Option<ScaleFn>; 2]>,
+}
+
+impl Endgames {
+    fn new() -> Self {
+        let mut endgames = Self {
+            evaluations: MaterialMap::default(),
+            scales: MaterialMap::default(),
+        };
+        endgames.add_evaluation("KK", draw);
+        endgames.add_evaluation("KNK", draw);
+        endgames.add_evaluation("KBK", draw);
+        endgames.add_evaluation("KNNK", draw);
+        endgames.add_evaluation("KQK", kxk);
+        endgames.add_evaluation("KRK", kxk);
+        endgames.add_evaluation("KBNK", kbnk);
+        endgames.add_evaluation("KPK", kpk);
+        endgames.add_scale("KBPK", kbpk);
+        endgames.add_scale("KRPKR", krpkr);
+        endgames
+    }
+
+    /// registers an evaluation for the signature with either color as the strong side
+    fn add_evaluation(&mut self, signature: &str, function: EndgameFn) {
+        for strong in [Color::White, Color::Black] {
+            self.evaluations
+                .insert(signature_key(signature, strong), (function, strong));
+        }
+    }
+
+    /// registers a scaling function for the signature with either color as the strong side
+    fn add_scale(&mut self, signature: &str, function: ScaleFn) {
+        for strong in [Color::White, Color::Black] {
+            self.scales
+                .entry(signature_key(signature, strong))
+                .or_default()[strong as usize] = Some(function);
+        }
+    }
+
+    /// returns the evaluation registered for the material and its strong side
+    pub fn evaluation(&self, material_key: u64) -> Option<(EndgameFn, Color)> {
+        self.evaluations.get(&material_key).copied()
+    }
+
+    /// returns the scaling function registered for the material with the given strong side
+    pub fn scale(&self, material_key: u64, strong: Color) -> Option<ScaleFn> {
+        self.scales
+            .get(&material_key)
+            .and_then(|scales| scales[strong as usize])
+    }
+}
+
+static ENDGAMES: LazyLock<Endgames> = LazyLock::new(Endgames::new);
+
+/// returns the registry of endgame knowledge
+pub fn endgames() -> &'static Endgames {
+    &ENDGAMES
+}
+
+/// returns the evaluation of a recognized endgame from white's point of view
+pub fn probe_evaluation(board: &Board) -> Option<i32> {
+    let (function, strong) = ENDGAMES.evaluation(board.state.material_key)?;
+    let score = function(board, strong);
+    Some(match strong {
+        Color::White => score,
+        Color::Black => -score,
+    })
+}
+
+/// returns the scale factor of the endgame part of the evaluation when the given side is ahead
+pub fn probe_scale(board: &Board, strong: Color) -> i32 {
+    ENDGAMES
+        .scale(board.state.material_key, strong)
+        .and_then(|function| function(board, strong))
+        .or_else(|| opposite_bishops(board, strong))
+        .unwrap_or(SCALE_NORMAL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KNOWN_WIN, SCALE_DRAW, SCALE_NORMAL, endgames, probe_evaluation, probe_scale};
+    use crate::eval::evaluate;
+    use crate::state::{self, Color, fen::fen_to_board};
+
+    #[test]
+    fn insufficient_material_is_a_draw() {
+        state::init();
+        for fen in [
+            "8/8/8/8/4K3/8/8/k7 w - - 0 1",
+            "8/8/8/8/4K3/8/8/kN6 b - - 0 1",
+            "8/8/3b4/8/4K3/8/8/k7 w - - 0 1",
+            "8/8/8/8/4K3/8/8/kNN5 w - - 0 1",
+        ] {
+            assert_eq!(evaluate(&fen_to_board(fen).unwrap()), 0, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn kpk_uses_the_bitbase() {
+        state::init();
+        // the defending king is stalemated in front of the pawn
+        let drawn = fen_to_board("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(evaluate(&drawn), 0);
+        // with the king on the sixth rank ahead of its pawn the attacker always wins
+        let won = fen_to_board("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1").unwrap();
+        assert!(evaluate(&won) < -super::KNOWN_WIN);
+    }
+
+    #[test]
+    fn krpkr_scales_the_philidor_defence() {
+        state::init();
+        // the rook holds the sixth rank with the king on the queening square
+        let drawn = fen_to_board("4k3/8/r7/4P3/4K3/8/8/7R w - - 0 1").unwrap();
+        let open = fen_to_board("4k3/8/8/3KP3/8/r7/8/7R w - - 0 1").unwrap();
+        assert_eq!(probe_scale(&drawn, Color::White), SCALE_DRAW);
+        assert_eq!(probe_scale(&open, Color::White), SCALE_NORMAL);
+        // the side without the pawn is never the one scaled
+        assert_eq!(probe_scale(&drawn, Color::Black), SCALE_NORMAL);
+        assert!(evaluate(&open) > evaluate(&drawn) && evaluate(&drawn) >= 0);
+
+        let flipped = drawn.flip_vertical();
+        assert_eq!(probe_scale(&flipped, Color::Black), SCALE_DRAW);
+        assert_eq!(probe_scale(&flipped, Color::White), SCALE_NORMAL);
+        assert_eq!(evaluate(&flipped), evaluate(&drawn));
+    }
+
+    #[test]
+    fn kbnk_drives_the_king_to_the_bishop_corner() {
+        state::init();
+        // the dark squared bishop mates in a1 or h8, not in h1
+        let right = fen_to_board("8/8/4N3/8/3B4/2K5/8/k7 w - - 0 1").unwrap();
+        let wrong = fen_to_board("8/8/3N4/8/3B4/5K2/8/7k w - - 0 1").unwrap();
+        assert!(evaluate(&right) > KNOWN_WIN);
+        assert!(evaluate(&wrong) > KNOWN_WIN);
+        assert!(evaluate(&right) > evaluate(&wrong));
+
+        let flipped = right.flip_vertical();
+        assert!(probe_evaluation(&flipped).unwrap() < -KNOWN_WIN);
+        assert_eq!(evaluate(&flipped), evaluate(&right));
+    }
+
+    #[test]
+    fn opposite_bishops_only_scale_bishop_endings() {
+        state::init();
+        let scale = |fen: &str| probe_scale(&fen_to_board(fen).unwrap(), Color::White);
+        let pure = scale("8/4kb2/8/3p4/3P4/2B1K3/1P6/8 w - - 0 1");
+        let rooks = scale("r7/4kb2/8/3p4/3P4/2B1K3/1P6/7R w - - 0 1");
+        assert!(pure < rooks && rooks < SCALE_NORMAL, "{} {}", pure, rooks);
+        for fen in [
+            // queens, unequal extra pieces and two extra pieces each play on
+            "q7/4kb2/8/3p4/3P4/2B1K3/1P6/7Q w - - 0 1",
+            "n7/4kb2/8/3p4/3P4/2B1K3/1P6/7R w - - 0 1",
+            "rn6/4kb2/8/3p4/3P4/2B1K3/1P6/6NR w - - 0 1",
+        ] {
+            assert_eq!(scale(fen), SCALE_NORMAL, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn dispatch_finds_the_strong_side_of_either_color() {
+        state::init();
+        for (fen, strong) in [
+            ("8/8/8/4k3/8/8/8/KBN5 w - - 0 1", Color::White),
+            ("kbn5/8/8/8/4K3/8/8/8 w - - 0 1", Color::Black),
+            ("8/8/8/4k3/8/8/4P3/4K3 b - - 0 1", Color::White),
+            ("8/8/8/4k3/8/8/8/K6r w - - 0 1", Color::Black),
+        ] {
+            let board = fen_to_board(fen).unwrap();
+            let (_, found) = endgames()
+                .evaluation(board.state.material_key)
+                .expect("endgame not recognized");
+            assert_eq!(found, strong, "{}", fen);
+
+            // swapping the colors swaps the strong side and the sign of the score
+            let flipped = board.flip_vertical();
+            let (_, swapped) = endgames()
+                .evaluation(flipped.state.material_key)
+                .expect("flipped endgame not recognized");
+            assert_eq!(swapped, strong.opposite(), "{}", fen);
+            let score = probe_evaluation(&board).unwrap();
+            assert_eq!(probe_evaluation(&flipped), Some(-score), "{}", fen);
+            if score != 0 {
+                assert_eq!(score > 0, strong == Color::White, "{}", fen);
+            }
+        }
+    }
+}
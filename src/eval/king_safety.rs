@@ -0,0 +1,201 @@
+use crate::state::piece_move_gen::get_king_moves_bb;
+use crate::state::{Color, ColoredPiece, GenericPiece, board::Board};
+use crate::util::bithelpers::BitFunctions;
+
+use super::params::{EvalParams, OFFSETS};
+use super::pawns::{adjacent_files, file_fill, forward};
+use super::psqt::Score;
+use super::trace::{Trace, term};
+
+/// returns the squares around the king plus the rank in front of those, seen from color
+pub fn king_zone(square: u32, color: Color) -> u64 {
+    let around = get_king_moves_bb(square, 0) | (1u64 << square);
+    around | forward(around, color)
+}
+
+/// attacks gathered on one king zone
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KingAttack {
+    pub attackers: u32,
+    pub units: i32,
+}
+
+impl KingAttack {
+    /// adds a piece whose attacks are given to the attack on the zone
+    pub fn add(&mut self, params: &EvalParams, piece: GenericPiece, attacks: u64, zone: u64) {
+        let hits = attacks & zone;
+        if hits != 0 {
+            self.attackers += 1;
+            self.units += params.king_attack_weights[piece as usize] * hits.count_set_bits() as i32;
+        }
+    }
+
+    /// returns the penalty for the attacked side, growing quadratically with the attack units
+    pub fn danger(&self, params: &EvalParams) -> Score {
+        if (self.attackers as i32) < params.king_min_attackers {
+            return Score::ZERO;
+        }
+        let mg = (self.units * self.units / 4).min(params.king_max_danger);
+        Score::new(-mg, -self.units)
+    }
+}
+
+/// returns the pawn shield, pawn storm and open file terms around the given color's king
+pub fn king_shelter<T: Trace>(board: &Board, color: Color, trace: &mut T) -> Score {
+    let params = &board.psqt.params;
+    let Some(king) = board.king_square(color) else {
+        return Score::ZERO;
+    };
+    let own = board.pieces[ColoredPiece::from_parts(color, GenericPiece::Pawn) as usize];
+    let enemy =
+        board.pieces[ColoredPiece::from_parts(color.opposite(), GenericPiece::Pawn) as usize];
+
+    let king_bit = 1u64 << king;
+    let king_files = king_bit | adjacent_files(king_bit);
+    let files = file_fill(king_files);
+    let near = files & rank_ahead(king, color, 1);
+    let far = files & rank_ahead(king, color, 2);
+    let storm = files & (rank_ahead(king, color, 2) | rank_ahead(king, color, 3));
+
+    let mut score = term(
+        trace,
+        color,
+        OFFSETS.shield_near,
+        params.shield_near,
+        (own & near).count_set_bits() as i32,
+    ) + term(
+        trace,
+        color,
+        OFFSETS.shield_far,
+        params.shield_far,
+        (own & far).count_set_bits() as i32,
+    ) + term(
+        trace,
+        color,
+        OFFSETS.storm,
+        params.storm,
+        (enemy & storm).count_set_bits() as i32,
+    );
+
+    king_files.bit_for_each(|square| {
+        let file = file_fill(1u64 << square);
+        if own & file == 0 {
+            score += term(
+                trace,
+                color,
+                OFFSETS.king_semi_open_file,
+                params.king_semi_open_file,
+                1,
+            );
+            if enemy & file == 0 {
+                score += term(
+                    trace,
+                    color,
+                    OFFSETS.king_open_file,
+                    params.king_open_file,
+                    1,
+                );
+            }
+        }
+    });
+
+    score
+}
+
+/// returns the full rank the given number of ranks in front of the square, or nothing if that
+/// is off the board
+fn rank_ahead(square: u32, color: Color, distance: u32) -> u64 {
+    let rank = match color {
+        Color::White => square / 8 + distance,
+        Color::Black => (square / 8).wrapping_sub(distance),
+    };
+    if rank < 8 { 0xFF << (rank * 8) } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KingAttack, king_shelter, king_zone};
+    use crate::eval::{eval_trace, params::EvalParams, psqt::Score, trace::NoTrace};
+    use crate::state::{Color, GenericPiece, fen::fen_to_board};
+
+    fn shelter(fen: &str, color: Color) -> i32 {
+        let board = fen_to_board(fen).unwrap();
+        king_shelter(&board, color, &mut NoTrace).mg
+    }
+
+    #[test]
+    fn broken_shelter_scores_worse() {
+        crate::state::init();
+        let intact = shelter("6k1/8/8/8/8/8/5PPP/6K1 w - - 0 1", Color::White);
+        let advanced = shelter("6k1/8/8/8/8/6P1/5P1P/6K1 w - - 0 1", Color::White);
+        let semi_open = shelter("6k1/6p1/8/8/8/8/5P1P/6K1 w - - 0 1", Color::White);
+        let open = shelter("6k1/8/8/8/8/8/5P1P/6K1 w - - 0 1", Color::White);
+        assert!(intact > 0);
+        assert!(intact > advanced, "{} {}", intact, advanced);
+        assert!(advanced > semi_open, "{} {}", advanced, semi_open);
+        assert!(semi_open > open, "{} {}", semi_open, open);
+    }
+
+    #[test]
+    fn storming_pawns_score_worse() {
+        crate::state::init();
+        let quiet = shelter("6k1/6pp/8/8/8/8/5PPP/6K1 w - - 0 1", Color::White);
+        let storm = shelter("6k1/8/8/8/6pp/8/5PPP/6K1 w - - 0 1", Color::White);
+        assert!(storm < quiet, "{} {}", storm, quiet);
+    }
+
+    #[test]
+    fn shelter_is_symmetric() {
+        crate::state::init();
+        for fen in [
+            "6k1/8/8/8/8/6P1/5P1P/6K1 w - - 0 1",
+            "6k1/8/8/8/6pp/8/5PPP/6K1 w - - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w - - 0 1",
+        ] {
+            let board = fen_to_board(fen).unwrap();
+            let flipped = board.flip_vertical();
+            assert_eq!(
+                king_shelter(&board, Color::White, &mut NoTrace),
+                king_shelter(&flipped, Color::Black, &mut NoTrace),
+                "{}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn danger_needs_enough_attackers_and_is_capped() {
+        let params = EvalParams::default();
+        let zone = king_zone(6, Color::White);
+
+        let mut attack = KingAttack::default();
+        attack.add(&params, GenericPiece::Queen, zone, zone);
+        assert_eq!(attack.danger(&params), Score::ZERO);
+
+        attack.add(&params, GenericPiece::Knight, 1u64 << 14, zone);
+        let danger = attack.danger(&params);
+        assert!(danger.mg < 0 && danger.eg < 0, "{:?}", danger);
+        assert_eq!(danger.mg, -params.king_max_danger);
+
+        let mut small = KingAttack::default();
+        small.add(&params, GenericPiece::Knight, 1u64 << 14, zone);
+        small.add(&params, GenericPiece::Bishop, 1u64 << 13, zone);
+        let small_danger = small.danger(&params);
+        assert!(danger.mg < small_danger.mg && small_danger.mg < 0);
+    }
+
+    #[test]
+    fn danger_falls_on_the_attacked_king() {
+        crate::state::init();
+        let board = fen_to_board("6k1/5ppp/8/8/5n1q/8/5PPP/6K1 w - - 0 1").unwrap();
+        let trace = eval_trace(&board);
+        let danger = trace
+            .terms
+            .iter()
+            .find(|term| term.name == "king_danger")
+            .unwrap();
+        let [white, black] = danger.scores;
+        assert!(white.mg < 0, "{:?}", white);
+        assert_eq!(black, Score::ZERO);
+    }
+}
@@ -0,0 +1,178 @@
+use std::sync::LazyLock;
+
+use crate::state::Color;
+use crate::state::piece_move_gen::get_king_moves_bb;
+use crate::util::bithelpers::BitFunctions;
+
+use super::pawns::pawn_attacks;
+
+/// positions are indexed by white king, black king, side to move, and a white pawn on files a
+/// to d and ranks 2 to 7
+const POSITIONS: usize = 64 * 64 * 2 * 4 * 6;
+
+const INVALID: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+/// one bit per position, set if white wins
+static BITBASE: LazyLock<Vec<u64>> = LazyLock::new(generate);
+
+fn index(side_to_move: Color, black_king: u32, white_king: u32, pawn: u32) -> usize {
+    white_king as usize
+        | (black_king as usize) << 6
+        | (side_to_move as usize) << 12
+        | ((pawn % 8) as usize) << 13
+        | ((6 - pawn / 8) as usize) << 15
+}
+
+fn king_attacks(square: u32) -> u64 {
+    get_king_moves_bb(square, 0)
+}
+
+/// returns the state of the position before any retrograde step
+fn initial(side_to_move: Color, black_king: u32, white_king: u32, pawn: u32) -> u8 {
+    let pawn_bb = 1u64 << pawn;
+    let black_king_bb = 1u64 << black_king;
+    if king_attacks(white_king).contains(black_king_bb)
+        || white_king == black_king
+        || white_king == pawn
+        || black_king == pawn
+        || (side_to_move == Color::White
+            && pawn_attacks(pawn_bb, Color::White).contains(black_king_bb))
+    {
+        return INVALID;
+    }
+
+    if side_to_move == Color::White && pawn / 8 == 6 {
+        // the pawn promotes safely if the black king cannot take the new queen
+        let queen = pawn + 8;
+        if white_king != queen
+            && black_king != queen
+            && (!king_attacks(black_king).contains(1u64 << queen)
+                || king_attacks(white_king).contains(1u64 << queen))
+        {
+            return WIN;
+        }
+    }
+
+    if side_to_move == Color::Black {
+        let escapes = king_attacks(black_king)
+            & !(king_attacks(white_king) | pawn_attacks(pawn_bb, Color::White));
+        if escapes == 0 {
+            // stalemate, checkmate is impossible with a king and a pawn
+            return DRAW;
+        }
+        if king_attacks(black_king) & !king_attacks(white_king) & pawn_bb != 0 {
+            // the pawn can be taken
+            return DRAW;
+        }
+    }
+
+    UNKNOWN
+}
+
+/// returns the state of the position given the states of the positions it leads to
+fn classify(db: &[u8], side_to_move: Color, black_king: u32, white_king: u32, pawn: u32) -> u8 {
+    let mut reached = 0;
+    match side_to_move {
+        Color::White => {
+            king_attacks(white_king).bit_for_each(|square| {
+                reached |= db[index(Color::Black, black_king, square, pawn)];
+            });
+            if pawn / 8 < 6 {
+                reached |= db[index(Color::Black, black_king, white_king, pawn + 8)];
+            }
+            if pawn / 8 == 1 && pawn + 8 != white_king && pawn + 8 != black_king {
+                reached |= db[index(Color::Black, black_king, white_king, pawn + 16)];
+            }
+        }
+        Color::Black => {
+            king_attacks(black_king).bit_for_each(|square| {
+                reached |= db[index(Color::White, square, white_king, pawn)];
+            });
+        }
+    }
+
+    // white needs one winning move, black one drawing move
+    let (good, bad) = match side_to_move {
+        Color::White => (WIN, DRAW),
+        Color::Black => (DRAW, WIN),
+    };
+    if reached & good != 0 {
+        good
+    } else if reached & UNKNOWN != 0 {
+        UNKNOWN
+    } else {
+        bad
+    }
+}
+
+/// decodes an index into side to move, black king, white king and pawn square
+fn decode(index: usize) -> (Color, u32, u32, u32) {
+    let side_to_move = if (index >> 12) & 1 == 0 {
+        Color::White
+    } else {
+        Color::Black
+    };
+    let pawn = (6 - ((index >> 15) & 7)) * 8 + ((index >> 13) & 3);
+    (
+        side_to_move,
+        ((index >> 6) & 63) as u32,
+        (index & 63) as u32,
+        pawn as u32,
+    )
+}
+
+/// builds the bitbase by retrograde analysis, repeating until no position changes
+fn generate() -> Vec<u64> {
+    let mut db: Vec<u8> = (0..POSITIONS)
+        .map(|index| {
+            let (side_to_move, black_king, white_king, pawn) = decode(index);
+            initial(side_to_move, black_king, white_king, pawn)
+        })
+        .collect();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in 0..POSITIONS {
+            if db[index] != UNKNOWN {
+                continue;
+            }
+            let (side_to_move, black_king, white_king, pawn) = decode(index);
+            let state = classify(&db, side_to_move, black_king, white_king, pawn);
+            if state != UNKNOWN {
+                db[index] = state;
+                changed = true;
+            }
+        }
+    }
+
+    let mut bits = vec![0u64; POSITIONS.div_ceil(64)];
+    for (index, &state) in db.iter().enumerate() {
+        if state == WIN {
+            bits[index / 64] |= 1u64 << (index % 64);
+        }
+    }
+    bits
+}
+
+/// builds the bitbase now rather than during the first probe
+pub fn init() {
+    LazyLock::force(&BITBASE);
+}
+
+/// returns true if the side with the pawn wins, squares being given from that side's point of
+/// view as if it were white
+pub fn probe(side_to_move: Color, strong_king: u32, pawn: u32, weak_king: u32) -> bool {
+    debug_assert!((8..56).contains(&pawn));
+    // the bitbase only holds pawns on the queen side, the king side mirrors it
+    let (strong_king, pawn, weak_king) = if pawn % 8 >= 4 {
+        (strong_king ^ 7, pawn ^ 7, weak_king ^ 7)
+    } else {
+        (strong_king, pawn, weak_king)
+    };
+    let index = index(side_to_move, weak_king, strong_king, pawn);
+    BITBASE[index / 64] & (1u64 << (index % 64)) != 0
+}
@@ -0,0 +1,63 @@
+use crate::state::piece_move_gen::{get_bishop_moves_bb, get_knight_moves_bb, get_rook_moves_bb};
+use crate::state::{Color, ColoredPiece, GenericPiece, board::Board};
+use crate::util::bithelpers::BitFunctions;
+
+use super::king_safety::{KingAttack, king_zone};
+use super::params::{OFFSETS, score_index};
+use super::pawns::pawn_attacks;
+use super::psqt::Score;
+use super::trace::{Trace, term};
+
+/// reachable squares of an average placed piece, which scores zero, indexed by GenericPiece
+pub const MOBILITY_BASELINE: [i32; 6] = [0, 4, 7, 7, 14, 0];
+
+/// returns the mobility score of the given color's pieces and their attack on the enemy king
+pub fn evaluate_mobility<T: Trace>(
+    board: &Board,
+    color: Color,
+    trace: &mut T,
+) -> (Score, KingAttack) {
+    let params = &board.psqt.params;
+    let own_occ = board.occupied[color as usize];
+    let enemy_occ = board.occupied[color.opposite() as usize];
+    let enemy_pawns =
+        board.pieces[ColoredPiece::from_parts(color.opposite(), GenericPiece::Pawn) as usize];
+    // squares covered by enemy pawns are not worth counting
+    let area = !pawn_attacks(enemy_pawns, color.opposite());
+    let zone = board
+        .king_square(color.opposite())
+        .map_or(0, |king| king_zone(king, color.opposite()));
+
+    let mut score = Score::ZERO;
+    let mut attack = KingAttack::default();
+    for generic in [
+        GenericPiece::Knight,
+        GenericPiece::Bishop,
+        GenericPiece::Rook,
+        GenericPiece::Queen,
+    ] {
+        let pieces = board.pieces[ColoredPiece::from_parts(color, generic) as usize];
+        pieces.bit_for_each(|square| {
+            let attacks = match generic {
+                GenericPiece::Knight => get_knight_moves_bb(square, own_occ),
+                GenericPiece::Bishop => get_bishop_moves_bb(square, own_occ, enemy_occ),
+                GenericPiece::Rook => get_rook_moves_bb(square, own_occ, enemy_occ),
+                _ => {
+                    get_bishop_moves_bb(square, own_occ, enemy_occ)
+                        | get_rook_moves_bb(square, own_occ, enemy_occ)
+                }
+            };
+            let count = (attacks & area).count_set_bits() as i32;
+            score += term(
+                trace,
+                color,
+                score_index(OFFSETS.mobility, generic as usize),
+                params.mobility[generic as usize],
+                count - MOBILITY_BASELINE[generic as usize],
+            );
+            attack.add(params, generic, attacks, zone);
+        });
+    }
+
+    (score, attack)
+}
@@ -0,0 +1,516 @@
+use std::sync::Arc;
+
+use strum::IntoEnumIterator;
+
+use crate::state::board::Board;
+use crate::state::board_move_gen::{Move, move_flags};
+use crate::state::{Color, ColoredPiece, GenericPiece};
+use crate::util::bithelpers::BitFunctions;
+
+/// bytes every network file starts with
+const MAGIC: &[u8; 4] = b"MNUE";
+const VERSION: u32 = 1;
+/// one feature per king square, piece relative to the perspective and square
+pub const INPUTS: usize = 64 * 12 * 64;
+/// quantization of the feature transformer and of the output layer
+const QA: i32 = 255;
+const QB: i32 = 64;
+/// converts the network output into centipawns
+const SCALE: i32 = 400;
+/// lanes processed together, small enough for the compiler to map onto vector registers
+const LANES: usize = 16;
+
+/// a HalfKA style network with one hidden layer: int16 feature transformer weights, a clipped
+/// ReLU and int8 output weights for the side to move and the other side
+pub struct Network {
+    hidden: usize,
+    feature_weights: Vec<i16>,
+    feature_bias: Vec<i16>,
+    output_weights: Vec<i8>,
+    output_bias: i32,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < count {
+            return Err("Network file is truncated".to_string());
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i16s(&mut self, count: usize) -> Result<Vec<i16>, String> {
+        Ok(self
+            .take(count * 2)?
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect())
+    }
+
+    fn i8s(&mut self, count: usize) -> Result<Vec<i8>, String> {
+        Ok(self.take(count)?.iter().map(|&b| b as i8).collect())
+    }
+}
+
+impl Network {
+    /// parses a network: magic, version, hidden size, feature weights, feature biases,
+    /// output weights and output bias, all little endian
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes };
+        if reader.take(4)? != MAGIC {
+            return Err("Not a network file".to_string());
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(format!("Unsupported network version {}", version));
+        }
+        let hidden = reader.u32()? as usize;
+        if hidden == 0 || !hidden.is_multiple_of(LANES) {
+            return Err(format!(
+                "Hidden layer size must be a positive multiple of {}",
+                LANES
+            ));
+        }
+
+        let network = Self {
+            hidden,
+            feature_weights: reader.i16s(INPUTS * hidden)?,
+            feature_bias: reader.i16s(hidden)?,
+            output_weights: reader.i8s(2 * hidden)?,
+            output_bias: reader.i32()?,
+        };
+        if !reader.bytes.is_empty() {
+            return Err("Network file has trailing data".to_string());
+        }
+        Ok(network)
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn hidden(&self) -> usize {
+        self.hidden
+    }
+
+    fn weights(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * self.hidden..(feature + 1) * self.hidden]
+    }
+
+    /// returns the score in centipawns from the side to move's view
+    fn forward(&self, accumulator: &Accumulator, side: Color) -> i32 {
+        let us = &accumulator.values[side as usize];
+        let them = &accumulator.values[side.opposite() as usize];
+        let (our_weights, their_weights) = self.output_weights.split_at(self.hidden);
+        let sum = dot_clipped(us, our_weights) + dot_clipped(them, their_weights);
+        // a large output bias plus a large sum overflows i32 once scaled
+        ((sum as i64 + self.output_bias as i64) * SCALE as i64 / (QA * QB) as i64) as i32
+    }
+}
+
+/// sum of clipped ReLU activations times weights
+fn dot_clipped(values: &[i16], weights: &[i8]) -> i32 {
+    let mut lanes = [0i32; LANES];
+    for (values, weights) in values.chunks_exact(LANES).zip(weights.chunks_exact(LANES)) {
+        for i in 0..LANES {
+            lanes[i] += (values[i] as i32).clamp(0, QA) * weights[i] as i32;
+        }
+    }
+    lanes.iter().sum()
+}
+
+fn add_weights(values: &mut [i16], weights: &[i16]) {
+    for (value, weight) in values.iter_mut().zip(weights) {
+        *value = value.wrapping_add(*weight);
+    }
+}
+
+fn sub_weights(values: &mut [i16], weights: &[i16]) {
+    for (value, weight) in values.iter_mut().zip(weights) {
+        *value = value.wrapping_sub(*weight);
+    }
+}
+
+/// returns the input of the piece on the square as seen by the perspective with its king on
+/// king, black seeing the board flipped so both perspectives share the weights
+fn feature(perspective: Color, king: u32, piece: ColoredPiece, square: u32) -> usize {
+    let flip = match perspective {
+        Color::White => 0,
+        Color::Black => 56,
+    };
+    let relative_piece = piece.generic() as usize * 2 + (piece.color() != perspective) as usize;
+    (king ^ flip) as usize * 768 + relative_piece * 64 + (square ^ flip) as usize
+}
+
+/// hidden layer inputs of both perspectives, indexed by Color
+#[derive(Clone)]
+struct Accumulator {
+    values: [Vec<i16>; 2],
+}
+
+impl Accumulator {
+    fn new(hidden: usize) -> Self {
+        Self {
+            values: [vec![0; hidden], vec![0; hidden]],
+        }
+    }
+
+    /// recomputes one perspective from the pieces on the board
+    fn refresh(&mut self, network: &Network, board: &Board, perspective: Color) {
+        let values = &mut self.values[perspective as usize];
+        values.copy_from_slice(&network.feature_bias);
+        let Some(king) = board.king_square(perspective) else {
+            return;
+        };
+        for piece in ColoredPiece::iter() {
+            board.pieces[piece as usize].bit_for_each(|square| {
+                add_weights(
+                    values,
+                    network.weights(feature(perspective, king, piece, square)),
+                );
+            });
+        }
+    }
+}
+
+/// a piece appearing on or disappearing from a square
+#[derive(Clone, Copy)]
+struct Change {
+    piece: ColoredPiece,
+    square: u32,
+    added: bool,
+}
+
+/// returns the pieces changed by the move, given the board after it was made
+fn move_changes(board: &Board, m: Move) -> Vec<Change> {
+    let side = board.side_to_move.opposite();
+    let from = m.extract_from() as u32;
+    let to = m.destination();
+    let flags = m.extract_flags() as u8;
+
+    let placed = board
+        .colored_piece_at(to)
+        .expect("moved piece missing from its target square");
+    let moved = if m.is_promotion() {
+        ColoredPiece::from_parts(side, GenericPiece::Pawn)
+    } else {
+        placed
+    };
+
+    let mut changes = vec![
+        Change {
+            piece: moved,
+            square: from,
+            added: false,
+        },
+        Change {
+            piece: placed,
+            square: to,
+            added: true,
+        },
+    ];
+    if let Some(captured) = board.state.captured_piece {
+        let square = match (flags, side) {
+            (move_flags::EN_PASSANT, Color::White) => to - 8,
+            (move_flags::EN_PASSANT, Color::Black) => to + 8,
+            _ => to,
+        };
+        changes.push(Change {
+            piece: ColoredPiece::from_parts(side.opposite(), captured),
+            square,
+            added: false,
+        });
+    }
+    if let Some((rook_from, rook_to)) = m.castling_rook_squares() {
+        let rook = ColoredPiece::from_parts(side, GenericPiece::Rook);
+        changes.push(Change {
+            piece: rook,
+            square: rook_from,
+            added: false,
+        });
+        changes.push(Change {
+            piece: rook,
+            square: rook_to,
+            added: true,
+        });
+    }
+    changes
+}
+
+/// accumulators of the line being searched, one per ply
+pub struct NnueState {
+    network: Arc<Network>,
+    stack: Vec<Accumulator>,
+    ply: usize,
+}
+
+impl NnueState {
+    pub fn new(network: Arc<Network>) -> Self {
+        let hidden = network.hidden;
+        Self {
+            network,
+            stack: vec![Accumulator::new(hidden)],
+            ply: 0,
+        }
+    }
+
+    /// recomputes the accumulators of the given root position
+    pub fn reset(&mut self, board: &Board) {
+        self.ply = 0;
+        for perspective in [Color::White, Color::Black] {
+            self.stack[0].refresh(&self.network, board, perspective);
+        }
+    }
+
+    /// pushes the accumulators of the position after the move, the board having made it
+    pub fn make_move(&mut self, board: &Board, m: Move) {
+        if self.ply + 1 == self.stack.len() {
+            self.stack.push(Accumulator::new(self.network.hidden));
+        }
+        let (done, rest) = self.stack.split_at_mut(self.ply + 1);
+        let previous = &done[self.ply];
+        let next = &mut rest[0];
+        self.ply += 1;
+
+        let mover = board.side_to_move.opposite();
+        let king_moved = board.colored_piece_at(m.destination())
+            == Some(ColoredPiece::from_parts(mover, GenericPiece::King));
+        let changes = move_changes(board, m);
+
+        for perspective in [Color::White, Color::Black] {
+            // every feature depends on the own king, so its moves need a full refresh
+            if king_moved && perspective == mover {
+                next.refresh(&self.network, board, perspective);
+                continue;
+            }
+            let Some(king) = board.king_square(perspective) else {
+                continue;
+            };
+            let values = &mut next.values[perspective as usize];
+            values.copy_from_slice(&previous.values[perspective as usize]);
+            for change in &changes {
+                let weights =
+                    self.network
+                        .weights(feature(perspective, king, change.piece, change.square));
+                if change.added {
+                    add_weights(values, weights);
+                } else {
+                    sub_weights(values, weights);
+                }
+            }
+        }
+    }
+
+    /// pushes an unchanged copy of the accumulators
+    pub fn make_null_move(&mut self) {
+        if self.ply + 1 == self.stack.len() {
+            self.stack.push(Accumulator::new(self.network.hidden));
+        }
+        let (done, rest) = self.stack.split_at_mut(self.ply + 1);
+        rest[0].clone_from(&done[self.ply]);
+        self.ply += 1;
+    }
+
+    /// returns to the accumulators before the last move
+    pub fn unmake_move(&mut self) {
+        self.ply -= 1;
+    }
+
+    /// returns the evaluation from the side to move's view
+    pub fn evaluate(&self, board: &Board) -> i32 {
+        self.network
+            .forward(&self.stack[self.ply], board.side_to_move)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::state::fen::fen_to_board;
+    use crate::util::const_rand::XorShift64;
+
+    const HIDDEN: usize = 32;
+
+    /// returns the bytes of a small network with pseudo random weights
+    fn network_bytes(output_bias: i32) -> Vec<u8> {
+        let mut rng = XorShift64::new(0x5851_f42d_4c95_7f2d);
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend((HIDDEN as u32).to_le_bytes());
+        for _ in 0..(INPUTS + 1) * HIDDEN {
+            let weight = (rng.next() % 129) as i16 - 64;
+            bytes.extend(weight.to_le_bytes());
+        }
+        for _ in 0..2 * HIDDEN {
+            bytes.push((rng.next() % 255) as u8);
+        }
+        bytes.extend(output_bias.to_le_bytes());
+        bytes
+    }
+
+    fn network() -> Arc<Network> {
+        Arc::new(Network::from_bytes(&network_bytes(1000)).unwrap())
+    }
+
+    fn find_move(board: &mut Board, text: &str) -> Move {
+        board
+            .legal_moves()
+            .into_iter()
+            .find(|m| m.to_uci() == text)
+            .unwrap_or_else(|| panic!("illegal move {} in test", text))
+    }
+
+    /// asserts that the incrementally updated accumulators equal freshly computed ones
+    fn assert_fresh(state: &NnueState, board: &Board, context: &str) {
+        let mut fresh = NnueState::new(Arc::clone(&state.network));
+        fresh.reset(board);
+        for perspective in [Color::White, Color::Black] {
+            assert!(
+                state.stack[state.ply].values[perspective as usize]
+                    == fresh.stack[0].values[perspective as usize],
+                "{:?} accumulator drifts {}",
+                perspective,
+                context
+            );
+        }
+        assert_eq!(state.evaluate(board), fresh.evaluate(board), "{}", context);
+    }
+
+    /// plays the moves one after another, then takes them all back, checking every step
+    fn check_line(network: &Arc<Network>, fen: &str, line: &[&str]) {
+        let mut board = fen_to_board(fen).unwrap();
+        let mut state = NnueState::new(Arc::clone(network));
+        state.reset(&board);
+        let mut played = Vec::new();
+        for text in line {
+            let m = find_move(&mut board, text);
+            board.make_move(m).unwrap();
+            state.make_move(&board, m);
+            played.push(m);
+            assert_fresh(&state, &board, &format!("after {} from {}", text, fen));
+        }
+        while let Some(m) = played.pop() {
+            board.unmake_move(m);
+            state.unmake_move();
+            assert_fresh(
+                &state,
+                &board,
+                &format!("after undoing {} from {}", m.to_uci(), fen),
+            );
+        }
+    }
+
+    #[test]
+    fn incremental_updates_match_a_refresh() {
+        crate::state::init();
+        let network = network();
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        // quiet moves, captures and both castlings
+        check_line(
+            &network,
+            kiwipete,
+            &["a2a3", "b4c3", "e1g1", "e8c8", "e5f7", "h3g2"],
+        );
+        // king moves refresh the mover's perspective only
+        check_line(&network, kiwipete, &["e1f1", "e8d8", "f1g1", "e7d6"]);
+        // en passant by either side
+        check_line(
+            &network,
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3",
+            &["d4e3", "a2a4", "a7a6", "a4a5", "b7b5", "a5b6"],
+        );
+        // promotions with and without a capture
+        check_line(
+            &network,
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            &["d7c8q", "d8c8", "e1f2"],
+        );
+        check_line(
+            &network,
+            "8/P6k/8/8/8/8/1p6/7K w - - 0 1",
+            &["a7a8n", "b2b1r"],
+        );
+    }
+
+    #[test]
+    fn random_lines_match_a_refresh() {
+        crate::state::init();
+        let network = network();
+        let mut rng = XorShift64::new(0x1405_7b7e_f767_814f);
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            for _ in 0..10 {
+                let mut board = fen_to_board(fen).unwrap();
+                let mut state = NnueState::new(Arc::clone(&network));
+                state.reset(&board);
+                let mut played = Vec::new();
+                for _ in 0..30 {
+                    let moves = board.legal_moves();
+                    if moves.is_empty() {
+                        break;
+                    }
+                    let m = moves[(rng.next() % moves.len() as u64) as usize];
+                    board.make_move(m).unwrap();
+                    state.make_move(&board, m);
+                    played.push(m);
+                    assert_fresh(
+                        &state,
+                        &board,
+                        &format!("after {} from {}", m.to_uci(), fen),
+                    );
+                }
+                while let Some(m) = played.pop() {
+                    board.unmake_move(m);
+                    state.unmake_move();
+                    assert_fresh(&state, &board, &format!("after undoing {}", m.to_uci()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn large_output_bias_does_not_overflow() {
+        crate::state::init();
+        let network = Arc::new(Network::from_bytes(&network_bytes(i32::MAX / 2)).unwrap());
+        let board = Board::default_setup();
+        let mut state = NnueState::new(Arc::clone(&network));
+        state.reset(&board);
+        let accumulator = &state.stack[0];
+        let (ours, theirs) = network.output_weights.split_at(HIDDEN);
+        let sum = dot_clipped(&accumulator.values[0], ours) as i64
+            + dot_clipped(&accumulator.values[1], theirs) as i64;
+        let expected = (sum + (i32::MAX / 2) as i64) * SCALE as i64 / (QA * QB) as i64;
+        assert_eq!(state.evaluate(&board) as i64, expected);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let bytes = network_bytes(0);
+        assert!(Network::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(Network::from_bytes(&trailing).is_err());
+        let mut magic = bytes;
+        magic[0] = b'X';
+        assert!(Network::from_bytes(&magic).is_err());
+    }
+}
@@ -0,0 +1,563 @@
+use std::sync::{Arc, LazyLock, RwLock};
+
+use super::psqt::Score;
+
+/// a value or group of values of the evaluation, stored as a flat list of integers
+pub trait Param: Sized {
+    /// number of integers in the flat form
+    const LEN: usize;
+
+    fn flatten(&self, out: &mut Vec<i32>);
+    /// reads the value back from exactly LEN integers
+    fn unflatten(values: &[i32]) -> Self;
+    /// writes the value as a TOML value, continuation lines indented by indent spaces
+    fn write_toml(&self, out: &mut String, indent: usize);
+}
+
+impl Param for i32 {
+    const LEN: usize = 1;
+
+    fn flatten(&self, out: &mut Vec<i32>) {
+        out.push(*self);
+    }
+
+    fn unflatten(values: &[i32]) -> Self {
+        values[0]
+    }
+
+    fn write_toml(&self, out: &mut String, _indent: usize) {
+        out.push_str(&self.to_string());
+    }
+}
+
+impl Param for Score {
+    const LEN: usize = 2;
+
+    fn flatten(&self, out: &mut Vec<i32>) {
+        out.push(self.mg);
+        out.push(self.eg);
+    }
+
+    fn unflatten(values: &[i32]) -> Self {
+        Score::new(values[0], values[1])
+    }
+
+    fn write_toml(&self, out: &mut String, _indent: usize) {
+        out.push_str(&format!("[{}, {}]", self.mg, self.eg));
+    }
+}
+
+impl<T: Param, const N: usize> Param for [T; N] {
+    const LEN: usize = N * T::LEN;
+
+    fn flatten(&self, out: &mut Vec<i32>) {
+        for value in self {
+            value.flatten(out);
+        }
+    }
+
+    fn unflatten(values: &[i32]) -> Self {
+        std::array::from_fn(|i| T::unflatten(&values[i * T::LEN..(i + 1) * T::LEN]))
+    }
+
+    fn write_toml(&self, out: &mut String, indent: usize) {
+        // nested arrays get one element per line and long ones eight, like the rank of a board
+        let per_line = if T::LEN > 2 { 1 } else { 8 };
+        if N <= per_line && T::LEN <= 2 {
+            out.push('[');
+            for (i, value) in self.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                value.write_toml(out, indent);
+            }
+            out.push(']');
+            return;
+        }
+
+        out.push('[');
+        for (i, value) in self.iter().enumerate() {
+            if i % per_line == 0 {
+                out.push('\n');
+                out.push_str(&" ".repeat(indent + 4));
+            } else {
+                out.push(' ');
+            }
+            value.write_toml(out, indent + 4);
+            out.push(',');
+        }
+        out.push('\n');
+        out.push_str(&" ".repeat(indent));
+        out.push(']');
+    }
+}
+
+/// returns the flat index of an element of a score array starting at base, which is where
+/// its middlegame value is, followed by the endgame value
+pub const fn score_index(base: usize, element: usize) -> usize {
+    base + element * <Score as Param>::LEN
+}
+
+/// declares the evaluation parameters once, generating the struct, its compiled-in defaults
+/// and its conversions from the same list
+macro_rules! eval_params {
+    ($($(#[doc = $doc:literal])* $name:ident: $ty:ty = $default:expr,)*) => {
+        /// every weight of the hand written evaluation
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct EvalParams {
+            $($(#[doc = $doc])* pub $name: $ty,)*
+        }
+
+        /// position of every parameter in the flat form
+        #[derive(Debug, Clone, Copy)]
+        pub struct ParamOffsets {
+            $(pub $name: usize,)*
+        }
+
+        /// offsets of the parameters, in the order they are declared
+        // the offset after the last parameter is not needed
+        #[allow(unused_variables)]
+        pub const OFFSETS: ParamOffsets = {
+            let offset = 0;
+            $(
+                let $name = offset;
+                let offset = offset + <$ty as Param>::LEN;
+            )*
+            ParamOffsets { $($name,)* }
+        };
+
+        impl Default for EvalParams {
+            fn default() -> Self {
+                Self {
+                    $($name: $default,)*
+                }
+            }
+        }
+
+        impl EvalParams {
+            /// names of the parameters in the order of the flat form
+            pub const NAMES: &[&str] = &[$(stringify!($name),)*];
+            /// number of integers in the flat form
+            pub const LEN: usize = 0 $(+ <$ty as Param>::LEN)*;
+
+            /// returns every parameter as one list of integers
+            pub fn to_vec(&self) -> Vec<i32> {
+                let mut out = Vec::with_capacity(Self::LEN);
+                $(self.$name.flatten(&mut out);)*
+                out
+            }
+
+            /// reads parameters from a list made by to_vec
+            #[allow(unused_assignments)]
+            pub fn from_slice(values: &[i32]) -> Result<Self, String> {
+                if values.len() != Self::LEN {
+                    return Err(format!(
+                        "Expected {} parameter values, got {}",
+                        Self::LEN,
+                        values.len()
+                    ));
+                }
+                let mut offset = 0;
+                $(
+                    let $name = <$ty as Param>::unflatten(
+                        &values[offset..offset + <$ty as Param>::LEN],
+                    );
+                    offset += <$ty as Param>::LEN;
+                )*
+                Ok(Self { $($name,)* })
+            }
+
+            /// returns the offset of the named parameter in the flat form and its length
+            pub fn range_of(name: &str) -> Option<(usize, usize)> {
+                let mut offset = 0;
+                $(
+                    if name == stringify!($name) {
+                        return Some((offset, <$ty as Param>::LEN));
+                    }
+                    offset += <$ty as Param>::LEN;
+                )*
+                None
+            }
+
+            /// writes the parameters as a TOML document
+            pub fn to_toml(&self) -> String {
+                let mut out = String::from(
+                    "# evaluation parameters, scores are [middlegame, endgame] pairs\n",
+                );
+                $(
+                    out.push('\n');
+                    $(
+                        out.push('#');
+                        out.push_str($doc);
+                        out.push('\n');
+                    )*
+                    out.push_str(concat!(stringify!($name), " = "));
+                    self.$name.write_toml(&mut out, 0);
+                    out.push('\n');
+                )*
+                out
+            }
+
+            fn set(&mut self, name: &str, values: &[i32]) -> Result<(), String> {
+                match name {
+                    $(stringify!($name) => {
+                        if values.len() != <$ty as Param>::LEN {
+                            return Err(format!(
+                                "Parameter {} needs {} values, got {}",
+                                name,
+                                <$ty as Param>::LEN,
+                                values.len()
+                            ));
+                        }
+                        self.$name = <$ty as Param>::unflatten(values);
+                    })*
+                    _ => return Err(format!("Unknown parameter {}", name)),
+                }
+                Ok(())
+            }
+        }
+    };
+}
+
+const fn pair(mg: &[i32; 64], eg: &[i32; 64]) -> [Score; 64] {
+    let mut table = [Score::ZERO; 64];
+    let mut i = 0;
+    while i < 64 {
+        table[i] = Score::new(mg[i], eg[i]);
+        i += 1;
+    }
+    table
+}
+
+const fn scores<const N: usize>(values: [(i32, i32); N]) -> [Score; N] {
+    let mut table = [Score::ZERO; N];
+    let mut i = 0;
+    while i < N {
+        table[i] = Score::new(values[i].0, values[i].1);
+        i += 1;
+    }
+    table
+}
+
+eval_params! {
+    /// indexed by GenericPiece
+    material: [Score; 6] = scores([(82, 94), (337, 281), (365, 297), (477, 512), (1025, 936), (0, 0)]),
+    /// piece-square tables indexed by GenericPiece, from white's point of view with a8 first
+    pst: [[Score; 64]; 6] = [
+        pair(&MG_PAWN, &EG_PAWN),
+        pair(&MG_KNIGHT, &EG_KNIGHT),
+        pair(&MG_BISHOP, &EG_BISHOP),
+        pair(&MG_ROOK, &EG_ROOK),
+        pair(&MG_QUEEN, &EG_QUEEN),
+        pair(&MG_KING, &EG_KING),
+    ],
+    doubled: Score = Score::new(-10, -20),
+    isolated: Score = Score::new(-10, -15),
+    backward: Score = Score::new(-8, -10),
+    /// indexed by the rank of the pawn relative to its color
+    connected: [Score; 8] = scores([(0, 0), (3, 0), (5, 2), (8, 5), (15, 12), (25, 25), (40, 40), (0, 0)]),
+    passed: [Score; 8] = scores([(0, 0), (5, 10), (8, 15), (12, 25), (30, 50), (60, 100), (100, 150), (0, 0)]),
+    /// added to passed when the square in front of the pawn is occupied
+    passed_blocked: [Score; 8] = scores([(0, 0), (-2, -5), (-4, -7), (-6, -12), (-15, -25), (-30, -50), (-50, -75), (0, 0)]),
+    candidate: [Score; 8] = scores([(0, 0), (2, 5), (4, 8), (8, 12), (15, 25), (25, 40), (0, 0), (0, 0)]),
+    /// per square between a king and the square in front of a passed pawn, scaled by how far
+    /// the pawn is advanced
+    passed_enemy_king_distance: Score = Score::new(0, 5),
+    passed_own_king_distance: Score = Score::new(0, -2),
+    /// value of each reachable square beyond the baseline, indexed by GenericPiece
+    mobility: [Score; 6] = scores([(0, 0), (4, 4), (5, 5), (2, 4), (1, 2), (0, 0)]),
+    /// attack units per attacked king zone square, indexed by GenericPiece
+    king_attack_weights: [i32; 6] = [0, 2, 2, 3, 5, 0],
+    /// attackers needed before attacks on the king zone count
+    king_min_attackers: i32 = 2,
+    /// highest middlegame penalty for attacks on the king zone
+    king_max_danger: i32 = 500,
+    shield_near: Score = Score::new(12, 0),
+    shield_far: Score = Score::new(6, 0),
+    storm: Score = Score::new(-8, 0),
+    /// king file or neighbouring file without own pawns
+    king_semi_open_file: Score = Score::new(-15, 0),
+    /// additional penalty when the file has no pawns at all
+    king_open_file: Score = Score::new(-10, 0),
+}
+
+impl EvalParams {
+    /// reads parameters from a TOML document, which has to set every parameter
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let mut params = Self::default();
+        let mut seen = Vec::new();
+        let mut lines = text.lines().enumerate();
+        while let Some((number, line)) = lines.next() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = line
+                .split_once('=')
+                .ok_or(format!("Expected name = value on line {}", number + 1))?;
+            let mut value = value.to_string();
+            // arrays may continue over several lines until their brackets are balanced
+            while value.matches('[').count() > value.matches(']').count() {
+                let (_, next) = lines
+                    .next()
+                    .ok_or(format!("Unterminated array for {}", name.trim()))?;
+                value.push(' ');
+                value.push_str(strip_comment(next));
+            }
+
+            let values = value
+                .split(|c: char| c == '[' || c == ']' || c == ',' || c.is_whitespace())
+                .filter(|token| !token.is_empty())
+                .map(|token| {
+                    token
+                        .parse()
+                        .map_err(|_| format!("Invalid number {} for {}", token, name.trim()))
+                })
+                .collect::<Result<Vec<i32>, String>>()?;
+            params.set(name.trim(), &values)?;
+            seen.push(name.trim().to_string());
+        }
+        if let Some(missing) = Self::NAMES
+            .iter()
+            .find(|name| !seen.iter().any(|s| s == *name))
+        {
+            return Err(format!("Missing parameter {}", missing));
+        }
+        Ok(params)
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+        Self::from_toml(&text)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, self.to_toml()).map_err(|e| format!("Cannot write {}: {}", path, e))
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    line.split('#').next().unwrap_or("")
+}
+
+static CURRENT: LazyLock<RwLock<Arc<EvalParams>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(EvalParams::default())));
+
+/// returns the parameters new boards are evaluated with
+pub fn current() -> Arc<EvalParams> {
+    Arc::clone(&CURRENT.read().unwrap())
+}
+
+/// replaces the parameters new boards are evaluated with
+pub fn set_current(params: EvalParams) {
+    *CURRENT.write().unwrap() = Arc::new(params);
+}
+
+/// makes the parameters in the file current, or the defaults for an empty path, leaving the
+/// current ones in place if the file cannot be loaded
+pub fn load_current(path: &str) -> Result<(), String> {
+    let params = if path.is_empty() {
+        EvalParams::default()
+    } else {
+        EvalParams::load(path)?
+    };
+    set_current(params);
+    Ok(())
+}
+
+#[rustfmt::skip]
+const MG_PAWN: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     98, 134,  61,  95,  68, 126,  34, -11,
+     -6,   7,  26,  31,  65,  56,  25, -20,
+    -14,  13,   6,  21,  23,  12,  17, -23,
+    -27,  -2,  -5,  12,  17,   6,  10, -25,
+    -26,  -4,  -4, -10,   3,   3,  33, -12,
+    -35,  -1, -20, -23, -15,  24,  38, -22,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const EG_PAWN: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+    178, 173, 158, 134, 147, 132, 165, 187,
+     94, 100,  85,  67,  56,  53,  82,  84,
+     32,  24,  13,   5,  -2,   4,  17,  17,
+     13,   9,  -3,  -7,  -7,  -8,   3,  -1,
+      4,   7,  -6,   1,   0,  -5,  -1,  -8,
+     13,   8,   8,  10,  13,   0,   2,  -7,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const MG_KNIGHT: [i32; 64] = [
+   -167, -89, -34, -49,  61, -97, -15,-107,
+    -73, -41,  72,  36,  23,  62,   7, -17,
+    -47,  60,  37,  65,  84, 129,  73,  44,
+     -9,  17,  19,  53,  37,  69,  18,  22,
+    -13,   4,  16,  13,  28,  19,  21,  -8,
+    -23,  -9,  12,  10,  19,  17,  25, -16,
+    -29, -53, -12,  -3,  -1,  18, -14, -19,
+   -105, -21, -58, -33, -17, -28, -19, -23,
+];
+
+#[rustfmt::skip]
+const EG_KNIGHT: [i32; 64] = [
+    -58, -38, -13, -28, -31, -27, -63, -99,
+    -25,  -8, -25,  -2,  -9, -25, -24, -52,
+    -24, -20,  10,   9,  -1,  -9, -19, -41,
+    -17,   3,  22,  22,  22,  11,   8, -18,
+    -18,  -6,  16,  25,  16,  17,   4, -18,
+    -23,  -3,  -1,  15,  10,  -3, -20, -22,
+    -42, -20, -10,  -5,  -2, -20, -23, -44,
+    -29, -51, -23, -15, -22, -18, -50, -64,
+];
+
+#[rustfmt::skip]
+const MG_BISHOP: [i32; 64] = [
+    -29,   4, -82, -37, -25, -42,   7,  -8,
+    -26,  16, -18, -13,  30,  59,  18, -47,
+    -16,  37,  43,  40,  35,  50,  37,  -2,
+     -4,   5,  19,  50,  37,  37,   7,  -2,
+     -6,  13,  13,  26,  34,  12,  10,   4,
+      0,  15,  15,  15,  14,  27,  18,  10,
+      4,  15,  16,   0,   7,  21,  33,   1,
+    -33,  -3, -14, -21, -13, -12, -39, -21,
+];
+
+#[rustfmt::skip]
+const EG_BISHOP: [i32; 64] = [
+    -14, -21, -11,  -8,  -7,  -9, -17, -24,
+     -8,  -4,   7, -12,  -3, -13,  -4, -14,
+      2,  -8,   0,  -1,  -2,   6,   0,   4,
+     -3,   9,  12,   9,  14,  10,   3,   2,
+     -6,   3,  13,  19,   7,  10,  -3,  -9,
+    -12,  -3,   8,  10,  13,   3,  -7, -15,
+    -14, -18,  -7,  -1,   4,  -9, -15, -27,
+    -23,  -9, -23,  -5,  -9, -16,  -5, -17,
+];
+
+#[rustfmt::skip]
+const MG_ROOK: [i32; 64] = [
+     32,  42,  32,  51,  63,   9,  31,  43,
+     27,  32,  58,  62,  80,  67,  26,  44,
+     -5,  19,  26,  36,  17,  45,  61,  16,
+    -24, -11,   7,  26,  24,  35,  -8, -20,
+    -36, -26, -12,  -1,   9,  -7,   6, -23,
+    -45, -25, -16, -17,   3,   0,  -5, -33,
+    -44, -16, -20,  -9,  -1,  11,  -6, -71,
+    -19, -13,   1,  17,  16,   7, -37, -26,
+];
+
+#[rustfmt::skip]
+const EG_ROOK: [i32; 64] = [
+     13,  10,  18,  15,  12,  12,   8,   5,
+     11,  13,  13,  11,  -3,   3,   8,   3,
+      7,   7,   7,   5,   4,  -3,  -5,  -3,
+      4,   3,  13,   1,   2,   1,  -1,   2,
+      3,   5,   8,   4,  -5,  -6,  -8, -11,
+     -4,   0,  -5,  -1,  -7, -12,  -8, -16,
+     -6,  -6,   0,   2,  -9,  -9, -11,  -3,
+     -9,   2,   3,  -1,  -5, -13,   4, -20,
+];
+
+#[rustfmt::skip]
+const MG_QUEEN: [i32; 64] = [
+    -28,   0,  29,  12,  59,  44,  43,  45,
+    -24, -39,  -5,   1, -16,  57,  28,  54,
+    -13, -17,   7,   8,  29,  56,  47,  57,
+    -27, -27, -16, -16,  -1,  17,  -2,   1,
+     -9, -26,  -9, -10,  -2,  -4,   3,  -3,
+    -14,   2, -11,  -2,  -5,   2,  14,   5,
+    -35,  -8,  11,   2,   8,  15,  -3,   1,
+     -1, -18,  -9,  10, -15, -25, -31, -50,
+];
+
+#[rustfmt::skip]
+const EG_QUEEN: [i32; 64] = [
+     -9,  22,  22,  27,  27,  19,  10,  20,
+    -17,  20,  32,  41,  58,  25,  30,   0,
+    -20,   6,   9,  49,  47,  35,  19,   9,
+      3,  22,  24,  45,  57,  40,  57,  36,
+    -18,  28,  19,  47,  31,  34,  39,  23,
+    -16, -27,  15,   6,   9,  17,  10,   5,
+    -22, -23, -30, -16, -16, -23, -36, -32,
+    -33, -28, -22, -43,  -5, -32, -20, -41,
+];
+
+#[rustfmt::skip]
+const MG_KING: [i32; 64] = [
+    -65,  23,  16, -15, -56, -34,   2,  13,
+     29,  -1, -20,  -7,  -8,  -4, -38, -29,
+     -9,  24,   2, -16, -20,   6,  22, -22,
+    -17, -20, -12, -27, -30, -25, -14, -36,
+    -49,  -1, -27, -39, -46, -44, -33, -51,
+    -14, -14, -22, -46, -44, -30, -15, -27,
+      1,   7,  -8, -64, -43, -16,   9,   8,
+    -15,  36,  12, -54,   8, -28,  24,  14,
+];
+
+#[rustfmt::skip]
+const EG_KING: [i32; 64] = [
+    -74, -35, -18, -18, -11,  15,   4, -17,
+    -12,  17,  14,  17,  17,  38,  23,  11,
+     10,  17,  23,  15,  20,  45,  44,  13,
+     -8,  22,  24,  27,  26,  33,  26,   3,
+    -18,  -4,  21,  24,  27,  23,   9, -11,
+    -19,  -3,  11,  21,  23,  16,   7,  -9,
+    -27, -11,   4,  13,  14,   4,  -5, -17,
+    -53, -34, -21, -11, -28, -14, -24, -43,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_round_trip() {
+        let mut params = EvalParams {
+            doubled: Score::new(-13, -27),
+            king_max_danger: 321,
+            ..Default::default()
+        };
+        params.pst[3][10] = Score::new(-5, 7);
+        assert_eq!(EvalParams::from_toml(&params.to_toml()).unwrap(), params);
+        assert_eq!(
+            EvalParams::from_toml(&EvalParams::default().to_toml()).unwrap(),
+            EvalParams::default()
+        );
+    }
+
+    #[test]
+    fn unknown_parameter_is_an_error() {
+        let text = format!("{}not_a_parameter = 5\n", EvalParams::default().to_toml());
+        let error = EvalParams::from_toml(&text).unwrap_err();
+        assert!(error.contains("not_a_parameter"), "{}", error);
+    }
+
+    #[test]
+    fn missing_parameter_is_an_error() {
+        let text: String = EvalParams::default()
+            .to_toml()
+            .lines()
+            .filter(|line| !line.starts_with("king_max_danger"))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let error = EvalParams::from_toml(&text).unwrap_err();
+        assert!(error.contains("king_max_danger"), "{}", error);
+    }
+
+    #[test]
+    fn failed_load_keeps_the_current_parameters() {
+        let before = current();
+        assert!(load_current("/nonexistent/params.toml").is_err());
+        assert!(Arc::ptr_eq(&before, &current()));
+    }
+
+    #[test]
+    fn wrong_value_count_is_an_error() {
+        let text = format!("{}doubled = [1, 2, 3]\n", EvalParams::default().to_toml());
+        assert!(EvalParams::from_toml(&text).is_err());
+    }
+}
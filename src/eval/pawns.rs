@@ -0,0 +1,323 @@
+use crate::state::game_constants::{FILE_A, FILE_H};
+use crate::state::{Color, ColoredPiece, GenericPiece, board::Board};
+use crate::util::bithelpers::BitFunctions;
+
+use std::sync::Arc;
+
+use super::params::{EvalParams, OFFSETS, score_index};
+use super::psqt::Score;
+use super::trace::{NoTrace, Trace, term};
+
+const PAWN_TABLE_ENTRIES: usize = 1 << 14;
+
+/// shifts the bitboard one rank forward from the given color's point of view
+pub fn forward(bb: u64, color: Color) -> u64 {
+    match color {
+        Color::White => bb << 8,
+        Color::Black => bb >> 8,
+    }
+}
+
+/// spreads every bit over all the squares in front of it, including its own
+pub fn front_fill(mut bb: u64, color: Color) -> u64 {
+    match color {
+        Color::White => {
+            bb |= bb << 8;
+            bb |= bb << 16;
+            bb |= bb << 32;
+        }
+        Color::Black => {
+            bb |= bb >> 8;
+            bb |= bb >> 16;
+            bb |= bb >> 32;
+        }
+    }
+    bb
+}
+
+/// spreads every bit over its whole file
+pub fn file_fill(bb: u64) -> u64 {
+    front_fill(bb, Color::White) | front_fill(bb, Color::Black)
+}
+
+/// returns the squares on the files next to the set bits
+pub fn adjacent_files(bb: u64) -> u64 {
+    ((bb & !FILE_A) >> 1) | ((bb & !FILE_H) << 1)
+}
+
+/// returns the squares attacked by the given pawns
+pub fn pawn_attacks(pawns: u64, color: Color) -> u64 {
+    adjacent_files(forward(pawns, color))
+}
+
+/// returns the rank of the square from the given color's point of view
+pub fn relative_rank(square: u32, color: Color) -> usize {
+    match color {
+        Color::White => (square / 8) as usize,
+        Color::Black => 7 - (square / 8) as usize,
+    }
+}
+
+fn pawns(board: &Board, color: Color) -> u64 {
+    board.pieces[ColoredPiece::from_parts(color, GenericPiece::Pawn) as usize]
+}
+
+/// returns the pawns no enemy pawn can stop and no friendly pawn stands in front of
+pub fn passed_pawns(own: u64, enemy: u64, color: Color) -> u64 {
+    // squares the enemy pawns can still reach or attack when moving toward this side
+    let enemy_front = front_fill(forward(enemy, color.opposite()), color.opposite());
+    let enemy_control = enemy_front | adjacent_files(enemy_front);
+    let behind_own = front_fill(forward(own, color.opposite()), color.opposite());
+    own & !enemy_control & !behind_own
+}
+
+/// pawn structure of one position, depending on nothing but the pawns
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PawnEntry {
+    key: u64,
+    /// structure score from white's point of view
+    pub score: Score,
+    /// passed pawns of each color
+    pub passed: [u64; 2],
+}
+
+/// returns the structure score of one side's pawns and its passed pawns
+fn evaluate_side<T: Trace>(
+    board: &Board,
+    params: &EvalParams,
+    color: Color,
+    trace: &mut T,
+) -> (Score, u64) {
+    let own = pawns(board, color);
+    let enemy = pawns(board, color.opposite());
+    let own_attacks = pawn_attacks(own, color);
+    let enemy_attacks = pawn_attacks(enemy, color.opposite());
+
+    let enemy_front = front_fill(forward(enemy, color.opposite()), color.opposite());
+    // pawns with a friendly pawn in front of them on the same file
+    let behind_own = front_fill(forward(own, color.opposite()), color.opposite());
+    let passed = passed_pawns(own, enemy, color);
+
+    let doubled = own & behind_own;
+    let isolated = own & !adjacent_files(file_fill(own));
+    let phalanx = own & adjacent_files(own);
+    let connected = own & (own_attacks | phalanx);
+    // a pawn is backward if it can no longer be supported by a neighbour and its advance is
+    // stopped by an enemy pawn attack
+    let supportable = front_fill(own_attacks, color);
+    let backward = own & !isolated & forward(enemy_attacks & !supportable, color.opposite());
+
+    let mut score = term(
+        trace,
+        color,
+        OFFSETS.doubled,
+        params.doubled,
+        doubled.count_set_bits() as i32,
+    ) + term(
+        trace,
+        color,
+        OFFSETS.isolated,
+        params.isolated,
+        isolated.count_set_bits() as i32,
+    ) + term(
+        trace,
+        color,
+        OFFSETS.backward,
+        params.backward,
+        backward.count_set_bits() as i32,
+    );
+
+    connected.bit_for_each(|square| {
+        let rank = relative_rank(square, color);
+        let index = score_index(OFFSETS.connected, rank);
+        score += term(trace, color, index, params.connected[rank], 1);
+    });
+    passed.bit_for_each(|square| {
+        let rank = relative_rank(square, color);
+        let index = score_index(OFFSETS.passed, rank);
+        score += term(trace, color, index, params.passed[rank], 1);
+    });
+
+    // a half-open pawn that is not passed yet but has at least as many friendly pawns that can
+    // support its advance as enemy pawns that can stop it
+    let half_open = own & !enemy_front & !behind_own & !passed;
+    half_open.bit_for_each(|square| {
+        let bit = 1u64 << square;
+        let files = adjacent_files(file_fill(bit));
+        let ahead = front_fill(forward(bit, color), color);
+        let sentries = enemy & files & ahead;
+        let helpers = own & files & !ahead;
+        if helpers.count_set_bits() >= sentries.count_set_bits() {
+            let rank = relative_rank(square, color);
+            let index = score_index(OFFSETS.candidate, rank);
+            score += term(trace, color, index, params.candidate[rank], 1);
+        }
+    });
+
+    (score, passed)
+}
+
+/// computes the pawn structure of the board without any caching
+pub fn evaluate_pawns<T: Trace>(board: &Board, trace: &mut T) -> PawnEntry {
+    let params = &board.psqt.params;
+    let (white, white_passed) = evaluate_side(board, params, Color::White, trace);
+    let (black, black_passed) = evaluate_side(board, params, Color::Black, trace);
+    PawnEntry {
+        key: board.state.pawn_hash,
+        score: white - black,
+        passed: [white_passed, black_passed],
+    }
+}
+
+/// returns the score of the given passed pawns from their owner's point of view, scaled down
+/// when blocked and up the further the enemy king is from their path
+pub fn passed_pawn_extras<T: Trace>(
+    board: &Board,
+    passed: u64,
+    color: Color,
+    trace: &mut T,
+) -> Score {
+    let params = &board.psqt.params;
+    let occupied = board.occupied[0] | board.occupied[1];
+    let own_king = board.king_square(color);
+    let enemy_king = board.king_square(color.opposite());
+    let mut score = Score::ZERO;
+
+    passed.bit_for_each(|square| {
+        let rank = relative_rank(square, color);
+        let stop = match color {
+            Color::White => square + 8,
+            Color::Black => square - 8,
+        };
+        if occupied.contains(1u64 << stop) {
+            let index = score_index(OFFSETS.passed_blocked, rank);
+            score += term(trace, color, index, params.passed_blocked[rank], 1);
+        }
+
+        let weight = rank.saturating_sub(2) as i32;
+        if let (Some(own_king), Some(enemy_king)) = (own_king, enemy_king) {
+            score += term(
+                trace,
+                color,
+                OFFSETS.passed_enemy_king_distance,
+                params.passed_enemy_king_distance,
+                weight * distance(enemy_king, stop),
+            ) + term(
+                trace,
+                color,
+                OFFSETS.passed_own_king_distance,
+                params.passed_own_king_distance,
+                weight * distance(own_king, stop),
+            );
+        }
+    });
+
+    score
+}
+
+/// returns the number of king moves between the two squares
+pub fn distance(a: u32, b: u32) -> i32 {
+    let file = (a % 8).abs_diff(b % 8);
+    let rank = (a / 8).abs_diff(b / 8);
+    file.max(rank) as i32
+}
+
+/// cache of pawn structure evaluations keyed by the pawn hash
+pub struct PawnTable {
+    entries: Vec<PawnEntry>,
+    /// parameters the entries were computed with
+    params: Option<Arc<EvalParams>>,
+}
+
+impl PawnTable {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![PawnEntry::default(); PAWN_TABLE_ENTRIES],
+            params: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(PawnEntry::default());
+    }
+
+    /// returns the pawn structure of the board, computing and storing it on a miss
+    pub fn probe(&mut self, board: &Board) -> PawnEntry {
+        if !self
+            .params
+            .as_ref()
+            .is_some_and(|params| Arc::ptr_eq(params, &board.psqt.params))
+        {
+            self.clear();
+            self.params = Some(Arc::clone(&board.psqt.params));
+        }
+        let key = board.state.pawn_hash;
+        let index = key as usize & (PAWN_TABLE_ENTRIES - 1);
+        let entry = &mut self.entries[index];
+        if entry.key != key {
+            *entry = evaluate_pawns(board, &mut NoTrace);
+        }
+        *entry
+    }
+}
+
+impl Default for PawnTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{PawnTable, evaluate_pawns};
+    use crate::eval::{self, Evaluator, params::EvalParams, trace::NoTrace};
+    use crate::state::{self, fen::fen_to_board};
+    use crate::util::const_rand::XorShift64;
+
+    #[test]
+    fn cached_entries_match_a_fresh_evaluation() {
+        state::init();
+        let mut rng = XorShift64::new(0x2545_f491_4f6c_dd1d);
+        let mut table = PawnTable::new();
+        let mut evaluator = Evaluator::new();
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "2r3k1/pp3ppp/4p3/3pP3/3P4/P4N2/1P3PPP/2R3K1 b - - 0 25",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            let mut board = fen_to_board(fen).unwrap();
+            for _ in 0..200 {
+                let fresh = evaluate_pawns(&board, &mut NoTrace);
+                // the first probe may miss, the second always hits
+                assert_eq!(table.probe(&board), fresh);
+                assert_eq!(table.probe(&board), fresh);
+                assert_eq!(evaluator.evaluate(&board), eval::evaluate(&board));
+
+                let moves = board.legal_moves();
+                if moves.is_empty() {
+                    board = fen_to_board(fen).unwrap();
+                    continue;
+                }
+                let m = moves[(rng.next() % moves.len() as u64) as usize];
+                board.make_move(m).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn new_parameters_invalidate_the_table() {
+        state::init();
+        let mut board = fen_to_board("4k3/pp4p1/8/3P4/8/8/PP3P2/4K3 w - - 0 1").unwrap();
+        let mut table = PawnTable::new();
+        let before = table.probe(&board);
+
+        let mut params = EvalParams::default();
+        params.passed = params.passed.map(|score| score * 2);
+        board.set_eval_params(Arc::new(params));
+        let after = table.probe(&board);
+        assert_eq!(after, evaluate_pawns(&board, &mut NoTrace));
+        assert_ne!(after, before);
+    }
+}
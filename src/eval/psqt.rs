@@ -0,0 +1,200 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+use std::sync::Arc;
+
+use crate::state::{Color, ColoredPiece, board::Board};
+use crate::util::bithelpers::BitFunctions;
+use strum::IntoEnumIterator;
+
+use super::params::{self, EvalParams};
+
+/// a pair of middlegame and endgame values
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Score {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl Score {
+    pub const ZERO: Score = Score { mg: 0, eg: 0 };
+
+    pub const fn new(mg: i32, eg: i32) -> Self {
+        Self { mg, eg }
+    }
+
+    /// interpolates between the middlegame and endgame value, phase going from 0 in a bare
+    /// endgame to MAX_PHASE with all pieces on the board
+    pub fn taper(self, phase: i32) -> i32 {
+        let phase = phase.clamp(0, MAX_PHASE);
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+
+    fn add(self, other: Score) -> Score {
+        Score::new(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+
+    fn sub(self, other: Score) -> Score {
+        Score::new(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Score;
+
+    fn mul(self, factor: i32) -> Score {
+        Score::new(self.mg * factor, self.eg * factor)
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+
+    fn neg(self) -> Score {
+        Score::new(-self.mg, -self.eg)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Score) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Score {
+    fn sub_assign(&mut self, other: Score) {
+        *self = *self - other;
+    }
+}
+
+/// contribution of each generic piece to the game phase
+pub const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+/// phase of the starting position
+pub const MAX_PHASE: i32 = 24;
+
+/// returns the material and piece-square value of a piece on a square, from white's view
+pub fn psqt_value(params: &EvalParams, piece: ColoredPiece, square: u32) -> Score {
+    let generic = piece.generic() as usize;
+    match piece.color() {
+        // the tables are written from white's point of view with a8 first, black reads them
+        // mirrored so both colors value their pieces the same
+        Color::White => params.material[generic] + params.pst[generic][(square ^ 56) as usize],
+        Color::Black => -(params.material[generic] + params.pst[generic][square as usize]),
+    }
+}
+
+/// material and piece-square sum of a position kept up to date by make and unmake move,
+/// together with the parameters it was computed with
+#[derive(Debug, Clone)]
+pub struct Psqt {
+    /// sum from white's point of view
+    pub score: Score,
+    pub phase: i32,
+    pub params: Arc<EvalParams>,
+}
+
+impl Default for Psqt {
+    fn default() -> Self {
+        Self::empty(params::current())
+    }
+}
+
+impl PartialEq for Psqt {
+    fn eq(&self, other: &Self) -> bool {
+        self.score == other.score && self.phase == other.phase
+    }
+}
+
+impl Psqt {
+    /// returns the sums of an empty board
+    pub fn empty(params: Arc<EvalParams>) -> Self {
+        Self {
+            score: Score::ZERO,
+            phase: 0,
+            params,
+        }
+    }
+
+    /// computes the sums from scratch with the given parameters
+    pub fn calculate(board: &Board, params: Arc<EvalParams>) -> Self {
+        let mut psqt = Psqt::empty(params);
+        for piece in ColoredPiece::iter() {
+            let mut bb = board.pieces[piece as usize];
+            while bb != 0 {
+                psqt.add(piece, bb.pop_lsb());
+            }
+        }
+        psqt
+    }
+
+    pub fn add(&mut self, piece: ColoredPiece, square: u32) {
+        self.score += psqt_value(&self.params, piece, square);
+        self.phase += PHASE_WEIGHTS[piece.generic() as usize];
+    }
+
+    pub fn remove(&mut self, piece: ColoredPiece, square: u32) {
+        self.score -= psqt_value(&self.params, piece, square);
+        self.phase -= PHASE_WEIGHTS[piece.generic() as usize];
+    }
+
+    /// returns the tapered score from white's point of view
+    pub fn value(&self) -> i32 {
+        self.score.taper(self.phase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::Psqt;
+    use crate::state::{self, board::Board, fen::fen_to_board};
+    use crate::util::const_rand::XorShift64;
+
+    fn assert_fresh(board: &Board, context: &str) {
+        let fresh = Psqt::calculate(board, Arc::clone(&board.psqt.params));
+        assert_eq!(board.psqt.score, fresh.score, "score drifts {}", context);
+        assert_eq!(board.psqt.phase, fresh.phase, "phase drifts {}", context);
+    }
+
+    #[test]
+    fn incremental_sums_match_recalculation() {
+        state::init();
+        let mut rng = XorShift64::new(0x9e37_79b9_7f4a_7c15);
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b Kq e3 0 3",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            for _ in 0..20 {
+                let mut board = fen_to_board(fen).unwrap();
+                let mut played = Vec::new();
+                for _ in 0..40 {
+                    let moves = board.legal_moves();
+                    if moves.is_empty() {
+                        break;
+                    }
+                    let m = moves[(rng.next() % moves.len() as u64) as usize];
+                    board.make_move(m).unwrap();
+                    played.push(m);
+                    assert_fresh(&board, &format!("after {} from {}", m.to_uci(), fen));
+                }
+                while let Some(m) = played.pop() {
+                    board.unmake_move(m);
+                    assert_fresh(
+                        &board,
+                        &format!("after undoing {} from {}", m.to_uci(), fen),
+                    );
+                }
+                assert_eq!(board.psqt, fen_to_board(fen).unwrap().psqt);
+            }
+        }
+    }
+}
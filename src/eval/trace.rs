@@ -0,0 +1,172 @@
+use std::fmt;
+
+use crate::state::Color;
+
+use super::endgame::SCALE_NORMAL;
+use super::params::EvalParams;
+use super::psqt::{MAX_PHASE, Score};
+
+/// receives the linear terms of the hand written evaluation, each being a score parameter
+/// counted for one color, so tools can see how every weight contributes to a position
+pub trait Trace {
+    /// records that the score parameter at the given flat index counts count times for color
+    fn add(&mut self, index: usize, color: Color, count: i32);
+
+    /// records the danger to the color's king, which is not linear in its parameters
+    fn king_danger(&mut self, _color: Color, _danger: Score) {}
+}
+
+/// trace used by normal evaluation, which records nothing
+pub struct NoTrace;
+
+impl Trace for NoTrace {
+    #[inline(always)]
+    fn add(&mut self, _index: usize, _color: Color, _count: i32) {}
+}
+
+/// records a term and returns its value
+#[inline(always)]
+pub fn term<T: Trace>(
+    trace: &mut T,
+    color: Color,
+    index: usize,
+    value: Score,
+    count: i32,
+) -> Score {
+    trace.add(index, color, count);
+    value * count
+}
+
+/// contribution of one evaluation term to each side, indexed by Color
+#[derive(Debug, Clone, Copy)]
+pub struct TermScore {
+    pub name: &'static str,
+    pub scores: [Score; 2],
+}
+
+/// breakdown of the hand written evaluation of one position into its terms
+#[derive(Debug, Clone)]
+pub struct EvalTrace {
+    pub terms: Vec<TermScore>,
+    pub phase: i32,
+    /// factor out of SCALE_NORMAL applied to the endgame part
+    pub scale: i32,
+    /// score of a recognized endgame from white's point of view, which replaces the terms
+    pub endgame: Option<i32>,
+    pub side_to_move: Color,
+}
+
+impl EvalTrace {
+    /// returns the sum of all terms from white's point of view
+    pub fn total(&self) -> Score {
+        self.terms.iter().fold(Score::ZERO, |sum, term| {
+            sum + term.scores[Color::White as usize] - term.scores[Color::Black as usize]
+        })
+    }
+
+    /// returns the tapered evaluation of the terms from white's point of view
+    pub fn blended(&self) -> i32 {
+        let total = self.total();
+        Score::new(total.mg, total.eg * self.scale / SCALE_NORMAL).taper(self.phase)
+    }
+
+    /// returns the final evaluation from the side to move's point of view, which is what the
+    /// search sees
+    pub fn score(&self) -> i32 {
+        let score = self.endgame.unwrap_or_else(|| self.blended());
+        match self.side_to_move {
+            Color::White => score,
+            Color::Black => -score,
+        }
+    }
+}
+
+impl fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let line = "-".repeat(70);
+        writeln!(
+            f,
+            "{:<28}{:>14}{:>14}{:>14}",
+            "term", "white", "black", "total"
+        )?;
+        writeln!(
+            f,
+            "{:<28}{:>7}{:>7}{:>7}{:>7}{:>7}{:>7}",
+            "", "mg", "eg", "mg", "eg", "mg", "eg"
+        )?;
+        writeln!(f, "{}", line)?;
+        for term in &self.terms {
+            let white = term.scores[Color::White as usize];
+            let black = term.scores[Color::Black as usize];
+            let total = white - black;
+            writeln!(
+                f,
+                "{:<28}{:>7}{:>7}{:>7}{:>7}{:>7}{:>7}",
+                term.name, white.mg, white.eg, black.mg, black.eg, total.mg, total.eg
+            )?;
+        }
+        writeln!(f, "{}", line)?;
+        let total = self.total();
+        writeln!(f, "{:<56}{:>7}{:>7}", "total", total.mg, total.eg)?;
+        writeln!(f, "phase {}/{}", self.phase.clamp(0, MAX_PHASE), MAX_PHASE)?;
+        writeln!(f, "endgame scale {}/{}", self.scale, SCALE_NORMAL)?;
+        writeln!(f, "blended {} (white)", self.blended())?;
+        if let Some(score) = self.endgame {
+            writeln!(f, "recognized endgame {} (white)", score)?;
+        }
+        writeln!(f, "final {} (side to move)", self.score())
+    }
+}
+
+/// trace that sums the value of every recorded parameter into the term it belongs to
+pub struct TermCollector {
+    values: Vec<i32>,
+    /// index into terms of the term owning each flat parameter
+    owners: Vec<Option<usize>>,
+    /// index into terms of the king danger, if it is collected
+    king_danger: Option<usize>,
+    terms: Vec<TermScore>,
+}
+
+impl TermCollector {
+    /// collects into the named parameters, in the given order, valued with params
+    pub fn new(params: &EvalParams, names: &[&'static str]) -> Self {
+        let mut owners = vec![None; EvalParams::LEN];
+        for (term, name) in names.iter().enumerate() {
+            if let Some((offset, len)) = EvalParams::range_of(name) {
+                owners[offset..offset + len].fill(Some(term));
+            }
+        }
+        Self {
+            values: params.to_vec(),
+            owners,
+            king_danger: names.iter().position(|&name| name == "king_danger"),
+            terms: names
+                .iter()
+                .map(|&name| TermScore {
+                    name,
+                    scores: [Score::ZERO; 2],
+                })
+                .collect(),
+        }
+    }
+
+    pub fn into_terms(self) -> Vec<TermScore> {
+        self.terms
+    }
+}
+
+impl Trace for TermCollector {
+    fn add(&mut self, index: usize, color: Color, count: i32) {
+        if let Some(term) = self.owners[index] {
+            let value = Score::new(self.values[index], self.values[index + 1]);
+            self.terms[term].scores[color as usize] += value * count;
+        }
+    }
+
+    fn king_danger(&mut self, color: Color, danger: Score) {
+        if let Some(term) = self.king_danger {
+            self.terms[term].scores[color as usize] += danger;
+        }
+    }
+}
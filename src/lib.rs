@@ -0,0 +1,7 @@
+pub mod eval;
+pub mod search;
+pub mod solver;
+pub mod state;
+pub mod uci;
+pub mod util;
+pub mod xboard;
@@ -1,18 +1,101 @@
 #![allow(dead_code)]
 #![allow(unused)]
 
-use state::{board::Board, fen};
-
-mod state;
-mod util;
+use mautlie_rs::state::{self, board::Board, fen};
+use mautlie_rs::{eval, search, uci};
 
 fn main() {
     state::init();
+    eval::kpk::init();
 
-    let board = Board::default_setup();
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(index) = args.iter().position(|arg| arg == "--eval-params") {
+        let loaded = args
+            .get(index + 1)
+            .ok_or("Usage: --eval-params <file>".to_string())
+            .and_then(|path| eval::params::load_current(path));
+        if let Err(e) = loaded {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        args.drain(index..index + 2);
+    }
 
-    let moves = board.generate_moves();
-    for (i, cmove) in moves.iter().enumerate() {
-        println!("Move {}: {}", i + 1, cmove.to_uci());
+    match args.first().map(String::as_str) {
+        Some("params") => print!("{}", eval::params::current().to_toml()),
+        Some("eval") => {
+            if let Err(e) = run_eval(&args[1..]) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        Some("bench") => {
+            if let Err(e) = run_bench(&args[1..]) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        Some("trace") => {
+            if let Err(e) = run_trace(&args[1..]) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(command) => {
+            eprintln!("Unknown command {}", command);
+            std::process::exit(1);
+        }
+        None => uci::Uci::new().run(),
     }
 }
+
+/// prints the evaluation breakdown of a position
+/// usage: eval [fen]
+fn run_eval(args: &[String]) -> Result<(), String> {
+    let board = if args.is_empty() {
+        Board::default_setup()
+    } else {
+        fen::fen_to_board(&args.join(" "))?
+    };
+    print!("{}", eval::eval_trace(&board));
+    Ok(())
+}
+
+/// searches the bench positions and prints the node count
+/// usage: bench [depth]
+fn run_bench(args: &[String]) -> Result<(), String> {
+    let depth = match args.first() {
+        Some(depth) => depth.parse().map_err(|_| "Invalid depth".to_string())?,
+        None => uci::debug::DEFAULT_BENCH_DEPTH,
+    };
+    uci::debug::bench(depth)
+}
+
+/// searches a position to a fixed depth and prints the search trace as JSON
+/// usage: trace <depth> [fen]
+fn run_trace(args: &[String]) -> Result<(), String> {
+    let depth: u8 = args
+        .first()
+        .ok_or("Usage: trace <depth> [fen]")?
+        .parse()
+        .map_err(|_| "Invalid depth".to_string())?;
+    let board = if args.len() > 1 {
+        fen::fen_to_board(&args[1..].join(" "))?
+    } else {
+        Board::default_setup()
+    };
+
+    let mut pool = search::smp::SearchPool::new(1, search::tt::DEFAULT_HASH_MB);
+    let mut config = pool.config().clone();
+    config.trace = true;
+    pool.set_config(config);
+
+    let limits = search::SearchLimits {
+        depth: Some(depth),
+        ..Default::default()
+    };
+    let result = pool.search(&board, limits);
+    let trace = result.trace.unwrap_or_default();
+    println!("{}", trace.to_json(&result.stats));
+    Ok(())
+}
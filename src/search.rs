@@ -0,0 +1,1134 @@
+use std::cmp::Reverse;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering},
+};
+use std::time::Duration;
+
+use crate::eval::{self, Evaluator};
+use crate::state::{Color, GenericPiece, board::Board, board_move_gen::Move};
+
+pub mod extensions;
+pub mod ordering;
+pub mod pruning;
+pub mod pv;
+pub mod smp;
+pub mod stats;
+pub mod time;
+pub mod tt;
+
+use ordering::Heuristics;
+use pv::PvTable;
+use stats::{PvChange, SearchStats, SearchTrace, TraceIteration};
+use time::TimeManager;
+use tt::{Bound, TranspositionTable};
+
+pub const MAX_PLY: usize = 128;
+pub const INFINITY: i32 = 32_000;
+pub const MATE: i32 = 31_000;
+/// scores beyond this bound are mate scores
+pub const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+
+/// how many nodes are searched between checks of the stop conditions
+const STOP_CHECK_INTERVAL: u64 = 2048;
+/// shallowest iteration searched with an aspiration window
+const ASPIRATION_DEPTH: u8 = 5;
+/// initial half-width of the aspiration window in centipawns
+const ASPIRATION_WINDOW: i32 = 25;
+
+/// engine settings for the search, selective parts are all enabled by default
+#[derive(Debug, Clone)]
+pub struct SearchConfig {
+    pub null_move: bool,
+    pub late_move_reductions: bool,
+    pub reverse_futility: bool,
+    pub futility: bool,
+    pub late_move_pruning: bool,
+    /// time kept in reserve on every move for communication delays
+    pub move_overhead: Duration,
+    /// number of best root moves to find and report
+    pub multi_pv: usize,
+    /// record a trace of iterations and root PV changes
+    pub trace: bool,
+    pub check_extension: bool,
+    pub singular_extension: bool,
+    pub recapture_extension: bool,
+    /// centipawns the engine gives up to avoid a draw, negative values make it seek draws
+    pub contempt: i32,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            null_move: true,
+            late_move_reductions: true,
+            reverse_futility: true,
+            futility: true,
+            late_move_pruning: true,
+            move_overhead: Duration::from_millis(time::DEFAULT_MOVE_OVERHEAD_MS),
+            multi_pv: 1,
+            trace: false,
+            check_extension: true,
+            singular_extension: true,
+            recapture_extension: false,
+            contempt: 0,
+        }
+    }
+}
+
+/// limits on a single search, unset limits are unbounded
+#[derive(Debug, Clone, Default)]
+pub struct SearchLimits {
+    pub depth: Option<u8>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Option<Duration>,
+    pub binc: Option<Duration>,
+    pub moves_to_go: Option<u32>,
+    /// search on the opponent's time, time limits only apply after a ponderhit
+    pub ponder: bool,
+    /// stop once a mate in this many moves is found
+    pub mate: Option<u32>,
+    /// root moves to consider, all of them if empty
+    pub search_moves: Vec<Move>,
+    /// keep searching until stopped, even after reaching the maximum depth
+    pub infinite: bool,
+}
+
+/// one principal variation found by the search, starting with a distinct root move
+#[derive(Debug, Clone)]
+pub struct PvLine {
+    pub depth: u8,
+    pub score: i32,
+    pub pv: Vec<Move>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: u8,
+    pub nodes: u64,
+    pub pv: Vec<Move>,
+    /// the best lines in descending order of score, more than one only in MultiPV mode
+    pub lines: Vec<PvLine>,
+    pub stats: SearchStats,
+    pub trace: Option<SearchTrace>,
+}
+
+impl SearchResult {
+    /// returns the expected reply to the best move
+    pub fn ponder_move(&self) -> Option<Move> {
+        self.pv.get(1).copied()
+    }
+}
+
+/// progress report sent after every completed iteration
+#[derive(Debug, Clone)]
+pub struct IterationInfo {
+    pub depth: u8,
+    /// deepest ply reached so far, including quiescence search
+    pub seldepth: usize,
+    pub nodes: u64,
+    pub elapsed: Duration,
+    /// permille of the transposition table in use
+    pub hashfull: u32,
+    pub lines: Vec<PvLine>,
+}
+
+pub type InfoCallback = Box<dyn FnMut(&IterationInfo) + Send>;
+
+/// per-ply information about the line currently being searched
+#[derive(Debug, Clone, Copy, Default)]
+struct StackEntry {
+    /// move skipped by a singular extension's exclusion search at this ply
+    excluded: Option<Move>,
+    /// extensions applied on the way to this ply
+    extensions: i32,
+    /// move being searched from this ply
+    current_move: Option<Move>,
+}
+
+/// state shared by every thread searching the same position
+#[derive(Clone, Default)]
+pub struct SharedState {
+    pub tt: Arc<TranspositionTable>,
+    pub stop: Arc<AtomicBool>,
+    /// set while searching on the opponent's time, cleared on a ponderhit
+    pub pondering: Arc<AtomicBool>,
+    /// nodes searched by all threads, updated every STOP_CHECK_INTERVAL nodes
+    pub nodes: Arc<AtomicU64>,
+    /// last depth of the main thread once it stops deepening, zero before, helpers do not
+    /// search past it
+    pub main_depth: Arc<AtomicU8>,
+}
+
+impl SharedState {
+    pub fn control(&self) -> SearchControl {
+        SearchControl {
+            stop: Arc::clone(&self.stop),
+            pondering: Arc::clone(&self.pondering),
+        }
+    }
+}
+
+/// handle to control a running search from another thread
+#[derive(Clone)]
+pub struct SearchControl {
+    stop: Arc<AtomicBool>,
+    pondering: Arc<AtomicBool>,
+}
+
+impl SearchControl {
+    /// aborts the search, which then returns its best result so far
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// turns a ponder search into a normal search with the time limits it was started with
+    pub fn ponderhit(&self) {
+        self.pondering.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.pondering.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Searcher {
+    board: Board,
+    config: SearchConfig,
+    limits: SearchLimits,
+    heuristics: Box<Heuristics>,
+    evaluator: Box<Evaluator>,
+    pv_table: Box<PvTable>,
+    shared: SharedState,
+    thread_id: usize,
+    stopped: bool,
+    nodes: u64,
+    seldepth: usize,
+    time: TimeManager,
+    info_callback: Option<InfoCallback>,
+    /// true until this thread notices the ponderhit of a ponder search
+    pondering: bool,
+    stats: SearchStats,
+    trace: Option<SearchTrace>,
+    /// depth of the iteration being searched
+    root_depth: u8,
+    root_side: Color,
+    /// length of the game history before the root position
+    root_history: usize,
+    stack: Box<[StackEntry; MAX_PLY + 1]>,
+    /// root moves already reported as better lines in the current iteration
+    root_excluded: Vec<Move>,
+    /// move searched first at the root, the same line's move from the previous iteration
+    root_hint: Option<Move>,
+}
+
+impl Searcher {
+    pub fn new(board: Board, config: SearchConfig) -> Self {
+        Self::with_shared_state(board, config, SharedState::default(), 0)
+    }
+
+    /// creates a searcher for the given thread, thread 0 being the main thread
+    pub fn with_shared_state(
+        board: Board,
+        config: SearchConfig,
+        shared: SharedState,
+        thread_id: usize,
+    ) -> Self {
+        Self {
+            board,
+            config,
+            limits: SearchLimits::default(),
+            heuristics: Box::default(),
+            evaluator: Box::default(),
+            pv_table: Box::default(),
+            shared,
+            thread_id,
+            stopped: false,
+            nodes: 0,
+            seldepth: 0,
+            time: TimeManager::new(&SearchLimits::default(), Color::White, Duration::ZERO),
+            info_callback: None,
+            pondering: false,
+            stats: SearchStats::default(),
+            trace: None,
+            root_depth: 0,
+            root_side: Color::White,
+            root_history: 0,
+            stack: Box::new([StackEntry::default(); MAX_PLY + 1]),
+            root_excluded: Vec::new(),
+            root_hint: None,
+        }
+    }
+
+    pub fn thread_id(&self) -> usize {
+        self.thread_id
+    }
+
+    /// forgets the heuristics and cached evaluations from previous searches
+    pub fn clear_heuristics(&mut self) {
+        self.heuristics.clear();
+        self.evaluator.clear();
+    }
+
+    /// sets a function to be called with the results of every completed iteration
+    pub fn set_info_callback(&mut self, callback: InfoCallback) {
+        self.info_callback = Some(callback);
+    }
+
+    /// returns a flag that aborts the running search when set
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.shared.stop)
+    }
+
+    pub fn control(&self) -> SearchControl {
+        self.shared.control()
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// replaces the position to search, keeping the learned heuristics
+    pub fn set_board(&mut self, board: Board) {
+        self.board = board;
+    }
+
+    #[cfg(feature = "nnue")]
+    pub fn set_network(&mut self, network: Option<Arc<eval::nnue::Network>>) {
+        self.evaluator.set_network(network);
+    }
+
+    pub fn config_mut(&mut self) -> &mut SearchConfig {
+        &mut self.config
+    }
+
+    /// runs an iterative deepening search within the given limits
+    pub fn search(&mut self, limits: SearchLimits) -> SearchResult {
+        self.time = TimeManager::new(&limits, self.board.side_to_move, self.config.move_overhead);
+        self.pondering = limits.ponder;
+        self.limits = limits;
+        self.stopped = false;
+        self.nodes = 0;
+        self.seldepth = 0;
+        self.stats = SearchStats::default();
+        self.trace = self.config.trace.then(SearchTrace::default);
+        self.root_side = self.board.side_to_move;
+        self.root_history = self.board.past_states.len();
+        let params = eval::params::current();
+        if !Arc::ptr_eq(&self.board.psqt.params, &params) {
+            self.board.set_eval_params(params);
+        }
+        self.evaluator.reset(&self.board);
+        let legal = self.board.legal_moves();
+        self.limits.search_moves.retain(|m| legal.contains(m));
+        let legal_moves = if self.limits.search_moves.is_empty() {
+            legal.len()
+        } else {
+            self.limits.search_moves.len()
+        };
+
+        let max_depth = self.limits.depth.unwrap_or(MAX_PLY as u8 - 1).max(1);
+        let multi_pv = self.config.multi_pv.clamp(1, legal_moves.max(1));
+        let mut result = SearchResult {
+            best_move: None,
+            score: 0,
+            depth: 0,
+            nodes: 0,
+            pv: Vec::new(),
+            lines: Vec::new(),
+            stats: SearchStats::default(),
+            trace: None,
+        };
+
+        if legal_moves == 0 {
+            result.score = if self.board.in_check() { -MATE } else { 0 };
+            return result;
+        }
+
+        // a search stopped before its first iteration completes still has to name a move
+        result.best_move = legal
+            .iter()
+            .copied()
+            .find(|&m| self.is_root_move_allowed(m));
+        result.pv.extend(result.best_move);
+
+        for depth in 1..=max_depth {
+            if self.past_main_depth(depth) {
+                break;
+            }
+            if depth > 1 && depth < max_depth && smp::skip_depth(self.thread_id, depth) {
+                continue;
+            }
+
+            self.root_depth = depth;
+            self.root_excluded.clear();
+            let mut lines: Vec<PvLine> = Vec::with_capacity(multi_pv);
+            for pv_index in 0..multi_pv {
+                let previous = result.lines.get(pv_index);
+                self.root_hint = previous.and_then(|line| line.pv.first().copied());
+                let score = self.aspiration_search(depth, previous.map_or(0, |line| line.score));
+                let pv = self.pv_table.line(0);
+
+                // a partially searched line is only used if nothing better is available
+                if pv.is_empty() || (self.stopped && (result.depth > 0 || !lines.is_empty())) {
+                    break;
+                }
+
+                self.root_excluded.push(pv[0]);
+                lines.push(PvLine {
+                    depth,
+                    score,
+                    pv: pv.to_vec(),
+                });
+
+                if self.stopped {
+                    break;
+                }
+            }
+            self.root_excluded.clear();
+            self.root_hint = None;
+
+            if self.stopped && (result.depth > 0 || lines.is_empty()) {
+                break;
+            }
+
+            // stable, so lines with equal scores keep the order they were found in
+            lines.sort_by_key(|line| Reverse(line.score));
+            result.best_move = lines[0].pv.first().copied();
+            result.pv = lines[0].pv.clone();
+            result.score = lines[0].score;
+            result.depth = depth;
+            result.lines = lines;
+
+            if self.stopped {
+                break;
+            }
+
+            self.stats
+                .record_iteration(depth, self.nodes, self.time.elapsed());
+            if let Some(trace) = self.trace.as_mut() {
+                trace.iterations.push(TraceIteration {
+                    depth,
+                    score: result.score,
+                    nodes: self.nodes,
+                    elapsed: self.time.elapsed(),
+                    pv: result.pv.clone(),
+                });
+            }
+
+            if let Some(callback) = self.info_callback.as_mut() {
+                callback(&IterationInfo {
+                    depth,
+                    seldepth: self.seldepth,
+                    nodes: self.shared.nodes.load(Ordering::Relaxed)
+                        + self.nodes % STOP_CHECK_INTERVAL,
+                    elapsed: self.time.elapsed(),
+                    hashfull: self.shared.tt.hashfull(),
+                    lines: result.lines.clone(),
+                });
+            }
+
+            let out_of_time =
+                self.time
+                    .iteration_complete(result.best_move, result.score, legal_moves);
+            if self.thread_id == 0 && out_of_time && !self.check_pondering() {
+                break;
+            }
+            if self
+                .limits
+                .mate
+                .is_some_and(|moves| result.score >= MATE - (2 * moves as i32 - 1))
+            {
+                break;
+            }
+        }
+
+        // ponder and infinite searches may not return before they are told to
+        if self.thread_id == 0 {
+            self.shared
+                .main_depth
+                .store(result.depth.max(1), Ordering::Relaxed);
+            while (self.check_pondering() || self.limits.infinite)
+                && !self.shared.stop.load(Ordering::Relaxed)
+            {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+
+        self.shared
+            .nodes
+            .fetch_add(self.nodes % STOP_CHECK_INTERVAL, Ordering::Relaxed);
+        result.nodes = self.nodes;
+        self.stats.nodes = self.nodes;
+        result.stats = self.stats.clone();
+        result.trace = self.trace.take();
+        result
+    }
+
+    /// searches the root with a window around the previous score, widening it on failure
+    fn aspiration_search(&mut self, depth: u8, previous_score: i32) -> i32 {
+        if depth < ASPIRATION_DEPTH || previous_score.abs() >= MATE_BOUND {
+            return self.negamax(depth as i32, 0, -INFINITY, INFINITY, true);
+        }
+
+        let mut delta = ASPIRATION_WINDOW;
+        let mut alpha = (previous_score - delta).max(-INFINITY);
+        let mut beta = (previous_score + delta).min(INFINITY);
+
+        loop {
+            let score = self.negamax(depth as i32, 0, alpha, beta, true);
+            if self.stopped {
+                return score;
+            }
+
+            if score <= alpha && alpha > -INFINITY {
+                beta = (alpha + beta) / 2;
+                alpha = (score - delta).max(-INFINITY);
+            } else if score >= beta && beta < INFINITY {
+                beta = (score + delta).min(INFINITY);
+            } else {
+                return score;
+            }
+
+            delta += delta / 2;
+        }
+    }
+
+    /// returns true if the root move is neither reported already nor left out by the limits
+    fn is_root_move_allowed(&self, m: Move) -> bool {
+        !self.root_excluded.contains(&m)
+            && (self.limits.search_moves.is_empty() || self.limits.search_moves.contains(&m))
+    }
+
+    /// returns true if the root is searched without some of its moves
+    fn is_root_restricted(&self) -> bool {
+        !self.root_excluded.is_empty() || !self.limits.search_moves.is_empty()
+    }
+
+    /// returns true if the search has to be aborted
+    fn should_stop(&mut self) -> bool {
+        if self.stopped {
+            return true;
+        }
+        if !self.nodes.is_multiple_of(STOP_CHECK_INTERVAL) {
+            return false;
+        }
+
+        let total_nodes = self
+            .shared
+            .nodes
+            .fetch_add(STOP_CHECK_INTERVAL, Ordering::Relaxed)
+            + STOP_CHECK_INTERVAL;
+        let out_of_nodes = self.limits.nodes.is_some_and(|n| total_nodes >= n);
+        let out_of_time = !self.check_pondering() && self.time.hard_limit_reached();
+
+        if out_of_nodes
+            || out_of_time
+            || self.shared.stop.load(Ordering::Relaxed)
+            || self.past_main_depth(self.root_depth)
+        {
+            self.stopped = true;
+        }
+        self.stopped
+    }
+
+    /// returns true if this helper thread is at a depth the main thread will not search
+    fn past_main_depth(&self, depth: u8) -> bool {
+        let main_depth = self.shared.main_depth.load(Ordering::Relaxed);
+        self.thread_id != 0 && main_depth != 0 && depth > main_depth
+    }
+
+    /// returns true while pondering, restarting the clock once a ponderhit is noticed
+    fn check_pondering(&mut self) -> bool {
+        if self.pondering && !self.shared.pondering.load(Ordering::Relaxed) {
+            self.pondering = false;
+            self.time.ponderhit();
+        }
+        self.pondering
+    }
+
+    /// fail-soft principal variation search returning the score from the side to move's
+    /// perspective
+    fn negamax(
+        &mut self,
+        depth: i32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+        allow_null: bool,
+    ) -> i32 {
+        if depth <= 0 {
+            return self.quiescence(ply, alpha, beta);
+        }
+
+        self.nodes += 1;
+        self.seldepth = self.seldepth.max(ply);
+        self.pv_table.clear_ply(ply);
+        if ply > 0 && self.should_stop() {
+            return 0;
+        }
+        if ply >= MAX_PLY - 1 {
+            return self.evaluator.evaluate(&self.board);
+        }
+        if ply > 0 && self.is_draw() {
+            return self.draw_score();
+        }
+
+        let is_pv = beta - alpha > 1;
+        let original_alpha = alpha;
+        let hash = self.board.state.hash;
+        let excluded = self.stack[ply].excluded;
+        if ply == 0 {
+            self.stack[0].extensions = 0;
+        }
+
+        let tt_entry = self.shared.tt.probe(hash);
+        self.stats.tt_probes += 1;
+        if tt_entry.is_some() {
+            self.stats.tt_hits += 1;
+        }
+        let tt_move = match self.root_hint {
+            Some(hint) if ply == 0 => Some(hint),
+            _ => tt_entry.and_then(|entry| entry.best_move),
+        };
+        if let Some(entry) = tt_entry
+            && ply > 0
+            && !is_pv
+            && excluded.is_none()
+            && entry.depth as i32 >= depth
+        {
+            let score = tt::score_from_tt(entry.score, ply);
+            let usable = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if usable {
+                self.stats.tt_cutoffs += 1;
+                return score;
+            }
+        }
+
+        let in_check = self.board.in_check();
+        let side = self.board.side_to_move;
+        let static_eval = if in_check {
+            -INFINITY
+        } else {
+            self.evaluator.evaluate(&self.board)
+        };
+
+        if ply > 0 && !is_pv && !in_check {
+            if self.config.reverse_futility
+                && depth <= pruning::REVERSE_FUTILITY_DEPTH
+                && beta.abs() < MATE_BOUND
+                && static_eval - pruning::REVERSE_FUTILITY_MARGIN * depth >= beta
+            {
+                return static_eval;
+            }
+
+            if self.config.null_move
+                && allow_null
+                && excluded.is_none()
+                && depth >= pruning::NULL_MOVE_DEPTH
+                && static_eval >= beta
+            {
+                let null_depth = depth - 1 - pruning::null_move_reduction(depth);
+                self.stats.null_move_tries += 1;
+
+                self.board.make_null_move();
+                self.evaluator.make_null_move();
+                let score = -self.negamax(null_depth, ply + 1, -beta, -beta + 1, false);
+                self.evaluator.unmake_move();
+                self.board.unmake_null_move();
+
+                if self.stopped {
+                    return 0;
+                }
+
+                if score >= beta {
+                    let score = if score >= MATE_BOUND { beta } else { score };
+
+                    // pawn-only positions are prone to zugzwang, so confirm the cutoff with a
+                    // reduced search in which the side to move has to actually move
+                    if self.board.has_non_pawn_material(side) {
+                        self.stats.null_move_cutoffs += 1;
+                        return score;
+                    }
+                    let verified = self.negamax(null_depth, ply, beta - 1, beta, false);
+                    if verified >= beta {
+                        self.stats.null_move_cutoffs += 1;
+                        return score;
+                    }
+                }
+            }
+        }
+
+        let can_futility_prune = self.config.futility
+            && !is_pv
+            && !in_check
+            && (depth as usize) < pruning::FUTILITY_MARGINS.len()
+            && alpha.abs() < MATE_BOUND
+            && static_eval + pruning::FUTILITY_MARGINS[depth as usize] <= alpha;
+
+        // the hash move is singular if every alternative fails low against a bound below its
+        // score, and if even the alternatives beat beta the node can be cut right away
+        let mut singular_move = None;
+        if self.config.singular_extension
+            && ply > 0
+            && excluded.is_none()
+            && depth >= extensions::SINGULAR_DEPTH
+            && let Some(entry) = tt_entry
+            && let Some(hash_move) = entry.best_move
+            && entry.bound != Bound::Upper
+            && entry.depth as i32 >= depth - extensions::SINGULAR_TT_DEPTH_MARGIN
+        {
+            let tt_score = tt::score_from_tt(entry.score, ply);
+            if tt_score.abs() < MATE_BOUND {
+                let singular_beta = extensions::singular_beta(tt_score, depth);
+                self.stack[ply].excluded = Some(hash_move);
+                let score = self.negamax(
+                    extensions::singular_depth(depth),
+                    ply,
+                    singular_beta - 1,
+                    singular_beta,
+                    false,
+                );
+                self.stack[ply].excluded = None;
+                self.pv_table.clear_ply(ply);
+
+                if self.stopped {
+                    return 0;
+                }
+                if score < singular_beta {
+                    singular_move = Some(hash_move);
+                } else if singular_beta >= beta {
+                    return singular_beta;
+                }
+            }
+        }
+
+        let mut moves = self.board.generate_moves();
+        let mut scores = ordering::score_moves(&self.board, &moves, &self.heuristics, ply, tt_move);
+
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        let mut legal_moves = 0;
+        let mut quiets_searched: Vec<Move> = Vec::new();
+
+        for i in 0..moves.len() {
+            ordering::pick_move(&mut moves, &mut scores, i);
+            let m = moves[i];
+            let is_quiet = !m.is_capture() && !m.is_promotion();
+
+            if ply == 0 && !self.is_root_move_allowed(m) {
+                continue;
+            }
+            if excluded == Some(m) {
+                continue;
+            }
+
+            if !self.make_move(m) {
+                continue;
+            }
+            let gives_check = self.board.in_check();
+
+            // quiet moves that give check may be the only way through, so they are never pruned
+            if is_quiet && !gives_check && legal_moves > 0 && !in_check && best_score > -MATE_BOUND
+            {
+                let late = self.config.late_move_pruning
+                    && !is_pv
+                    && depth <= pruning::LATE_MOVE_PRUNING_DEPTH
+                    && quiets_searched.len() >= pruning::late_move_pruning_threshold(depth);
+                if late || can_futility_prune {
+                    self.unmake_move(m);
+                    continue;
+                }
+            }
+            legal_moves += 1;
+
+            let mut extension = 0;
+            if extensions::within_budget(self.stack[ply].extensions, self.root_depth) {
+                let previous = if ply > 0 {
+                    self.stack[ply - 1].current_move
+                } else {
+                    None
+                };
+                if (self.config.check_extension && gives_check)
+                    || singular_move == Some(m)
+                    || (self.config.recapture_extension && extensions::is_recapture(m, previous))
+                {
+                    extension = 1;
+                }
+            }
+            self.stack[ply].current_move = Some(m);
+            self.stack[ply + 1].extensions = self.stack[ply].extensions + extension;
+            debug_assert!(self.stack[ply + 1].extensions <= self.root_depth as i32);
+            self.stack[ply + 1].excluded = None;
+            let new_depth = depth - 1 + extension;
+
+            let mut reduction = 0;
+            if self.config.late_move_reductions
+                && depth >= pruning::LMR_DEPTH
+                && legal_moves > 1 + is_pv as usize
+                && is_quiet
+                && !in_check
+                && !gives_check
+            {
+                reduction = pruning::lmr_reduction(depth, legal_moves);
+                if is_pv {
+                    reduction -= 1;
+                }
+                if self.heuristics.is_killer(m, ply) {
+                    reduction -= 1;
+                }
+                reduction = reduction.clamp(0, depth - 2);
+            }
+
+            // the first move is searched with the full window, the rest are scouted with a null
+            // window and only re-searched if they might raise alpha
+            let mut score;
+            if legal_moves == 1 {
+                score = -self.negamax(new_depth, ply + 1, -beta, -alpha, true);
+            } else {
+                score = -self.negamax(new_depth - reduction, ply + 1, -alpha - 1, -alpha, true);
+                if reduction > 0 {
+                    self.stats.lmr_searches += 1;
+                    if score > alpha {
+                        self.stats.lmr_researches += 1;
+                        score = -self.negamax(new_depth, ply + 1, -alpha - 1, -alpha, true);
+                    }
+                }
+                if is_pv && score > alpha && score < beta {
+                    score = -self.negamax(new_depth, ply + 1, -beta, -alpha, true);
+                }
+            }
+
+            self.unmake_move(m);
+
+            if self.stopped {
+                return 0;
+            }
+
+            if is_quiet {
+                quiets_searched.push(m);
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_move = Some(m);
+            }
+
+            if score > alpha {
+                alpha = score;
+                self.pv_table.update(ply, m);
+                if ply == 0
+                    && let Some(trace) = self.trace.as_mut()
+                {
+                    trace.pv_changes.push(PvChange {
+                        depth: self.root_depth,
+                        elapsed: self.time.elapsed(),
+                        score,
+                        pv: self.pv_table.line(0).to_vec(),
+                    });
+                }
+            }
+
+            if alpha >= beta {
+                self.stats.beta_cutoffs += 1;
+                if legal_moves == 1 {
+                    self.stats.first_move_cutoffs += 1;
+                }
+                if is_quiet {
+                    self.heuristics.update_killer(m, ply);
+                    let bonus = depth * depth;
+                    for &quiet in &quiets_searched {
+                        let delta = if quiet == m { bonus } else { -bonus };
+                        self.heuristics.update_history(side, quiet, delta);
+                    }
+                }
+                break;
+            }
+        }
+
+        // without the excluded move there may be nothing left, which makes it singular
+        if legal_moves == 0 && excluded.is_some() {
+            return alpha;
+        }
+
+        // moves are only pruned after a legal move was found, so this is checkmate or stalemate
+        if legal_moves == 0 {
+            if ply == 0 && self.is_root_restricted() {
+                return -INFINITY;
+            }
+            return if in_check { -MATE + ply as i32 } else { 0 };
+        }
+
+        // a node searched without some of its moves does not have its real score
+        if excluded.is_some() || (ply == 0 && self.is_root_restricted()) {
+            return best_score;
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.shared.tt.store(
+            hash,
+            best_move,
+            tt::score_to_tt(best_score, ply),
+            static_eval,
+            depth as u8,
+            bound,
+        );
+
+        best_score
+    }
+
+    /// makes the move on the board and the evaluator, returning false if it is illegal
+    fn make_move(&mut self, m: Move) -> bool {
+        if self.board.make_move(m).is_err() {
+            return false;
+        }
+        self.evaluator.make_move(&self.board, m);
+        true
+    }
+
+    fn unmake_move(&mut self, m: Move) {
+        self.evaluator.unmake_move();
+        self.board.unmake_move(m);
+    }
+
+    /// returns true if the position is drawn by the fifty move rule or by repetition, a
+    /// single repetition inside the search tree is enough while positions from the game
+    /// history have to occur twice
+    fn is_draw(&mut self) -> bool {
+        if self.board.is_fifty_move_draw()
+            && !(self.board.in_check() && self.board.legal_moves().is_empty())
+        {
+            return true;
+        }
+        self.board.repetitions_since(self.root_history) > 0 || self.board.repetitions() > 1
+    }
+
+    /// score of a draw from the side to move's view, contempt makes the root side avoid it
+    fn draw_score(&self) -> i32 {
+        if self.board.side_to_move == self.root_side {
+            -self.config.contempt
+        } else {
+            self.config.contempt
+        }
+    }
+
+    /// searches captures and promotions until the position is quiet
+    fn quiescence(&mut self, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.nodes += 1;
+        self.seldepth = self.seldepth.max(ply);
+        self.stats.qnodes += 1;
+        self.pv_table.clear_ply(ply);
+        if self.should_stop() {
+            return 0;
+        }
+
+        let in_check = self.board.in_check();
+        if ply >= MAX_PLY - 1 {
+            return if in_check {
+                0
+            } else {
+                self.evaluator.evaluate(&self.board)
+            };
+        }
+
+        let mut best_score = -INFINITY;
+        if !in_check {
+            let stand_pat = self.evaluator.evaluate(&self.board);
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            if stand_pat > alpha {
+                alpha = stand_pat;
+            }
+            best_score = stand_pat;
+        }
+
+        let mut moves = self.board.generate_moves();
+        if !in_check {
+            moves.retain(|m| m.is_capture() || m.promotion_piece() == Some(GenericPiece::Queen));
+        }
+        let mut scores = ordering::score_moves(&self.board, &moves, &self.heuristics, ply, None);
+
+        let mut legal_moves = 0;
+        for i in 0..moves.len() {
+            ordering::pick_move(&mut moves, &mut scores, i);
+            let m = moves[i];
+
+            if !self.make_move(m) {
+                continue;
+            }
+            legal_moves += 1;
+            let score = -self.quiescence(ply + 1, -beta, -alpha);
+            self.unmake_move(m);
+
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+            }
+            if score > alpha {
+                alpha = score;
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        if in_check && legal_moves == 0 {
+            return -MATE + ply as i32;
+        }
+
+        best_score
+    }
+}
+
+/// searches the given position on a single thread with the default configuration
+pub fn search(board: &Board, limits: SearchLimits) -> SearchResult {
+    let mut searcher = Searcher::new(board.clone(), SearchConfig::default());
+    searcher.search(limits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::fen::fen_to_board;
+
+    fn search_fen(fen: &str, depth: u8) -> SearchResult {
+        crate::state::init();
+        let limits = SearchLimits {
+            depth: Some(depth),
+            ..Default::default()
+        };
+        search(&fen_to_board(fen).unwrap(), limits)
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        let result = search_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 3);
+        assert_eq!(result.best_move.unwrap().to_uci(), "a1a8");
+        assert_eq!(result.score, MATE - 1);
+    }
+
+    #[test]
+    fn finds_mate_in_two() {
+        let result = search_fen("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1", 5);
+        assert_eq!(result.best_move.unwrap().to_uci(), "a1a6");
+        assert_eq!(result.score, MATE - 3);
+    }
+
+    #[test]
+    fn pv_starts_with_the_best_move() {
+        let result = search_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            5,
+        );
+        assert_eq!(result.depth, 5);
+        assert_eq!(result.pv.first().copied(), result.best_move);
+        assert_eq!(result.lines[0].pv, result.pv);
+    }
+
+    #[test]
+    fn search_moves_restrict_the_root() {
+        crate::state::init();
+        let mut board = fen_to_board("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let king_move = board
+            .legal_moves()
+            .into_iter()
+            .find(|m| m.to_uci() == "g1f1")
+            .unwrap();
+        let limits = SearchLimits {
+            depth: Some(3),
+            search_moves: vec![king_move],
+            ..Default::default()
+        };
+        assert_eq!(search(&board, limits).best_move, Some(king_move));
+    }
+
+    #[test]
+    fn multi_pv_reports_distinct_moves_in_score_order() {
+        crate::state::init();
+        let config = SearchConfig {
+            multi_pv: 3,
+            ..Default::default()
+        };
+        let board = fen_to_board("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mut searcher = Searcher::new(board, config);
+        let limits = SearchLimits {
+            depth: Some(4),
+            ..Default::default()
+        };
+        let result = searcher.search(limits);
+
+        assert_eq!(result.lines.len(), 3);
+        let firsts: Vec<Move> = result.lines.iter().map(|line| line.pv[0]).collect();
+        assert!(firsts[0] != firsts[1] && firsts[0] != firsts[2] && firsts[1] != firsts[2]);
+        assert!(result.lines.windows(2).all(|w| w[0].score >= w[1].score));
+        // only the back rank mate mates, the other lines are ordinary scores
+        assert_eq!(firsts[0].to_uci(), "a1a8");
+        assert_eq!(result.lines[0].score, MATE - 1);
+        assert!(result.lines[1].score < MATE_BOUND);
+        assert_eq!(result.best_move, Some(firsts[0]));
+    }
+
+    #[test]
+    fn ponder_search_waits_for_ponderhit_then_keeps_to_its_time() {
+        crate::state::init();
+        let mut pool = smp::SearchPool::new(1, 1);
+        let control = pool.control();
+        let board = Board::default_setup();
+        let limits = SearchLimits {
+            ponder: true,
+            movetime: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let search = std::thread::spawn(move || pool.search(&board, limits));
+
+        // the time limit does not apply while pondering
+        std::thread::sleep(Duration::from_millis(300));
+        assert!(control.is_pondering());
+        assert!(!search.is_finished());
+
+        let ponderhit = std::time::Instant::now();
+        control.ponderhit();
+        assert!(!control.is_pondering());
+        let result = search.join().unwrap();
+        assert!(ponderhit.elapsed() < Duration::from_millis(250));
+        assert!(result.best_move.is_some());
+    }
+
+    #[test]
+    fn ponder_miss_stops_the_search() {
+        crate::state::init();
+        let mut pool = smp::SearchPool::new(1, 1);
+        let control = pool.control();
+        let board = Board::default_setup();
+        let limits = SearchLimits {
+            ponder: true,
+            ..Default::default()
+        };
+        let search = std::thread::spawn(move || pool.search(&board, limits));
+        std::thread::sleep(Duration::from_millis(50));
+        while !search.is_finished() {
+            control.stop();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(search.join().unwrap().best_move.is_some());
+    }
+}
@@ -0,0 +1,71 @@
+use crate::state::board_move_gen::Move;
+
+/// shallowest remaining depth at which singular extensions are tried
+pub const SINGULAR_DEPTH: i32 = 8;
+/// how much shallower than the current depth the hash entry may be for a singular search
+pub const SINGULAR_TT_DEPTH_MARGIN: i32 = 3;
+
+/// returns the bound the alternatives to the hash move have to stay below for it to be singular
+pub fn singular_beta(tt_score: i32, depth: i32) -> i32 {
+    tt_score - 2 * depth
+}
+
+/// returns the depth of the exclusion search verifying a singular move
+pub fn singular_depth(depth: i32) -> i32 {
+    (depth - 1) / 2
+}
+
+/// returns true if the move recaptures on the square the previous move captured on
+pub fn is_recapture(m: Move, previous: Option<Move>) -> bool {
+    previous.is_some_and(|p| p.is_capture() && m.is_capture() && p.extract_to() == m.extract_to())
+}
+
+/// returns true if the line may be extended further, allowing at most as many extensions as
+/// the depth of the current iteration
+pub fn within_budget(extensions: i32, root_depth: u8) -> bool {
+    extensions < root_depth as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::{SearchConfig, SearchLimits, Searcher};
+    use crate::state::board_move_gen::move_flags;
+    use crate::state::fen::fen_to_board;
+
+    #[test]
+    fn budget_is_the_root_depth() {
+        assert!(within_budget(0, 1));
+        assert!(within_budget(4, 5));
+        assert!(!within_budget(5, 5));
+        assert!(!within_budget(6, 5));
+    }
+
+    #[test]
+    fn recapture_needs_two_captures_on_one_square() {
+        let capture = |from, to| Move::from_parts(from, to, move_flags::CAPTURE);
+        let quiet = Move::from_parts(12, 28, move_flags::QUIET);
+        assert!(is_recapture(capture(35, 28), Some(capture(21, 28))));
+        assert!(!is_recapture(capture(35, 28), Some(capture(21, 29))));
+        assert!(!is_recapture(capture(35, 28), Some(quiet)));
+        assert!(!is_recapture(capture(35, 28), None));
+    }
+
+    #[test]
+    fn checking_lines_stay_within_the_budget() {
+        crate::state::init();
+        // the queens can check back and forth, so every line is extended as far as allowed
+        let board = fen_to_board("6k1/5q2/8/8/8/8/5Q2/6K1 w - - 0 1").unwrap();
+        let config = SearchConfig {
+            recapture_extension: true,
+            ..Default::default()
+        };
+        let limits = SearchLimits {
+            depth: Some(6),
+            ..Default::default()
+        };
+        let result = Searcher::new(board, config).search(limits);
+        assert_eq!(result.depth, 6);
+        assert!(result.best_move.is_some());
+    }
+}
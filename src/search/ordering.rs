@@ -0,0 +1,113 @@
+use crate::eval;
+use crate::state::{Color, GenericPiece, board::Board, board_move_gen::Move};
+
+use super::MAX_PLY;
+
+const CAPTURE_BONUS: i32 = 1_000_000;
+const PROMOTION_BONUS: i32 = 900_000;
+const FIRST_KILLER_BONUS: i32 = 800_000;
+const SECOND_KILLER_BONUS: i32 = 799_000;
+const HISTORY_MAX: i32 = 16_384;
+
+/// quiet move heuristics learned during search
+#[derive(Clone)]
+pub struct Heuristics {
+    killers: [[Move; 2]; MAX_PLY],
+    history: [[[i32; 64]; 64]; 2],
+}
+
+impl Heuristics {
+    pub fn new() -> Self {
+        Self {
+            killers: [[Move::NULL; 2]; MAX_PLY],
+            history: [[[0; 64]; 64]; 2],
+        }
+    }
+
+    /// forgets all killers and history scores
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn is_killer(&self, m: Move, ply: usize) -> bool {
+        self.killers[ply].contains(&m)
+    }
+
+    /// records a quiet move that caused a beta cutoff at the given ply
+    pub fn update_killer(&mut self, m: Move, ply: usize) {
+        if self.killers[ply][0] != m {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = m;
+        }
+    }
+
+    /// adjusts the history score of a quiet move by the given bonus (negative for a malus)
+    pub fn update_history(&mut self, side: Color, m: Move, bonus: i32) {
+        let entry =
+            &mut self.history[side as usize][m.extract_from() as usize][m.extract_to() as usize];
+        let bonus = bonus.clamp(-HISTORY_MAX, HISTORY_MAX);
+        // gravity keeps scores bounded by HISTORY_MAX
+        *entry += bonus - *entry * bonus.abs() / HISTORY_MAX;
+    }
+
+    pub fn history_score(&self, side: Color, m: Move) -> i32 {
+        self.history[side as usize][m.extract_from() as usize][m.extract_to() as usize]
+    }
+}
+
+impl Default for Heuristics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// returns the most valuable victim / least valuable attacker score of a capture
+pub fn mvv_lva(board: &Board, m: Move) -> i32 {
+    let victim = if m.is_capture() {
+        board.piece_table[m.extract_to() as usize].unwrap_or(GenericPiece::Pawn)
+    } else {
+        return 0;
+    };
+    let attacker = board.piece_table[m.extract_from() as usize].unwrap_or(GenericPiece::Pawn);
+    eval::piece_value(victim) * 16 - eval::piece_value(attacker) / 16
+}
+
+/// assigns an ordering score to each move, higher scores are searched first
+pub fn score_moves(
+    board: &Board,
+    moves: &[Move],
+    heuristics: &Heuristics,
+    ply: usize,
+    hash_move: Option<Move>,
+) -> Vec<i32> {
+    moves
+        .iter()
+        .map(|&m| {
+            if Some(m) == hash_move {
+                i32::MAX
+            } else if m.is_capture() {
+                CAPTURE_BONUS + mvv_lva(board, m)
+            } else if m.promotion_piece() == Some(GenericPiece::Queen) {
+                PROMOTION_BONUS
+            } else if heuristics.killers[ply][0] == m {
+                FIRST_KILLER_BONUS
+            } else if heuristics.killers[ply][1] == m {
+                SECOND_KILLER_BONUS
+            } else {
+                heuristics.history_score(board.side_to_move, m)
+            }
+        })
+        .collect()
+}
+
+/// swaps the highest scored move at or after index into index (lazy selection sort)
+pub fn pick_move(moves: &mut [Move], scores: &mut [i32], index: usize) {
+    let mut best = index;
+    for i in (index + 1)..moves.len() {
+        if scores[i] > scores[best] {
+            best = i;
+        }
+    }
+    moves.swap(index, best);
+    scores.swap(index, best);
+}
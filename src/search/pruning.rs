@@ -0,0 +1,50 @@
+use std::sync::OnceLock;
+
+use super::MAX_PLY;
+
+/// largest move number with its own entry in the reduction table
+const MAX_MOVES: usize = 64;
+
+static LMR_TABLE: OnceLock<[[u8; MAX_MOVES]; MAX_PLY]> = OnceLock::new();
+
+/// margin per ply of depth for reverse futility pruning
+pub const REVERSE_FUTILITY_MARGIN: i32 = 80;
+/// deepest remaining depth at which reverse futility pruning is tried
+pub const REVERSE_FUTILITY_DEPTH: i32 = 6;
+/// futility margins indexed by remaining depth
+pub const FUTILITY_MARGINS: [i32; 4] = [0, 120, 220, 340];
+/// deepest remaining depth at which late move pruning is tried
+pub const LATE_MOVE_PRUNING_DEPTH: i32 = 4;
+/// shallowest remaining depth at which null moves are tried
+pub const NULL_MOVE_DEPTH: i32 = 3;
+/// shallowest remaining depth at which late moves are reduced
+pub const LMR_DEPTH: i32 = 3;
+
+fn init_lmr_table() -> [[u8; MAX_MOVES]; MAX_PLY] {
+    let mut table = [[0; MAX_MOVES]; MAX_PLY];
+    for (depth, row) in table.iter_mut().enumerate().skip(1) {
+        for (move_number, reduction) in row.iter_mut().enumerate().skip(1) {
+            let r = 0.75 + (depth as f64).ln() * (move_number as f64).ln() / 2.25;
+            *reduction = r as u8;
+        }
+    }
+    table
+}
+
+/// returns the logarithmic late move reduction for the given depth and move number
+pub fn lmr_reduction(depth: i32, move_number: usize) -> i32 {
+    let table = LMR_TABLE.get_or_init(init_lmr_table);
+    let depth = (depth as usize).min(MAX_PLY - 1);
+    let move_number = move_number.min(MAX_MOVES - 1);
+    table[depth][move_number] as i32
+}
+
+/// returns the number of quiet moves searched before the remaining ones are pruned
+pub fn late_move_pruning_threshold(depth: i32) -> usize {
+    (3 + depth * depth) as usize
+}
+
+/// returns the null move depth reduction for the given remaining depth
+pub fn null_move_reduction(depth: i32) -> i32 {
+    3 + depth / 6
+}
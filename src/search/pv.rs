@@ -0,0 +1,73 @@
+use crate::state::board_move_gen::Move;
+
+use super::MAX_PLY;
+
+/// triangular table holding the principal variation from every ply of the current search
+#[derive(Clone)]
+pub struct PvTable {
+    moves: [[Move; MAX_PLY]; MAX_PLY],
+    lengths: [usize; MAX_PLY],
+}
+
+impl PvTable {
+    pub fn new() -> Self {
+        Self {
+            moves: [[Move::NULL; MAX_PLY]; MAX_PLY],
+            lengths: [0; MAX_PLY],
+        }
+    }
+
+    /// empties the line starting at the given ply, called when a node is entered
+    pub fn clear_ply(&mut self, ply: usize) {
+        self.lengths[ply] = 0;
+    }
+
+    /// sets the line at ply to the given move followed by the line found at ply + 1
+    pub fn update(&mut self, ply: usize, m: Move) {
+        let child_len = if ply + 1 < MAX_PLY {
+            self.lengths[ply + 1]
+        } else {
+            0
+        };
+        let child_len = child_len.min(MAX_PLY - ply - 1);
+
+        let (head, tail) = self.moves.split_at_mut(ply + 1);
+        head[ply][0] = m;
+        if child_len > 0 {
+            head[ply][1..=child_len].copy_from_slice(&tail[0][..child_len]);
+        }
+        self.lengths[ply] = child_len + 1;
+    }
+
+    /// returns the principal variation starting at the given ply
+    pub fn line(&self, ply: usize) -> &[Move] {
+        &self.moves[ply][..self.lengths[ply]]
+    }
+}
+
+impl Default for PvTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_prepends_to_the_child_line() {
+        let [a, b, c] = [1, 2, 3].map(Move::from_data);
+        let mut table = PvTable::new();
+        table.clear_ply(2);
+        table.update(2, c);
+        table.update(1, b);
+        table.update(0, a);
+        assert_eq!(table.line(0), [a, b, c]);
+        assert_eq!(table.line(1), [b, c]);
+
+        table.clear_ply(1);
+        table.update(0, b);
+        assert_eq!(table.line(0), [b]);
+    }
+}
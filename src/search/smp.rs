@@ -0,0 +1,278 @@
+use std::sync::{Arc, atomic::AtomicBool, atomic::Ordering};
+use std::thread;
+
+use crate::state::{board::Board, board_move_gen::Move};
+
+use super::tt::TranspositionTable;
+use super::{
+    InfoCallback, SearchConfig, SearchControl, SearchLimits, SearchResult, Searcher, SharedState,
+};
+
+/// iteration skipping pattern for helper threads, so that they spread over neighbouring depths
+const SKIP_SIZE: [u8; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE: [u8; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
+/// returns true if the given helper thread should skip the iteration at depth
+pub fn skip_depth(thread_id: usize, depth: u8) -> bool {
+    if thread_id == 0 {
+        return false;
+    }
+    let index = (thread_id - 1) % SKIP_SIZE.len();
+    ((depth + SKIP_PHASE[index]) / SKIP_SIZE[index]) & 1 != 0
+}
+
+/// lazy SMP search: every thread searches the same position on its own board with its own
+/// heuristics, and the threads only share work through the transposition table
+pub struct SearchPool {
+    shared: SharedState,
+    searchers: Vec<Searcher>,
+    config: SearchConfig,
+    /// network given to every searcher, including those added later
+    #[cfg(feature = "nnue")]
+    network: Option<Arc<crate::eval::nnue::Network>>,
+}
+
+impl SearchPool {
+    pub fn new(threads: usize, hash_mb: usize) -> Self {
+        let shared = SharedState {
+            tt: Arc::new(TranspositionTable::new(hash_mb)),
+            ..SharedState::default()
+        };
+        let mut pool = Self {
+            shared,
+            searchers: Vec::new(),
+            config: SearchConfig::default(),
+            #[cfg(feature = "nnue")]
+            network: None,
+        };
+        pool.set_threads(threads);
+        pool
+    }
+
+    pub fn threads(&self) -> usize {
+        self.searchers.len()
+    }
+
+    /// changes the number of search threads, keeping the heuristics of the remaining ones
+    pub fn set_threads(&mut self, threads: usize) {
+        let threads = threads.max(1);
+        self.searchers.truncate(threads);
+        while self.searchers.len() < threads {
+            let thread_id = self.searchers.len();
+            #[cfg_attr(not(feature = "nnue"), allow(unused_mut))]
+            let mut searcher = Searcher::with_shared_state(
+                Board::empty(),
+                self.config.clone(),
+                self.shared.clone(),
+                thread_id,
+            );
+            #[cfg(feature = "nnue")]
+            searcher.set_network(self.network.clone());
+            self.searchers.push(searcher);
+        }
+    }
+
+    /// replaces the transposition table with an empty one of the given size
+    pub fn resize_hash(&mut self, hash_mb: usize) {
+        self.shared.tt = Arc::new(TranspositionTable::new(hash_mb));
+        for searcher in &mut self.searchers {
+            searcher.shared = self.shared.clone();
+        }
+    }
+
+    /// forgets everything learned in previous searches
+    pub fn clear(&mut self) {
+        self.shared.tt.clear();
+        for searcher in &mut self.searchers {
+            searcher.clear_heuristics();
+        }
+    }
+
+    pub fn tt(&self) -> &TranspositionTable {
+        &self.shared.tt
+    }
+
+    /// returns a flag that aborts the running search when set
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.shared.stop)
+    }
+
+    /// returns a handle to stop the search or signal a ponderhit from another thread
+    pub fn control(&self) -> SearchControl {
+        self.shared.control()
+    }
+
+    /// sets the function receiving the main thread's iteration reports
+    pub fn set_info_callback(&mut self, callback: InfoCallback) {
+        self.searchers[0].set_info_callback(callback);
+    }
+
+    /// evaluates with the given network on every thread, or with the hand written evaluation
+    #[cfg(feature = "nnue")]
+    pub fn set_network(&mut self, network: Option<Arc<crate::eval::nnue::Network>>) {
+        for searcher in &mut self.searchers {
+            searcher.set_network(network.clone());
+        }
+        self.network = network;
+    }
+
+    pub fn config(&self) -> &SearchConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: SearchConfig) {
+        for searcher in &mut self.searchers {
+            *searcher.config_mut() = config.clone();
+        }
+        self.config = config;
+    }
+
+    /// searches the given position on every thread and returns the most trustworthy result
+    pub fn search(&mut self, board: &Board, limits: SearchLimits) -> SearchResult {
+        self.shared.stop.store(false, Ordering::Relaxed);
+        self.shared
+            .pondering
+            .store(limits.ponder, Ordering::Relaxed);
+        self.shared.nodes.store(0, Ordering::Relaxed);
+        self.shared.main_depth.store(0, Ordering::Relaxed);
+        self.shared.tt.new_search();
+        for searcher in &mut self.searchers {
+            searcher.set_board(board.clone());
+        }
+
+        let stop = Arc::clone(&self.shared.stop);
+        let (main, helpers) = self
+            .searchers
+            .split_first_mut()
+            .expect("search pool has no threads");
+
+        let mut results = thread::scope(|scope| {
+            let handles: Vec<_> = helpers
+                .iter_mut()
+                .map(|helper| {
+                    let limits = limits.clone();
+                    scope.spawn(move || helper.search(limits))
+                })
+                .collect();
+
+            let mut results = vec![main.search(limits.clone())];
+            // helpers keep deepening until told otherwise
+            stop.store(true, Ordering::Relaxed);
+            results.extend(
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("search thread panicked")),
+            );
+            results
+        });
+
+        // statistics cover all threads, iterations and the trace come from the main thread
+        let mut stats = results[0].stats.clone();
+        let trace = results[0].trace.take();
+        for result in &results[1..] {
+            stats.merge(&result.stats);
+        }
+
+        // the lines of a MultiPV search only make sense together, so they come from one thread
+        let mut best = if self.config.multi_pv > 1 {
+            results.swap_remove(0)
+        } else {
+            select_best(results)
+        };
+        best.nodes = self.shared.nodes.load(Ordering::Relaxed);
+        best.stats = stats;
+        best.trace = trace;
+        best
+    }
+
+    /// returns the move to ponder on after the given result, falling back to the
+    /// transposition table when the principal variation ends after the best move
+    pub fn ponder_move(&self, board: &Board, result: &SearchResult) -> Option<Move> {
+        if let Some(m) = result.ponder_move() {
+            return Some(m);
+        }
+        let best = result.best_move?;
+        let mut board = board.clone();
+        board.make_move(best).ok()?;
+        let reply = self.shared.tt.probe(board.state.hash)?.best_move?;
+        board.legal_moves().contains(&reply).then_some(reply)
+    }
+}
+
+/// picks the result of the thread that completed the deepest iteration, preferring higher
+/// scores between threads that reached the same depth
+fn select_best(results: Vec<SearchResult>) -> SearchResult {
+    let mut results = results.into_iter();
+    let mut best = results.next().expect("no search results");
+    for result in results {
+        if result.best_move.is_none() {
+            continue;
+        }
+        let deeper = result.depth > best.depth;
+        let better = result.depth == best.depth && result.score > best.score;
+        if best.best_move.is_none() || deeper || better {
+            best = result;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::stats::SearchStats;
+
+    fn result(data: u16, depth: u8, score: i32) -> SearchResult {
+        let m = Move::from_data(data);
+        SearchResult {
+            best_move: Some(m),
+            score,
+            depth,
+            nodes: 0,
+            pv: vec![m],
+            lines: Vec::new(),
+            stats: SearchStats::default(),
+            trace: None,
+        }
+    }
+
+    fn selected(results: Vec<SearchResult>) -> u16 {
+        select_best(results).best_move.unwrap().data()
+    }
+
+    #[test]
+    fn prefers_the_deepest_iteration() {
+        // the deeper thread found a refutation of the shallower thread's move
+        assert_eq!(selected(vec![result(1, 8, 50), result(2, 10, -30)]), 2);
+        assert_eq!(selected(vec![result(1, 10, -30), result(2, 8, 50)]), 1);
+    }
+
+    #[test]
+    fn breaks_ties_by_score() {
+        let results = vec![result(1, 9, 10), result(2, 9, 40), result(3, 9, 20)];
+        assert_eq!(selected(results), 2);
+        assert_eq!(selected(vec![result(1, 9, 10), result(2, 9, 10)]), 1);
+    }
+
+    #[test]
+    fn ignores_threads_without_a_move() {
+        let mut empty = result(2, 12, 0);
+        empty.best_move = None;
+        assert_eq!(selected(vec![result(1, 6, 0), empty]), 1);
+    }
+
+    #[test]
+    fn helpers_stop_at_the_main_thread_depth() {
+        crate::state::init();
+        let shared = SharedState::default();
+        // the main thread has stopped deepening after depth 3 and waits for a stop
+        shared.main_depth.store(3, Ordering::Relaxed);
+        let board = Board::default_setup();
+        let mut helper = Searcher::with_shared_state(board, SearchConfig::default(), shared, 1);
+        let limits = SearchLimits {
+            infinite: true,
+            ..Default::default()
+        };
+        assert!(helper.search(limits).depth <= 3);
+    }
+}
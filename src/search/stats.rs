@@ -0,0 +1,340 @@
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::state::board_move_gen::Move;
+
+/// counters describing how a search behaved
+#[derive(Debug, Clone, Default)]
+pub struct SearchStats {
+    /// all nodes, including quiescence nodes
+    pub nodes: u64,
+    pub qnodes: u64,
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+    pub tt_cutoffs: u64,
+    pub beta_cutoffs: u64,
+    pub first_move_cutoffs: u64,
+    pub null_move_tries: u64,
+    pub null_move_cutoffs: u64,
+    pub lmr_searches: u64,
+    pub lmr_researches: u64,
+    /// one entry per completed iteration of the main thread
+    pub iterations: Vec<IterationStats>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IterationStats {
+    pub depth: u8,
+    /// nodes searched by this iteration alone
+    pub nodes: u64,
+    pub elapsed: Duration,
+    /// nodes of this iteration divided by nodes of the previous one
+    pub branching_factor: Option<f64>,
+}
+
+fn ratio(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64
+    }
+}
+
+impl SearchStats {
+    /// share of beta cutoffs produced by the first move searched
+    pub fn first_move_cutoff_rate(&self) -> f64 {
+        ratio(self.first_move_cutoffs, self.beta_cutoffs)
+    }
+
+    /// share of null move searches that failed high
+    pub fn null_move_success_rate(&self) -> f64 {
+        ratio(self.null_move_cutoffs, self.null_move_tries)
+    }
+
+    /// share of reduced searches that had to be repeated at full depth
+    pub fn lmr_research_rate(&self) -> f64 {
+        ratio(self.lmr_researches, self.lmr_searches)
+    }
+
+    pub fn tt_hit_rate(&self) -> f64 {
+        ratio(self.tt_hits, self.tt_probes)
+    }
+
+    /// records a completed iteration that ended after total_nodes nodes
+    pub fn record_iteration(&mut self, depth: u8, total_nodes: u64, elapsed: Duration) {
+        let previous_total: u64 = self.iterations.iter().map(|i| i.nodes).sum();
+        let nodes = total_nodes.saturating_sub(previous_total);
+        let branching_factor = self
+            .iterations
+            .last()
+            .filter(|last| last.nodes > 0)
+            .map(|last| nodes as f64 / last.nodes as f64);
+        self.iterations.push(IterationStats {
+            depth,
+            nodes,
+            elapsed,
+            branching_factor,
+        });
+    }
+
+    /// adds the counters of another thread, keeping this thread's iterations
+    pub fn merge(&mut self, other: &SearchStats) {
+        self.nodes += other.nodes;
+        self.qnodes += other.qnodes;
+        self.tt_probes += other.tt_probes;
+        self.tt_hits += other.tt_hits;
+        self.tt_cutoffs += other.tt_cutoffs;
+        self.beta_cutoffs += other.beta_cutoffs;
+        self.first_move_cutoffs += other.first_move_cutoffs;
+        self.null_move_tries += other.null_move_tries;
+        self.null_move_cutoffs += other.null_move_cutoffs;
+        self.lmr_searches += other.lmr_searches;
+        self.lmr_researches += other.lmr_researches;
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        write!(
+            out,
+            "{{\"nodes\":{},\"qnodes\":{},\"tt_probes\":{},\"tt_hits\":{},\"tt_cutoffs\":{},\
+             \"beta_cutoffs\":{},\"first_move_cutoff_rate\":{:.4},\"null_move_tries\":{},\
+             \"null_move_success_rate\":{:.4},\"lmr_searches\":{},\"lmr_researches\":{},\
+             \"iterations\":[",
+            self.nodes,
+            self.qnodes,
+            self.tt_probes,
+            self.tt_hits,
+            self.tt_cutoffs,
+            self.beta_cutoffs,
+            self.first_move_cutoff_rate(),
+            self.null_move_tries,
+            self.null_move_success_rate(),
+            self.lmr_searches,
+            self.lmr_researches,
+        )
+        .unwrap();
+        for (i, iteration) in self.iterations.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write!(
+                out,
+                "{{\"depth\":{},\"nodes\":{},\"time_ms\":{},\"branching_factor\":{}}}",
+                iteration.depth,
+                iteration.nodes,
+                iteration.elapsed.as_millis(),
+                iteration
+                    .branching_factor
+                    .map_or("null".to_string(), |b| format!("{:.3}", b)),
+            )
+            .unwrap();
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+/// a new best line found at the root
+#[derive(Debug, Clone)]
+pub struct PvChange {
+    pub depth: u8,
+    pub elapsed: Duration,
+    pub score: i32,
+    pub pv: Vec<Move>,
+}
+
+/// a finished iteration as recorded by the trace
+#[derive(Debug, Clone)]
+pub struct TraceIteration {
+    pub depth: u8,
+    pub score: i32,
+    pub nodes: u64,
+    pub elapsed: Duration,
+    pub pv: Vec<Move>,
+}
+
+/// timeline of a search, recorded when SearchConfig::trace is set
+#[derive(Debug, Clone, Default)]
+pub struct SearchTrace {
+    pub iterations: Vec<TraceIteration>,
+    pub pv_changes: Vec<PvChange>,
+}
+
+fn pv_to_json(pv: &[Move]) -> String {
+    let moves: Vec<String> = pv.iter().map(|m| format!("\"{}\"", m.to_uci())).collect();
+    format!("[{}]", moves.join(","))
+}
+
+impl SearchTrace {
+    pub fn to_json(&self, stats: &SearchStats) -> String {
+        let mut out = String::new();
+        write!(out, "{{\"stats\":{},\"iterations\":[", stats.to_json()).unwrap();
+        for (i, iteration) in self.iterations.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write!(
+                out,
+                "{{\"depth\":{},\"score\":{},\"nodes\":{},\"time_ms\":{},\"pv\":{}}}",
+                iteration.depth,
+                iteration.score,
+                iteration.nodes,
+                iteration.elapsed.as_millis(),
+                pv_to_json(&iteration.pv),
+            )
+            .unwrap();
+        }
+        out.push_str("],\"pv_changes\":[");
+        for (i, change) in self.pv_changes.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write!(
+                out,
+                "{{\"depth\":{},\"score\":{},\"time_ms\":{},\"pv\":{}}}",
+                change.depth,
+                change.score,
+                change.elapsed.as_millis(),
+                pv_to_json(&change.pv),
+            )
+            .unwrap();
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::{SearchConfig, SearchLimits, Searcher};
+    use crate::state::fen::fen_to_board;
+
+    /// minimal JSON reader that checks the syntax and returns the unread rest
+    fn value(s: &str) -> Result<&str, String> {
+        let s = s.trim_start();
+        let rest = match s.chars().next() {
+            Some('{') => {
+                let mut rest = s[1..].trim_start();
+                if let Some(after) = rest.strip_prefix('}') {
+                    return Ok(after);
+                }
+                loop {
+                    rest = string(rest.trim_start())?;
+                    rest = rest
+                        .trim_start()
+                        .strip_prefix(':')
+                        .ok_or(format!("expected : at {}", rest))?;
+                    rest = value(rest)?.trim_start();
+                    match rest.chars().next() {
+                        Some(',') => rest = &rest[1..],
+                        Some('}') => break &rest[1..],
+                        _ => return Err(format!("expected , or }} at {}", rest)),
+                    }
+                }
+            }
+            Some('[') => {
+                let mut rest = s[1..].trim_start();
+                if let Some(after) = rest.strip_prefix(']') {
+                    return Ok(after);
+                }
+                loop {
+                    rest = value(rest)?.trim_start();
+                    match rest.chars().next() {
+                        Some(',') => rest = &rest[1..],
+                        Some(']') => break &rest[1..],
+                        _ => return Err(format!("expected , or ] at {}", rest)),
+                    }
+                }
+            }
+            Some('"') => string(s)?,
+            _ if s.starts_with("null") => &s[4..],
+            _ => {
+                let end = s
+                    .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+                    .unwrap_or(s.len());
+                s[..end]
+                    .parse::<f64>()
+                    .map_err(|_| format!("invalid value at {}", s))?;
+                &s[end..]
+            }
+        };
+        Ok(rest)
+    }
+
+    fn string(s: &str) -> Result<&str, String> {
+        let inner = s
+            .strip_prefix('"')
+            .ok_or(format!("expected string at {}", s))?;
+        let end = inner.find('"').ok_or("unterminated string")?;
+        Ok(&inner[end + 1..])
+    }
+
+    fn assert_json(json: &str) {
+        assert_eq!(value(json).map(str::trim), Ok(""), "{}", json);
+    }
+
+    #[test]
+    fn counters_are_consistent() {
+        crate::state::init();
+        let board =
+            fen_to_board("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let config = SearchConfig {
+            trace: true,
+            ..Default::default()
+        };
+        let limits = SearchLimits {
+            depth: Some(6),
+            ..Default::default()
+        };
+        let result = Searcher::new(board, config).search(limits);
+        let stats = &result.stats;
+
+        assert_eq!(stats.nodes, result.nodes);
+        assert!(stats.qnodes > 0 && stats.qnodes < stats.nodes);
+        assert!(stats.tt_hits > 0 && stats.tt_hits <= stats.tt_probes);
+        assert!(stats.tt_cutoffs <= stats.tt_hits);
+        assert!(stats.first_move_cutoffs > 0 && stats.first_move_cutoffs <= stats.beta_cutoffs);
+        assert!(stats.null_move_tries > 0 && stats.null_move_cutoffs <= stats.null_move_tries);
+        assert!(stats.lmr_searches > 0 && stats.lmr_researches <= stats.lmr_searches);
+        assert!((0.0..=1.0).contains(&stats.first_move_cutoff_rate()));
+
+        let depths: Vec<u8> = stats.iterations.iter().map(|i| i.depth).collect();
+        assert_eq!(depths, [1, 2, 3, 4, 5, 6]);
+        let iteration_nodes: u64 = stats.iterations.iter().map(|i| i.nodes).sum();
+        assert_eq!(iteration_nodes, stats.nodes);
+        assert!(stats.iterations[0].branching_factor.is_none());
+        assert!(
+            stats.iterations[1..]
+                .iter()
+                .all(|i| i.branching_factor.is_some())
+        );
+
+        let trace = result.trace.as_ref().unwrap();
+        assert_eq!(trace.iterations.len(), 6);
+        assert_eq!(trace.iterations.last().unwrap().pv, result.pv);
+        assert!(!trace.pv_changes.is_empty());
+    }
+
+    #[test]
+    fn json_export_parses() {
+        crate::state::init();
+        let config = SearchConfig {
+            trace: true,
+            ..Default::default()
+        };
+        let limits = SearchLimits {
+            depth: Some(4),
+            ..Default::default()
+        };
+        let result =
+            Searcher::new(crate::state::board::Board::default_setup(), config).search(limits);
+        assert_json(&result.stats.to_json());
+        assert_json(&result.trace.unwrap().to_json(&result.stats));
+        assert_json(&SearchStats::default().to_json());
+        assert_json(&SearchTrace::default().to_json(&SearchStats::default()));
+        assert!(value("{\"nodes\":1,}").is_err());
+        assert!(value("[1 2]").is_err());
+    }
+}
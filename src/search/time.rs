@@ -0,0 +1,225 @@
+use std::time::{Duration, Instant};
+
+use crate::state::{Color, board_move_gen::Move};
+
+use super::SearchLimits;
+
+pub const DEFAULT_MOVE_OVERHEAD_MS: u64 = 10;
+
+/// moves assumed to be left in the game when the clock does not say
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+/// the hard limit may be at most this many times the soft limit
+const HARD_LIMIT_FACTOR: u32 = 4;
+/// iterations with the same best move after which it is considered stable
+const STABLE_ITERATIONS: u32 = 4;
+/// score drop in centipawns between iterations treated as a warning sign
+const SCORE_DROP_MARGIN: i32 = 20;
+
+/// decides how long a search may take given the clock situation
+#[derive(Debug, Clone)]
+pub struct TimeManager {
+    start: Instant,
+    soft_limit: Option<Duration>,
+    hard_limit: Option<Duration>,
+    /// fixed time searches use their whole budget, clock searches adapt it
+    adaptive: bool,
+    previous_best: Option<Move>,
+    previous_score: Option<i32>,
+    stable_iterations: u32,
+    best_move_changes: f64,
+}
+
+impl TimeManager {
+    pub fn new(limits: &SearchLimits, side: Color, move_overhead: Duration) -> Self {
+        let mut manager = Self {
+            start: Instant::now(),
+            soft_limit: None,
+            hard_limit: None,
+            adaptive: false,
+            previous_best: None,
+            previous_score: None,
+            stable_iterations: 0,
+            best_move_changes: 0.0,
+        };
+
+        let (time, increment) = match side {
+            Color::White => (limits.wtime, limits.winc),
+            Color::Black => (limits.btime, limits.binc),
+        };
+
+        if let Some(movetime) = limits.movetime {
+            let budget = movetime
+                .saturating_sub(move_overhead)
+                .max(Duration::from_millis(1));
+            manager.soft_limit = Some(budget);
+            manager.hard_limit = Some(budget);
+        } else if let Some(time) = time {
+            let increment = increment.unwrap_or(Duration::ZERO);
+            let moves_to_go = limits.moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+
+            // never plan to use more than most of what is left on the clock
+            let available = time
+                .saturating_sub(move_overhead)
+                .max(Duration::from_millis(1));
+            let ceiling = available * 8 / 10;
+
+            let soft = (available / moves_to_go + increment * 3 / 4).min(ceiling);
+            let hard = (soft * HARD_LIMIT_FACTOR).min(ceiling).max(soft);
+
+            manager.soft_limit = Some(soft);
+            manager.hard_limit = Some(hard);
+            manager.adaptive = true;
+        }
+
+        manager
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// restarts the clock when a ponder search turns into a normal search, the stability
+    /// measures gathered while pondering are kept
+    pub fn ponderhit(&mut self) {
+        self.start = Instant::now();
+    }
+
+    pub fn soft_limit(&self) -> Option<Duration> {
+        self.soft_limit
+    }
+
+    pub fn hard_limit(&self) -> Option<Duration> {
+        self.hard_limit
+    }
+
+    /// returns true once the search has to be aborted, even in the middle of an iteration
+    pub fn hard_limit_reached(&self) -> bool {
+        self.hard_limit.is_some_and(|hard| self.elapsed() >= hard)
+    }
+
+    /// updates the stability measures with a finished iteration and returns true if the next
+    /// iteration should not be started
+    pub fn iteration_complete(
+        &mut self,
+        best_move: Option<Move>,
+        score: i32,
+        legal_moves: usize,
+    ) -> bool {
+        let Some(soft) = self.soft_limit else {
+            return false;
+        };
+
+        if !self.adaptive {
+            return self.elapsed() >= soft;
+        }
+
+        // with only one legal move there is nothing to think about
+        if legal_moves == 1 {
+            return true;
+        }
+
+        self.best_move_changes /= 2.0;
+        if best_move == self.previous_best {
+            self.stable_iterations += 1;
+        } else {
+            self.stable_iterations = 0;
+            if self.previous_best.is_some() {
+                self.best_move_changes += 1.0;
+            }
+        }
+
+        let mut scale = 1.0 + self.best_move_changes / 2.0;
+        if let Some(previous) = self.previous_score {
+            let drop = previous - score;
+            if drop > SCORE_DROP_MARGIN {
+                scale *= (1.0 + drop as f64 / 100.0).min(2.0);
+            }
+        }
+        if self.stable_iterations >= STABLE_ITERATIONS {
+            scale *= 0.6;
+        }
+
+        self.previous_best = best_move;
+        self.previous_score = Some(score);
+
+        let target = soft.mul_f64(scale);
+        let target = match self.hard_limit {
+            Some(hard) => target.min(hard),
+            None => target,
+        };
+        self.elapsed() >= target
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OVERHEAD: Duration = Duration::from_millis(DEFAULT_MOVE_OVERHEAD_MS);
+
+    fn millis(ms: u64) -> Option<Duration> {
+        Some(Duration::from_millis(ms))
+    }
+
+    #[test]
+    fn movetime_is_used_whole_minus_the_overhead() {
+        let limits = SearchLimits {
+            movetime: millis(1000),
+            wtime: millis(60_000),
+            ..Default::default()
+        };
+        let manager = TimeManager::new(&limits, Color::White, OVERHEAD);
+        assert_eq!(manager.soft_limit(), millis(990));
+        assert_eq!(manager.hard_limit(), millis(990));
+    }
+
+    #[test]
+    fn clock_is_split_over_the_moves_to_go() {
+        let limits = SearchLimits {
+            wtime: millis(10_010),
+            btime: millis(60_010),
+            binc: millis(1000),
+            moves_to_go: Some(20),
+            ..Default::default()
+        };
+        let white = TimeManager::new(&limits, Color::White, OVERHEAD);
+        assert_eq!(white.soft_limit(), millis(500));
+        assert_eq!(white.hard_limit(), millis(500 * HARD_LIMIT_FACTOR as u64));
+
+        // the side's own clock and increment are used
+        let black = TimeManager::new(&limits, Color::Black, OVERHEAD);
+        assert_eq!(black.soft_limit(), millis(60_000 / 20 + 750));
+    }
+
+    #[test]
+    fn limits_stay_below_the_clock() {
+        let limits = SearchLimits {
+            wtime: millis(100),
+            winc: millis(5_000),
+            moves_to_go: Some(1),
+            ..Default::default()
+        };
+        let manager = TimeManager::new(&limits, Color::White, OVERHEAD);
+        assert_eq!(manager.soft_limit(), millis(72));
+        assert_eq!(manager.hard_limit(), millis(72));
+    }
+
+    #[test]
+    fn unlimited_without_a_clock() {
+        let mut manager = TimeManager::new(&SearchLimits::default(), Color::White, OVERHEAD);
+        assert_eq!(manager.soft_limit(), None);
+        assert!(!manager.hard_limit_reached());
+        assert!(!manager.iteration_complete(None, 0, 1));
+    }
+
+    #[test]
+    fn single_legal_move_stops_a_clock_search() {
+        let limits = SearchLimits {
+            wtime: millis(60_000),
+            ..Default::default()
+        };
+        let mut manager = TimeManager::new(&limits, Color::White, OVERHEAD);
+        assert!(manager.iteration_complete(None, 0, 1));
+        assert!(!manager.iteration_complete(None, 0, 20));
+    }
+}
@@ -0,0 +1,276 @@
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+
+use crate::state::board_move_gen::Move;
+
+use super::MATE_BOUND;
+
+pub const DEFAULT_HASH_MB: usize = 16;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact = 0,
+    Lower = 1,
+    Upper = 2,
+}
+
+impl Bound {
+    fn from_bits(bits: u64) -> Self {
+        match bits & 0b11 {
+            0 => Bound::Exact,
+            1 => Bound::Lower,
+            _ => Bound::Upper,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TtEntry {
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub eval: i32,
+    pub depth: u8,
+    pub bound: Bound,
+    generation: u8,
+}
+
+/// 16 bits move, 16 bits score, 16 bits eval, 8 bits depth, 2 bits bound, 6 bits generation
+fn pack(entry: &TtEntry) -> u64 {
+    let m = entry.best_move.map_or(0, |m| m.data()) as u64;
+    let score = entry.score as i16 as u16 as u64;
+    let eval = entry.eval as i16 as u16 as u64;
+    (m << 48)
+        | (score << 32)
+        | (eval << 16)
+        | ((entry.depth as u64) << 8)
+        | ((entry.bound as u64) << 6)
+        | (entry.generation as u64 & 0b111111)
+}
+
+fn unpack(data: u64) -> TtEntry {
+    let m = (data >> 48) as u16;
+    TtEntry {
+        best_move: if m == 0 {
+            None
+        } else {
+            Some(Move::from_data(m))
+        },
+        score: (data >> 32) as u16 as i16 as i32,
+        eval: (data >> 16) as u16 as i16 as i32,
+        depth: (data >> 8) as u8,
+        bound: Bound::from_bits(data >> 6),
+        generation: (data & 0b111111) as u8,
+    }
+}
+
+/// a single slot, the key is stored xored with the data so torn writes from another thread
+/// fail verification instead of returning a corrupted entry
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+/// lock-free transposition table shared between search threads
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+    generation: AtomicU8,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> Self {
+        let count = (size_mb.max(1) * 1024 * 1024 / size_of::<Slot>()).max(1);
+        let mut slots = Vec::with_capacity(count);
+        slots.resize_with(count, Slot::default);
+        Self {
+            slots,
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    /// empties every slot
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    /// marks the start of a new search so older entries are replaced first
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn generation(&self) -> u8 {
+        self.generation.load(Ordering::Relaxed) & 0b111111
+    }
+
+    fn slot(&self, hash: u64) -> &Slot {
+        let index = ((hash as u128 * self.slots.len() as u128) >> 64) as usize;
+        &self.slots[index]
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<TtEntry> {
+        let slot = self.slot(hash);
+        let key = slot.key.load(Ordering::Relaxed);
+        let data = slot.data.load(Ordering::Relaxed);
+        if data != 0 && key ^ data == hash {
+            Some(unpack(data))
+        } else {
+            None
+        }
+    }
+
+    pub fn store(
+        &self,
+        hash: u64,
+        best_move: Option<Move>,
+        score: i32,
+        eval: i32,
+        depth: u8,
+        bound: Bound,
+    ) {
+        let slot = self.slot(hash);
+        let generation = self.generation();
+
+        let old_data = slot.data.load(Ordering::Relaxed);
+        let old_key = slot.key.load(Ordering::Relaxed) ^ old_data;
+        if old_data != 0 {
+            let old = unpack(old_data);
+            let same_position = old_key == hash;
+            let replace = !same_position
+                || bound == Bound::Exact
+                || old.generation != generation
+                || depth as i32 + 2 >= old.depth as i32;
+            if !replace {
+                return;
+            }
+        }
+
+        // keep the old move if the new search did not find one for this position
+        let best_move = match best_move {
+            Some(m) => Some(m),
+            None if old_data != 0 && old_key == hash => unpack(old_data).best_move,
+            None => None,
+        };
+
+        let data = pack(&TtEntry {
+            best_move,
+            score,
+            eval,
+            depth,
+            bound,
+            generation,
+        });
+        slot.key.store(hash ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    /// returns the permille of sampled slots written during the current search
+    pub fn hashfull(&self) -> u32 {
+        let generation = self.generation();
+        let sample = self.slots.len().min(1000);
+        let used = self.slots[..sample]
+            .iter()
+            .filter(|slot| {
+                let data = slot.data.load(Ordering::Relaxed);
+                data != 0 && unpack(data).generation == generation
+            })
+            .count();
+        (used * 1000 / sample.max(1)) as u32
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASH_MB)
+    }
+}
+
+/// converts a score relative to the root into one relative to the node at ply for storage
+pub fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+/// converts a stored score back into one relative to the root
+pub fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::MATE;
+
+    const HASH: u64 = 0x9d39_247e_3377_6d41;
+
+    #[test]
+    fn store_and_probe_round_trip() {
+        let tt = TranspositionTable::new(1);
+        let m = Move::from_data(0x1234);
+        tt.store(HASH, Some(m), -1234, 56, 7, Bound::Lower);
+        let entry = tt.probe(HASH).unwrap();
+        assert_eq!(entry.best_move, Some(m));
+        assert_eq!(entry.score, -1234);
+        assert_eq!(entry.eval, 56);
+        assert_eq!(entry.depth, 7);
+        assert_eq!(entry.bound, Bound::Lower);
+
+        tt.clear();
+        assert!(tt.probe(HASH).is_none());
+    }
+
+    #[test]
+    fn verification_rejects_other_keys_and_torn_writes() {
+        let tt = TranspositionTable::new(1);
+        tt.store(HASH, None, 10, 0, 3, Bound::Exact);
+        // differs only in bits that do not select the slot
+        assert!(std::ptr::eq(tt.slot(HASH), tt.slot(HASH ^ 1)));
+        assert!(tt.probe(HASH ^ 1).is_none());
+
+        // data written for another position without its key
+        let slot = tt.slot(HASH);
+        let other = pack(&TtEntry {
+            best_move: None,
+            score: 20,
+            eval: 0,
+            depth: 3,
+            bound: Bound::Exact,
+            generation: 0,
+        });
+        slot.data.store(other, Ordering::Relaxed);
+        assert!(tt.probe(HASH).is_none());
+    }
+
+    #[test]
+    fn keeps_the_move_when_a_store_has_none() {
+        let tt = TranspositionTable::new(1);
+        let m = Move::from_data(0x0421);
+        tt.store(HASH, Some(m), 5, 0, 4, Bound::Exact);
+        tt.store(HASH, None, 8, 0, 6, Bound::Upper);
+        let entry = tt.probe(HASH).unwrap();
+        assert_eq!(entry.best_move, Some(m));
+        assert_eq!(entry.score, 8);
+    }
+
+    #[test]
+    fn mate_scores_are_stored_relative_to_the_node() {
+        for score in [MATE - 5, -MATE + 8, 123] {
+            assert_eq!(score_from_tt(score_to_tt(score, 4), 4), score);
+        }
+        assert_eq!(score_to_tt(MATE - 5, 4), MATE - 1);
+    }
+}
@@ -0,0 +1,27 @@
+pub mod mate;
+pub mod pns;
+pub mod problem;
+pub mod stipulation;
+
+use crate::state::{board::Board, board_move_gen::Move};
+
+/// returns the legal moves of the side to move, leaving the board unchanged
+pub(crate) fn legal_moves(board: &mut Board) -> Vec<Move> {
+    board.legal_moves()
+}
+
+/// returns true if the side to move is checkmated
+pub(crate) fn is_checkmate(board: &mut Board) -> bool {
+    board.in_check() && !has_legal_move(board)
+}
+
+/// returns true if the side to move has at least one legal move
+pub(crate) fn has_legal_move(board: &mut Board) -> bool {
+    for m in board.generate_moves() {
+        if board.make_move(m).is_ok() {
+            board.unmake_move(m);
+            return true;
+        }
+    }
+    false
+}
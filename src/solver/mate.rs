@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+
+use crate::state::{board::Board, board_move_gen::Move};
+
+use super::{has_legal_move, legal_moves};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// exactly one key and no faster mate
+    Sound,
+    /// the stipulated mate cannot be forced
+    NoSolution,
+    /// more than one key move forces mate
+    Cooked,
+    /// a unique key exists, but mate can be forced in fewer moves
+    ShortSolution,
+}
+
+/// a first move that fails, with the defences that refute it
+#[derive(Debug, Clone)]
+pub struct Refutation {
+    pub try_move: Move,
+    /// empty if the try stalemates the defender
+    pub defences: Vec<Move>,
+}
+
+/// a defence after the key that allows more than one mating continuation
+#[derive(Debug, Clone)]
+pub struct Dual {
+    pub defence: Move,
+    pub continuations: Vec<Move>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MateReport {
+    /// the stipulated number of moves
+    pub moves: u32,
+    pub keys: Vec<Move>,
+    pub refutations: Vec<Refutation>,
+    /// fewest moves in which mate can be forced, if it can be forced within the stipulation
+    pub shortest: Option<u32>,
+    /// duals following the key, only checked when the key is unique
+    pub duals: Vec<Dual>,
+    pub verdict: Verdict,
+    pub nodes: u64,
+}
+
+impl MateReport {
+    pub fn has_duals(&self) -> bool {
+        !self.duals.is_empty()
+    }
+}
+
+/// exhaustive prover for direct mates, the side to move attacks
+pub struct MateSolver {
+    /// (hash, moves) -> whether the side to move forces mate within moves
+    table: HashMap<(u64, u32), bool>,
+    nodes: u64,
+}
+
+impl MateSolver {
+    pub fn new() -> Self {
+        Self {
+            table: HashMap::new(),
+            nodes: 0,
+        }
+    }
+
+    /// returns true if the side to move can force mate within the given number of its moves
+    pub fn forces_mate(&mut self, board: &mut Board, moves: u32) -> bool {
+        if moves == 0 {
+            return false;
+        }
+        let key = (board.state.hash, moves);
+        if let Some(&known) = self.table.get(&key) {
+            return known;
+        }
+
+        let mut forced = false;
+        for m in board.generate_moves() {
+            if board.make_move(m).is_err() {
+                continue;
+            }
+            self.nodes += 1;
+            forced = self.defender_loses(board, moves);
+            board.unmake_move(m);
+            if forced {
+                break;
+            }
+        }
+
+        self.table.insert(key, forced);
+        forced
+    }
+
+    /// returns true if every defence of the side to move allows mate in moves - 1, or if it is
+    /// already mated
+    fn defender_loses(&mut self, board: &mut Board, moves: u32) -> bool {
+        let in_check = board.in_check();
+        // the last attacking move has to give check
+        if moves == 1 && !in_check {
+            return false;
+        }
+
+        let mut has_defence = false;
+        for r in board.generate_moves() {
+            if board.make_move(r).is_err() {
+                continue;
+            }
+            self.nodes += 1;
+            has_defence = true;
+            let lost = self.forces_mate(board, moves - 1);
+            board.unmake_move(r);
+            if !lost {
+                return false;
+            }
+        }
+
+        has_defence || in_check
+    }
+
+    /// returns the defences that refute the given (already made) attacking move
+    fn refuting_defences(&mut self, board: &mut Board, moves: u32) -> Vec<Move> {
+        let mut defences = Vec::new();
+        for r in legal_moves(board) {
+            board.make_move(r).expect("legal move rejected");
+            if !self.forces_mate(board, moves - 1) {
+                defences.push(r);
+            }
+            board.unmake_move(r);
+        }
+        defences
+    }
+
+    /// returns the attacking moves that still force mate in moves after the given (already
+    /// made) defence
+    fn mating_continuations(&mut self, board: &mut Board, moves: u32) -> Vec<Move> {
+        let mut continuations = Vec::new();
+        for c in legal_moves(board) {
+            board.make_move(c).expect("legal move rejected");
+            if self.defender_loses(board, moves) {
+                continuations.push(c);
+            }
+            board.unmake_move(c);
+        }
+        continuations
+    }
+
+    /// solves a mate in the given number of moves, listing keys, refuted tries and duals
+    pub fn solve(&mut self, board: &Board, moves: u32) -> MateReport {
+        debug_assert!(moves > 0, "a mate needs at least one move");
+        let mut board = board.clone();
+        let mut keys = Vec::new();
+        let mut refutations = Vec::new();
+
+        for m in legal_moves(&mut board) {
+            board.make_move(m).expect("legal move rejected");
+            if self.defender_loses(&mut board, moves) {
+                keys.push(m);
+            } else {
+                let defences = if has_legal_move(&mut board) {
+                    self.refuting_defences(&mut board, moves)
+                } else {
+                    Vec::new()
+                };
+                refutations.push(Refutation {
+                    try_move: m,
+                    defences,
+                });
+            }
+            board.unmake_move(m);
+        }
+
+        let shortest = (1..=moves).find(|&n| self.forces_mate(&mut board, n));
+
+        let mut duals = Vec::new();
+        if keys.len() == 1 && moves > 1 {
+            board.make_move(keys[0]).expect("legal move rejected");
+            for defence in legal_moves(&mut board) {
+                board.make_move(defence).expect("legal move rejected");
+                let continuations = self.mating_continuations(&mut board, moves - 1);
+                board.unmake_move(defence);
+                if continuations.len() > 1 {
+                    duals.push(Dual {
+                        defence,
+                        continuations,
+                    });
+                }
+            }
+            board.unmake_move(keys[0]);
+        }
+
+        let verdict = match keys.len() {
+            0 => Verdict::NoSolution,
+            1 if shortest.is_some_and(|n| n < moves) => Verdict::ShortSolution,
+            1 => Verdict::Sound,
+            _ => Verdict::Cooked,
+        };
+
+        MateReport {
+            moves,
+            keys,
+            refutations,
+            shortest,
+            duals,
+            verdict,
+            nodes: self.nodes,
+        }
+    }
+}
+
+impl Default for MateSolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// solves a mate in the given number of moves for the side to move
+pub fn solve(board: &Board, moves: u32) -> MateReport {
+    MateSolver::new().solve(board, moves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::fen::fen_to_board;
+
+    fn solve_fen(fen: &str, moves: u32) -> MateReport {
+        crate::state::init();
+        solve(&fen_to_board(fen).unwrap(), moves)
+    }
+
+    fn keys(report: &MateReport) -> Vec<String> {
+        report.keys.iter().map(|m| m.to_uci()).collect()
+    }
+
+    #[test]
+    fn sound_problem() {
+        let report = solve_fen("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1", 2);
+        assert_eq!(report.verdict, Verdict::Sound);
+        assert_eq!(keys(&report), ["a1a6"]);
+        assert_eq!(report.shortest, Some(2));
+    }
+
+    #[test]
+    fn cooked_problem() {
+        let report = solve_fen("8/8/8/8/8/5K2/7R/6k1 w - - 0 1", 2);
+        assert_eq!(report.verdict, Verdict::Cooked);
+        assert_eq!(
+            keys(&report),
+            ["h2h3", "h2h4", "h2h5", "h2h6", "h2h7", "h2h8"]
+        );
+    }
+
+    #[test]
+    fn short_solution() {
+        let report = solve_fen("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1", 3);
+        assert_eq!(report.verdict, Verdict::ShortSolution);
+        assert_eq!(keys(&report), ["a1a6"]);
+        assert_eq!(report.shortest, Some(2));
+
+        let report = solve_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 2);
+        assert_eq!(report.verdict, Verdict::ShortSolution);
+        assert_eq!(report.shortest, Some(1));
+    }
+
+    #[test]
+    fn no_solution() {
+        let report = solve_fen("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1", 1);
+        assert_eq!(report.verdict, Verdict::NoSolution);
+        assert!(report.keys.is_empty());
+        assert_eq!(report.shortest, None);
+        assert!(!report.refutations.is_empty());
+    }
+}
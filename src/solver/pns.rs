@@ -0,0 +1,309 @@
+use crate::state::{board::Board, board_move_gen::Move};
+
+use super::legal_moves;
+
+/// proof and disproof numbers at or above this are treated as infinite
+const INFINITE: u32 = u32::MAX / 2;
+
+pub const DEFAULT_NODE_LIMIT: usize = 2_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PnsOutcome {
+    /// mate is forced, with a main line starting with the key
+    Proven(Vec<Move>),
+    /// mate cannot be forced within the move limit
+    Disproven,
+    /// the node limit was reached first
+    Unknown,
+}
+
+#[derive(Debug, Clone)]
+pub struct PnsResult {
+    pub outcome: PnsOutcome,
+    pub nodes: usize,
+}
+
+struct Node {
+    /// move leading to this node, unused for the root
+    mv: Move,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    proof: u32,
+    disproof: u32,
+    expanded: bool,
+    /// the attacker is to move (an OR node), otherwise the defender is (an AND node)
+    attacker_to_move: bool,
+    ply: u32,
+}
+
+/// proof-number search for a forced mate by the side to move
+pub struct ProofNumberSearch {
+    nodes: Vec<Node>,
+    max_plies: u32,
+    node_limit: usize,
+}
+
+impl ProofNumberSearch {
+    /// prepares a search for mate within max_moves moves of the attacker
+    pub fn new(max_moves: u32, node_limit: usize) -> Self {
+        Self {
+            nodes: Vec::new(),
+            max_plies: max_moves.max(1) * 2 - 1,
+            node_limit,
+        }
+    }
+
+    pub fn search(&mut self, board: &Board) -> PnsResult {
+        let mut board = board.clone();
+        self.nodes.clear();
+        self.nodes.push(Node {
+            mv: Move::NULL,
+            parent: None,
+            children: Vec::new(),
+            proof: 1,
+            disproof: 1,
+            expanded: false,
+            attacker_to_move: true,
+            ply: 0,
+        });
+
+        while self.nodes[0].proof != 0
+            && self.nodes[0].disproof != 0
+            && self.nodes.len() < self.node_limit
+        {
+            let (leaf, path) = self.select_most_proving(&mut board);
+            self.expand(leaf, &mut board);
+            for &m in path.iter().rev() {
+                board.unmake_move(m);
+            }
+            self.update_ancestors(leaf);
+        }
+
+        let outcome = if self.nodes[0].proof == 0 {
+            PnsOutcome::Proven(self.main_line())
+        } else if self.nodes[0].disproof == 0 {
+            PnsOutcome::Disproven
+        } else {
+            PnsOutcome::Unknown
+        };
+
+        PnsResult {
+            outcome,
+            nodes: self.nodes.len(),
+        }
+    }
+
+    /// walks from the root to the most proving leaf, making the moves on the board
+    fn select_most_proving(&self, board: &mut Board) -> (usize, Vec<Move>) {
+        let mut index = 0;
+        let mut path = Vec::new();
+        while self.nodes[index].expanded {
+            let node = &self.nodes[index];
+            let next = if node.attacker_to_move {
+                node.children
+                    .iter()
+                    .copied()
+                    .find(|&c| self.nodes[c].proof == node.proof)
+            } else {
+                node.children
+                    .iter()
+                    .copied()
+                    .find(|&c| self.nodes[c].disproof == node.disproof)
+            }
+            .expect("expanded node without a matching child");
+
+            let m = self.nodes[next].mv;
+            board.make_move(m).expect("stored move is illegal");
+            path.push(m);
+            index = next;
+        }
+        (index, path)
+    }
+
+    /// creates the children of a leaf, or resolves it if the game ends there
+    fn expand(&mut self, index: usize, board: &mut Board) {
+        let ply = self.nodes[index].ply;
+        let attacker_to_move = self.nodes[index].attacker_to_move;
+        self.nodes[index].expanded = true;
+
+        let moves = legal_moves(board);
+        if moves.is_empty() {
+            // mate only counts if the defender is the one who cannot move
+            let proven = !attacker_to_move && board.in_check();
+            self.set_resolved(index, proven);
+            return;
+        }
+        if ply >= self.max_plies {
+            self.set_resolved(index, false);
+            return;
+        }
+
+        for m in moves {
+            // the last attacking move has to mate, so only checks are worth trying
+            if attacker_to_move && ply + 1 == self.max_plies {
+                board.make_move(m).expect("legal move rejected");
+                let gives_check = board.in_check();
+                board.unmake_move(m);
+                if !gives_check {
+                    continue;
+                }
+            }
+
+            let child = self.nodes.len();
+            self.nodes.push(Node {
+                mv: m,
+                parent: Some(index),
+                children: Vec::new(),
+                proof: 1,
+                disproof: 1,
+                expanded: false,
+                attacker_to_move: !attacker_to_move,
+                ply: ply + 1,
+            });
+            self.nodes[index].children.push(child);
+        }
+
+        if self.nodes[index].children.is_empty() {
+            self.set_resolved(index, false);
+        } else {
+            self.recompute(index);
+        }
+    }
+
+    fn set_resolved(&mut self, index: usize, proven: bool) {
+        let node = &mut self.nodes[index];
+        if proven {
+            node.proof = 0;
+            node.disproof = INFINITE;
+        } else {
+            node.proof = INFINITE;
+            node.disproof = 0;
+        }
+    }
+
+    /// recomputes the proof and disproof numbers of an expanded node from its children
+    fn recompute(&mut self, index: usize) {
+        let node = &self.nodes[index];
+        let children = node.children.iter().map(|&c| &self.nodes[c]);
+        let (proof, disproof) = if node.attacker_to_move {
+            let proof = children.clone().map(|c| c.proof).min().unwrap_or(INFINITE);
+            let disproof = children.map(|c| c.disproof).fold(0u32, sum_capped);
+            (proof, disproof)
+        } else {
+            let proof = children.clone().map(|c| c.proof).fold(0u32, sum_capped);
+            let disproof = children.map(|c| c.disproof).min().unwrap_or(INFINITE);
+            (proof, disproof)
+        };
+        let node = &mut self.nodes[index];
+        node.proof = proof;
+        node.disproof = disproof;
+    }
+
+    fn update_ancestors(&mut self, mut index: usize) {
+        while let Some(parent) = self.nodes[index].parent {
+            let before = (self.nodes[parent].proof, self.nodes[parent].disproof);
+            self.recompute(parent);
+            if (self.nodes[parent].proof, self.nodes[parent].disproof) == before {
+                break;
+            }
+            index = parent;
+        }
+    }
+
+    /// follows proven children from the root, taking the quickest mate for the attacker and
+    /// the longest resistance for the defender
+    fn main_line(&self) -> Vec<Move> {
+        let mut line = Vec::new();
+        let mut index = 0;
+        loop {
+            let node = &self.nodes[index];
+            let proven = node
+                .children
+                .iter()
+                .copied()
+                .filter(|&c| self.nodes[c].proof == 0);
+            let next = if node.attacker_to_move {
+                proven.min_by_key(|&c| self.proven_length(c))
+            } else {
+                proven.max_by_key(|&c| self.proven_length(c))
+            };
+            match next {
+                Some(next) => {
+                    line.push(self.nodes[next].mv);
+                    index = next;
+                }
+                None => return line,
+            }
+        }
+    }
+
+    /// returns the length in plies of the proven line below a proven node
+    fn proven_length(&self, index: usize) -> u32 {
+        let node = &self.nodes[index];
+        let proven = node
+            .children
+            .iter()
+            .copied()
+            .filter(|&c| self.nodes[c].proof == 0)
+            .map(|c| self.proven_length(c) + 1);
+        if node.attacker_to_move {
+            proven.min().unwrap_or(0)
+        } else {
+            proven.max().unwrap_or(0)
+        }
+    }
+}
+
+fn sum_capped(total: u32, value: u32) -> u32 {
+    total.saturating_add(value).min(INFINITE)
+}
+
+/// searches for a mate within max_moves moves of the side to move
+pub fn prove_mate(board: &Board, max_moves: u32, node_limit: usize) -> PnsResult {
+    ProofNumberSearch::new(max_moves, node_limit).search(board)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::mate;
+    use crate::state::fen::fen_to_board;
+
+    /// checks that the proof-number search agrees with the exhaustive solver
+    fn assert_agrees(fen: &str, moves: u32) {
+        crate::state::init();
+        let board = fen_to_board(fen).unwrap();
+        let report = mate::solve(&board, moves);
+        match prove_mate(&board, moves, DEFAULT_NODE_LIMIT).outcome {
+            PnsOutcome::Proven(line) => {
+                assert!(report.keys.contains(&line[0]), "{} #{}", fen, moves)
+            }
+            PnsOutcome::Disproven => assert!(report.keys.is_empty(), "{} #{}", fen, moves),
+            PnsOutcome::Unknown => panic!("node limit reached for {} #{}", fen, moves),
+        }
+    }
+
+    #[test]
+    fn agrees_with_exhaustive_solver() {
+        for (fen, moves) in [
+            ("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1", 1),
+            ("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1", 2),
+            ("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1", 3),
+            ("8/8/8/8/8/5K2/7R/6k1 w - - 0 1", 1),
+            ("8/8/8/8/8/5K2/7R/6k1 w - - 0 1", 2),
+            ("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 1),
+        ] {
+            assert_agrees(fen, moves);
+        }
+    }
+
+    #[test]
+    fn proven_line_starts_with_the_key() {
+        crate::state::init();
+        let board = fen_to_board("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1").unwrap();
+        match prove_mate(&board, 2, DEFAULT_NODE_LIMIT).outcome {
+            PnsOutcome::Proven(line) => assert_eq!(line[0].to_uci(), "a1a6"),
+            outcome => panic!("expected a proof, got {:?}", outcome),
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+
+use crate::state::{board::Board, board_move_gen::Move};
+
+use super::mate::MateSolver;
+use super::stipulation::{Stipulation, StipulationKind};
+use super::{has_legal_move, is_checkmate, legal_moves};
+
+#[derive(Debug, Clone)]
+pub struct ProblemReport {
+    pub stipulation: Stipulation,
+    /// for helpmates every full solution line, for the other stipulations every key move
+    pub solutions: Vec<Vec<Move>>,
+    pub nodes: u64,
+}
+
+/// solves the given stipulation for the position, the side to move moving first
+pub fn solve(board: &Board, stipulation: Stipulation) -> ProblemReport {
+    let mut board = board.clone();
+    let moves = stipulation.moves;
+    let (solutions, nodes) = match stipulation.kind {
+        StipulationKind::Direct => {
+            let report = MateSolver::new().solve(&board, moves);
+            (
+                report.keys.into_iter().map(|m| vec![m]).collect(),
+                report.nodes,
+            )
+        }
+        StipulationKind::Help => {
+            let mut solver = HelpmateSolver::default();
+            let mut line = Vec::new();
+            let mut solutions = Vec::new();
+            solver.collect(&mut board, moves * 2, &mut line, &mut solutions);
+            (solutions, solver.nodes)
+        }
+        StipulationKind::SelfMate | StipulationKind::Reflex => {
+            let mut solver = SelfmateSolver {
+                reflex: stipulation.kind == StipulationKind::Reflex,
+                ..SelfmateSolver::default()
+            };
+            let keys = solver.keys(&mut board, moves);
+            (keys.into_iter().map(|m| vec![m]).collect(), solver.nodes)
+        }
+    };
+
+    ProblemReport {
+        stipulation,
+        solutions,
+        nodes,
+    }
+}
+
+/// enumerates cooperative lines in which the side to move is mated
+#[derive(Default)]
+struct HelpmateSolver {
+    /// (hash, plies left) -> whether any solution exists from the position
+    table: HashMap<(u64, u32), bool>,
+    nodes: u64,
+}
+
+impl HelpmateSolver {
+    /// returns true if the side to move can be helped into mate in exactly the given plies
+    fn solvable(&mut self, board: &mut Board, plies: u32) -> bool {
+        let key = (board.state.hash, plies);
+        if let Some(&known) = self.table.get(&key) {
+            return known;
+        }
+
+        let mut found = false;
+        for m in board.generate_moves() {
+            if board.make_move(m).is_err() {
+                continue;
+            }
+            self.nodes += 1;
+            found = self.completes(board, plies);
+            board.unmake_move(m);
+            if found {
+                break;
+            }
+        }
+
+        self.table.insert(key, found);
+        found
+    }
+
+    /// returns true if the position reached by a move continues into a solution
+    fn completes(&mut self, board: &mut Board, plies: u32) -> bool {
+        if plies == 1 {
+            // the final move is the opponent's and it has to mate
+            return is_checkmate(board);
+        }
+        // nobody may be mated or stalemated before the end
+        has_legal_move(board) && self.solvable(board, plies - 1)
+    }
+
+    fn collect(
+        &mut self,
+        board: &mut Board,
+        plies: u32,
+        line: &mut Vec<Move>,
+        solutions: &mut Vec<Vec<Move>>,
+    ) {
+        for m in legal_moves(board) {
+            board.make_move(m).expect("legal move rejected");
+            line.push(m);
+            if plies == 1 {
+                if is_checkmate(board) {
+                    solutions.push(line.clone());
+                }
+            } else if self.completes(board, plies) {
+                self.collect(board, plies - 1, line, solutions);
+            }
+            line.pop();
+            board.unmake_move(m);
+        }
+    }
+}
+
+/// finds the keys of selfmates and reflexmates
+#[derive(Default)]
+struct SelfmateSolver {
+    reflex: bool,
+    /// (hash, moves) -> whether the side to move forces its own mate
+    table: HashMap<(u64, u32), bool>,
+    nodes: u64,
+}
+
+impl SelfmateSolver {
+    fn keys(&mut self, board: &mut Board, moves: u32) -> Vec<Move> {
+        if self.reflex && self.can_mate(board) {
+            return Vec::new();
+        }
+        let mut keys = Vec::new();
+        for m in legal_moves(board) {
+            board.make_move(m).expect("legal move rejected");
+            if self.defender_forced(board, moves) {
+                keys.push(m);
+            }
+            board.unmake_move(m);
+        }
+        keys
+    }
+
+    /// returns true if the side to move forces its opponent to mate it within moves
+    fn forces_selfmate(&mut self, board: &mut Board, moves: u32) -> bool {
+        let key = (board.state.hash, moves);
+        if let Some(&known) = self.table.get(&key) {
+            return known;
+        }
+
+        // in a reflexmate the attacker has to mate if it can, which ends the problem in failure
+        let forced = if self.reflex && self.can_mate(board) {
+            false
+        } else {
+            let mut forced = false;
+            for m in board.generate_moves() {
+                if board.make_move(m).is_err() {
+                    continue;
+                }
+                self.nodes += 1;
+                forced = self.defender_forced(board, moves);
+                board.unmake_move(m);
+                if forced {
+                    break;
+                }
+            }
+            forced
+        };
+
+        self.table.insert(key, forced);
+        forced
+    }
+
+    /// returns true if the defender, who is to move, cannot avoid mating within moves
+    fn defender_forced(&mut self, board: &mut Board, moves: u32) -> bool {
+        let replies = legal_moves(board);
+        if replies.is_empty() {
+            return false;
+        }
+
+        let mut escapes = Vec::new();
+        for &r in &replies {
+            board.make_move(r).expect("legal move rejected");
+            self.nodes += 1;
+            let mates = is_checkmate(board);
+            board.unmake_move(r);
+            // in a reflexmate the defender has to take any mate it is offered
+            if mates && self.reflex {
+                return true;
+            }
+            if !mates {
+                escapes.push(r);
+            }
+        }
+
+        if escapes.is_empty() {
+            return true;
+        }
+        if moves == 1 {
+            return false;
+        }
+
+        for r in escapes {
+            board.make_move(r).expect("legal move rejected");
+            let forced = self.forces_selfmate(board, moves - 1);
+            board.unmake_move(r);
+            if !forced {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// returns true if the side to move has a mating move
+    fn can_mate(&mut self, board: &mut Board) -> bool {
+        for m in legal_moves(board) {
+            board.make_move(m).expect("legal move rejected");
+            let mates = is_checkmate(board);
+            board.unmake_move(m);
+            if mates {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::fen::fen_to_board;
+
+    fn solutions(fen: &str, stipulation: &str) -> Vec<Vec<String>> {
+        crate::state::init();
+        let board = fen_to_board(fen).unwrap();
+        solve(&board, stipulation.parse().unwrap())
+            .solutions
+            .iter()
+            .map(|line| line.iter().map(|m| m.to_uci()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn helpmate() {
+        let fen = "rnbqkbnr/pppp1ppp/8/4p3/8/5P2/PPPPP1PP/RNBQKBNR w KQkq - 0 2";
+        assert_eq!(solutions(fen, "h#1"), [["g2g4", "d8h4"]]);
+    }
+
+    #[test]
+    fn helpmate_without_solution() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert!(solutions(fen, "h#1").is_empty());
+    }
+
+    #[test]
+    fn selfmate() {
+        let fen = "4r2K/4B2P/6pk/pP2Q2P/3N4/8/8/4R3 w - - 0 1";
+        assert_eq!(solutions(fen, "s#1"), [["e7f8"]]);
+        assert!(solutions(fen, "#1").is_empty());
+    }
+
+    #[test]
+    fn selfmate_without_solution() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert!(solutions(fen, "s#1").is_empty());
+    }
+
+    #[test]
+    fn reflexmate() {
+        let fen = "8/8/8/8/4R3/8/2p2B2/k3K3 w - - 0 1";
+        assert_eq!(solutions(fen, "r#1"), [["e4e2"]]);
+        assert!(solutions(fen, "#1").is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StipulationKind {
+    /// the side to move forces mate
+    Direct,
+    /// both sides cooperate so that the side to move is mated
+    Help,
+    /// the side to move forces its opponent to mate it
+    SelfMate,
+    /// a selfmate in which either side has to mate whenever it can
+    Reflex,
+}
+
+/// a problem stipulation such as `#2`, `h#3`, `s#2` or `r#2`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stipulation {
+    pub kind: StipulationKind,
+    /// number of moves of the side to move
+    pub moves: u32,
+}
+
+impl FromStr for Stipulation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        let (kind, rest) = match s.split_once('#') {
+            Some(("", rest)) => (StipulationKind::Direct, rest),
+            Some(("h", rest)) => (StipulationKind::Help, rest),
+            Some(("s", rest)) => (StipulationKind::SelfMate, rest),
+            Some(("r", rest)) => (StipulationKind::Reflex, rest),
+            _ => return Err(format!("Invalid stipulation: {}", s)),
+        };
+
+        let moves: u32 = rest
+            .parse()
+            .map_err(|_| format!("Invalid number of moves in stipulation: {}", s))?;
+        if moves == 0 {
+            return Err("Stipulation must have at least one move".to_string());
+        }
+
+        Ok(Stipulation { kind, moves })
+    }
+}
+
+impl fmt::Display for Stipulation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = match self.kind {
+            StipulationKind::Direct => "",
+            StipulationKind::Help => "h",
+            StipulationKind::SelfMate => "s",
+            StipulationKind::Reflex => "r",
+        };
+        write!(f, "{}#{}", prefix, self.moves)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_kind() {
+        for (text, kind, moves) in [
+            ("#2", StipulationKind::Direct, 2),
+            ("h#3", StipulationKind::Help, 3),
+            ("s#2", StipulationKind::SelfMate, 2),
+            ("R#2", StipulationKind::Reflex, 2),
+        ] {
+            let stipulation: Stipulation = text.parse().unwrap();
+            assert_eq!(stipulation, Stipulation { kind, moves });
+            assert_eq!(stipulation.to_string(), text.to_ascii_lowercase());
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_stipulations() {
+        for text in ["x#2", "#0", "#a", "h#", "2"] {
+            assert!(text.parse::<Stipulation>().is_err(), "{}", text);
+        }
+    }
+}
@@ -1,11 +1,14 @@
+use std::sync::Once;
+
 use strum_macros::EnumIter;
 
 pub mod board;
 pub mod board_move_gen;
 pub mod fen;
 pub mod hash;
+pub mod perft;
 
-mod piece_move_gen;
+pub(crate) mod piece_move_gen;
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -19,7 +22,7 @@ pub enum GenericPiece {
 }
 
 #[repr(usize)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Color {
     White = 0,
     Black = 1,
@@ -35,7 +38,7 @@ impl Color {
 }
 
 #[repr(usize)]
-#[derive(Debug, Clone, Copy, EnumIter)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
 pub enum ColoredPiece {
     WhitePawn = 0,
     BlackPawn = 1,
@@ -52,6 +55,7 @@ pub enum ColoredPiece {
 }
 
 impl ColoredPiece {
+    #[allow(clippy::manual_is_multiple_of)]
     pub fn color(self) -> Color {
         if self as u8 % 2 == 0 {
             Color::White
@@ -128,18 +132,11 @@ pub mod castling_rights {
     pub const BLACK_ALL: u8 = BLACK_SHORT | BLACK_LONG;
     pub const ALL: u8 = WHITE_ALL | BLACK_ALL;
     pub const NONE: u8 = 0b0000;
-
-    pub const WHITE_SHORT_CLEARANCE_MASK: u64 = 0b1100000;
-    pub const WHITE_LONG_CLEARANCE_MASK: u64 = 0b1110;
-    pub const BLACK_SHORT_CLEARANCE_MASK: u64 = WHITE_SHORT_CLEARANCE_MASK << 56;
-    pub const BLACK_LONG_CLEARANCE_MASK: u64 = WHITE_LONG_CLEARANCE_MASK << 56;
-
-    pub const WHITE_SHORT_CHECK_MASK: u64 = 0b1110000;
-    pub const WHITE_LONG_CHECK_MASK: u64 = 0b11110;
-    pub const BLACK_SHORT_CHECK_MASK: u64 = WHITE_SHORT_CHECK_MASK << 56;
-    pub const BLACK_LONG_CHECK_MASK: u64 = WHITE_LONG_CHECK_MASK << 56;
 }
 
+static INIT: Once = Once::new();
+
+/// builds the move generation tables, later calls do nothing
 pub fn init() {
-    piece_move_gen::init_magic_info();
+    INIT.call_once(piece_move_gen::init_magic_info);
 }
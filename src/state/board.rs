@@ -1,10 +1,22 @@
+use std::sync::Arc;
+
 use strum::IntoEnumIterator;
 
-use super::board_move_gen::generate_moves;
-use super::{Color, ColoredPiece, GenericPiece, board_move_gen::Move, fen, hash::calculate_hash};
-use super::{game_constants, piece_move_gen};
+use super::board_move_gen::{generate_moves, move_flags};
+use super::hash::{
+    EN_PASSANT_HASH, MATERIAL_KEY_BITS, PIECE_HASH, SIDE_HASH, calculate_hash,
+    calculate_material_key, calculate_pawn_hash, get_castling_hash, material_count,
+    material_key_unit,
+};
+use super::{Color, ColoredPiece, GenericPiece, board_move_gen::Move, fen};
+use super::{castling_rights, piece_move_gen};
+use crate::eval::params::EvalParams;
+use crate::eval::psqt::Psqt;
 use crate::util::bithelpers::BitFunctions;
 
+/// rook squares of the castling rights in standard chess, indexed like castling_rooks
+pub const STANDARD_CASTLING_ROOKS: [u8; 4] = [7, 0, 63, 56];
+
 #[derive(Debug, Clone)]
 pub struct Board {
     pub pieces: [u64; 12],
@@ -14,15 +26,26 @@ pub struct Board {
     pub fullmove_clock: u16,
     pub state: BoardState,
     pub past_states: Vec<BoardState>,
+    /// incrementally updated material and piece-square sums
+    pub psqt: Psqt,
+    /// starting square of the rook of each castling right, indexed by the position of the
+    /// right's bit, so Chess960 setups can castle with rooks on any file
+    pub castling_rooks: [u8; 4],
 }
 
 #[derive(Debug, Clone)]
 pub struct BoardState {
     pub halfmove_clock: u16,
+    /// plies since the last null move, positions before it cannot repeat after it
+    pub plies_since_null: u16,
     pub en_passant: Option<u8>,
     pub castling_rights: u8,
     pub captured_piece: Option<GenericPiece>,
     pub hash: u64,
+    /// hash of the pawn placement alone, keying the pawn structure cache
+    pub pawn_hash: u64,
+    /// number of each piece on the board, keying the endgame knowledge
+    pub material_key: u64,
 }
 
 impl Board {
@@ -36,12 +59,17 @@ impl Board {
             fullmove_clock: 1,
             state: BoardState {
                 halfmove_clock: 0,
+                plies_since_null: u16::MAX,
                 en_passant: None,
                 castling_rights: 0,
                 captured_piece: None,
                 hash: 0,
+                pawn_hash: 0,
+                material_key: 0,
             },
             past_states: Vec::new(),
+            psqt: Psqt::default(),
+            castling_rooks: STANDARD_CASTLING_ROOKS,
         }
     }
 
@@ -80,6 +108,73 @@ impl Board {
     /// recalculates and sets the board hash from scratch
     pub fn recalculate_hash(&mut self) {
         self.state.hash = calculate_hash(self);
+        self.state.pawn_hash = calculate_pawn_hash(self);
+        self.state.material_key = calculate_material_key(self);
+    }
+
+    pub fn recalculate_psqt(&mut self) {
+        self.psqt = Psqt::calculate(self, Arc::clone(&self.psqt.params));
+    }
+
+    /// switches the evaluation parameters the board keeps its piece-square sums with
+    pub fn set_eval_params(&mut self, params: Arc<EvalParams>) {
+        self.psqt = Psqt::calculate(self, params);
+    }
+
+    /// returns the position with the colors swapped and the ranks mirrored, so the new side to
+    /// move stands exactly where the old one did
+    pub fn flip_vertical(&self) -> Self {
+        let rights = self.state.castling_rights;
+        // the rights of one color become the same rights of the other
+        let castling = ((rights & castling_rights::WHITE_ALL) << 2)
+            | ((rights & castling_rights::BLACK_ALL) >> 2);
+        let mut board = self.transformed(|square| square ^ 56, true, castling);
+        let rooks = self.castling_rooks.map(|square| square ^ 56);
+        board.castling_rooks = [rooks[2], rooks[3], rooks[0], rooks[1]];
+        board
+    }
+
+    /// returns the position mirrored between the a and h files, dropping the castling rights
+    /// since kings and rooks no longer stand where castling expects them
+    pub fn mirror_horizontal(&self) -> Self {
+        self.transformed(|square| square ^ 7, false, castling_rights::NONE)
+    }
+
+    /// returns the position turned half a circle with the colors swapped, which is
+    /// flip_vertical followed by mirror_horizontal, so the castling rights are dropped as well
+    pub fn rotate(&self) -> Self {
+        self.transformed(|square| square ^ 63, true, castling_rights::NONE)
+    }
+
+    /// returns the position with every square moved by map and the colors swapped if asked,
+    /// without any history and with hashes and piece-square sums recomputed
+    fn transformed(&self, map: impl Fn(u32) -> u32, swap_colors: bool, castling: u8) -> Self {
+        let mut board = Self::empty();
+        for piece in ColoredPiece::iter() {
+            let target = if swap_colors {
+                ColoredPiece::from_parts(piece.color().opposite(), piece.generic())
+            } else {
+                piece
+            };
+            self.pieces[piece as usize].bit_for_each(|square| {
+                board.pieces[target as usize] |= 1u64 << map(square);
+            });
+        }
+        board.update_occupied();
+        board.update_piece_table();
+
+        board.side_to_move = if swap_colors {
+            self.side_to_move.opposite()
+        } else {
+            self.side_to_move
+        };
+        board.fullmove_clock = self.fullmove_clock;
+        board.state.halfmove_clock = self.state.halfmove_clock;
+        board.state.en_passant = self.state.en_passant.map(|square| map(square as u32) as u8);
+        board.state.castling_rights = castling;
+        board.recalculate_hash();
+        board.set_eval_params(Arc::clone(&self.psqt.params));
+        board
     }
 
     /// panics if the board is in an invalid state
@@ -151,7 +246,25 @@ impl Board {
             "Incorrect hash. state = {}, calculated = {}",
             self.state.hash,
             calculate_hash(self)
-        )
+        );
+
+        assert_eq!(
+            self.state.pawn_hash,
+            calculate_pawn_hash(self),
+            "Incorrect pawn hash"
+        );
+
+        assert_eq!(
+            self.state.material_key,
+            calculate_material_key(self),
+            "Incorrect material key"
+        );
+
+        assert_eq!(
+            self.psqt,
+            Psqt::calculate(self, Arc::clone(&self.psqt.params)),
+            "Incorrect piece-square sums"
+        );
     }
 
     #[cfg(not(debug_assertions))]
@@ -163,8 +276,299 @@ impl Board {
         generate_moves(self)
     }
 
+    /// returns the starting square of the rook the castling right belongs to
+    pub fn castling_rook(&self, right: u8) -> u32 {
+        self.castling_rooks[right.trailing_zeros() as usize] as u32
+    }
+
+    /// returns the castling rights that survive a move from or to the square
+    fn castling_rights_kept(&self, square: u32) -> u8 {
+        let mut kept = castling_rights::ALL;
+        for (index, &rook) in self.castling_rooks.iter().enumerate() {
+            if rook as u32 == square {
+                kept &= !(1 << index);
+            }
+        }
+        kept
+    }
+
+    /// returns the colored piece on the given square, if any
+    pub fn colored_piece_at(&self, index: u32) -> Option<ColoredPiece> {
+        let generic = self.piece_table[index as usize]?;
+        let color = if self.occupied[Color::White as usize].contains(1u64 << index) {
+            Color::White
+        } else {
+            Color::Black
+        };
+        Some(ColoredPiece::from_parts(color, generic))
+    }
+
+    /// returns the square of the given color's king, or None if it has no king
+    pub fn king_square(&self, color: Color) -> Option<u32> {
+        let king = self.pieces[ColoredPiece::from_parts(color, GenericPiece::King) as usize];
+        if king == 0 {
+            None
+        } else {
+            Some(king.get_lsb())
+        }
+    }
+
+    /// returns true if the king of the given color is attacked
+    pub fn is_king_attacked(&self, color: Color) -> bool {
+        match self.king_square(color) {
+            Some(sq) => self.index_in_check(sq, color.opposite()),
+            None => false,
+        }
+    }
+
+    /// returns true if the side to move is in check
+    pub fn in_check(&self) -> bool {
+        self.is_king_attacked(self.side_to_move)
+    }
+
+    /// returns true if the given color has any pieces other than pawns and its king
+    pub fn has_non_pawn_material(&self, color: Color) -> bool {
+        let pawns = self.pieces[ColoredPiece::from_parts(color, GenericPiece::Pawn) as usize];
+        let king = self.pieces[ColoredPiece::from_parts(color, GenericPiece::King) as usize];
+        self.occupied[color as usize] & !(pawns | king) != 0
+    }
+
+    /// returns how often the current position occurred before, only looking back as far as
+    /// the last capture, pawn move or null move since nothing earlier can repeat
+    pub fn repetitions(&self) -> usize {
+        self.repetitions_since(0)
+    }
+
+    /// counts earlier occurrences of the current position stored at or after the given index
+    /// of past_states
+    pub fn repetitions_since(&self, start: usize) -> usize {
+        let reversible = (self.state.halfmove_clock.min(self.state.plies_since_null) as usize)
+            .min(self.past_states.len());
+        let first = self.past_states.len() - reversible;
+        self.past_states[first.max(start).min(self.past_states.len())..]
+            .iter()
+            .rev()
+            .skip(1)
+            .step_by(2)
+            .filter(|state| state.hash == self.state.hash)
+            .count()
+    }
+
+    /// returns true if the fifty move rule allows claiming a draw, which still has to be
+    /// checked against checkmate
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.state.halfmove_clock >= 100
+    }
+
+    /// returns all fully legal moves in the current position
+    pub fn legal_moves(&mut self) -> Vec<Move> {
+        let mut moves = self.generate_moves();
+        moves.retain(|&m| {
+            if self.make_move(m).is_ok() {
+                self.unmake_move(m);
+                true
+            } else {
+                false
+            }
+        });
+        moves
+    }
+
+    /// places the given piece on the given (empty) square, updating the hashes and psqt
+    fn add_piece(&mut self, piece: ColoredPiece, index: u32) {
+        let bit = 1u64 << index;
+        self.pieces[piece as usize] |= bit;
+        self.occupied[piece.color() as usize] |= bit;
+        self.piece_table[index as usize] = Some(piece.generic());
+        self.state.hash ^= PIECE_HASH[index as usize][piece as usize];
+        if piece.generic() == GenericPiece::Pawn {
+            self.state.pawn_hash ^= PIECE_HASH[index as usize][piece as usize];
+        }
+        debug_assert!(
+            material_count(self.state.material_key, piece) < (1 << MATERIAL_KEY_BITS) - 1,
+            "material key overflows"
+        );
+        self.state.material_key += material_key_unit(piece);
+        self.psqt.add(piece, index);
+    }
+
+    /// removes the given piece from the given square, updating the hashes and psqt
+    fn remove_piece(&mut self, piece: ColoredPiece, index: u32) {
+        let bit = 1u64 << index;
+        self.pieces[piece as usize] &= !bit;
+        self.occupied[piece.color() as usize] &= !bit;
+        self.piece_table[index as usize] = None;
+        self.state.hash ^= PIECE_HASH[index as usize][piece as usize];
+        if piece.generic() == GenericPiece::Pawn {
+            self.state.pawn_hash ^= PIECE_HASH[index as usize][piece as usize];
+        }
+        self.state.material_key -= material_key_unit(piece);
+        self.psqt.remove(piece, index);
+    }
+
+    /// applies a pseudo-legal move, saving the previous state in past_states
+    /// if the move leaves the mover's king in check it is taken back and an error is returned
     pub fn make_move(&mut self, m: Move) -> Result<(), String> {
-        todo!()
+        let side = self.side_to_move;
+        let from = m.extract_from() as u32;
+        let to = m.destination();
+        let flags = m.extract_flags() as u8;
+
+        let moving = self
+            .colored_piece_at(from)
+            .ok_or_else(|| format!("No piece to move for {}", m.to_uci()))?;
+
+        let captured = if flags == move_flags::EN_PASSANT {
+            Some(GenericPiece::Pawn)
+        } else if m.is_capture() {
+            Some(
+                self.piece_table[to as usize]
+                    .ok_or_else(|| format!("No piece to capture for {}", m.to_uci()))?,
+            )
+        } else {
+            None
+        };
+
+        self.past_states.push(self.state.clone());
+        self.state.halfmove_clock += 1;
+        self.state.plies_since_null = self.state.plies_since_null.saturating_add(1);
+        self.state.captured_piece = captured;
+        if let Some(sq) = self.state.en_passant.take() {
+            self.state.hash ^= EN_PASSANT_HASH[(sq % 8) as usize];
+        }
+
+        if let Some(generic) = captured {
+            let captured_index = match (flags, side) {
+                (move_flags::EN_PASSANT, Color::White) => to - 8,
+                (move_flags::EN_PASSANT, Color::Black) => to + 8,
+                _ => to,
+            };
+            self.remove_piece(
+                ColoredPiece::from_parts(side.opposite(), generic),
+                captured_index,
+            );
+            self.state.halfmove_clock = 0;
+        }
+
+        // in Chess960 the king may end where the rook started or the other way round, so both
+        // leave the board before either is placed
+        let rook = ColoredPiece::from_parts(side, GenericPiece::Rook);
+        let castling = m.castling_rook_squares();
+        self.remove_piece(moving, from);
+        if let Some((rook_from, _)) = castling {
+            self.remove_piece(rook, rook_from);
+        }
+        match m.promotion_piece() {
+            Some(promo) => self.add_piece(ColoredPiece::from_parts(side, promo), to),
+            None => self.add_piece(moving, to),
+        }
+        if let Some((_, rook_to)) = castling {
+            self.add_piece(rook, rook_to);
+        }
+
+        if moving.generic() == GenericPiece::Pawn {
+            self.state.halfmove_clock = 0;
+            if flags == move_flags::DOUBLE_PAWN_PUSH {
+                let ep_square = (from + to) / 2;
+                self.state.en_passant = Some(ep_square as u8);
+                self.state.hash ^= EN_PASSANT_HASH[(ep_square % 8) as usize];
+            }
+        }
+
+        let mut new_rights = self.state.castling_rights
+            & self.castling_rights_kept(from)
+            & self.castling_rights_kept(to);
+        if moving.generic() == GenericPiece::King {
+            new_rights &= match side {
+                Color::White => !castling_rights::WHITE_ALL,
+                Color::Black => !castling_rights::BLACK_ALL,
+            };
+        }
+        self.state.hash ^= get_castling_hash(self.state.castling_rights);
+        self.state.hash ^= get_castling_hash(new_rights);
+        self.state.castling_rights = new_rights;
+
+        if matches!(side, Color::Black) {
+            self.fullmove_clock += 1;
+        }
+        self.side_to_move = side.opposite();
+        self.state.hash ^= SIDE_HASH;
+
+        if self.is_king_attacked(side) {
+            self.unmake_move(m);
+            return Err(format!("Move {} leaves king in check", m.to_uci()));
+        }
+
+        Ok(())
+    }
+
+    /// takes back the given move, which must be the last move made
+    pub fn unmake_move(&mut self, m: Move) {
+        let side = self.side_to_move.opposite();
+        let from = m.extract_from() as u32;
+        let to = m.destination();
+        let flags = m.extract_flags() as u8;
+
+        self.side_to_move = side;
+        if matches!(side, Color::Black) {
+            self.fullmove_clock -= 1;
+        }
+
+        let placed = self
+            .colored_piece_at(to)
+            .expect("unmake_move called with a move that was not made");
+        let rook = ColoredPiece::from_parts(side, GenericPiece::Rook);
+        let castling = m.castling_rook_squares();
+        self.remove_piece(placed, to);
+        if let Some((_, rook_to)) = castling {
+            self.remove_piece(rook, rook_to);
+        }
+        if m.is_promotion() {
+            self.add_piece(ColoredPiece::from_parts(side, GenericPiece::Pawn), from);
+        } else {
+            self.add_piece(placed, from);
+        }
+        if let Some((rook_from, _)) = castling {
+            self.add_piece(rook, rook_from);
+        }
+
+        if let Some(generic) = self.state.captured_piece {
+            let captured_index = match (flags, side) {
+                (move_flags::EN_PASSANT, Color::White) => to - 8,
+                (move_flags::EN_PASSANT, Color::Black) => to + 8,
+                _ => to,
+            };
+            self.add_piece(
+                ColoredPiece::from_parts(side.opposite(), generic),
+                captured_index,
+            );
+        }
+
+        self.state = self
+            .past_states
+            .pop()
+            .expect("unmake_move called without a previous state");
+    }
+
+    /// passes the turn to the opponent without moving a piece
+    pub fn make_null_move(&mut self) {
+        self.past_states.push(self.state.clone());
+        self.state.plies_since_null = 0;
+        self.state.captured_piece = None;
+        if let Some(sq) = self.state.en_passant.take() {
+            self.state.hash ^= EN_PASSANT_HASH[(sq % 8) as usize];
+        }
+        self.side_to_move = self.side_to_move.opposite();
+        self.state.hash ^= SIDE_HASH;
+    }
+
+    /// takes back a null move made with make_null_move
+    pub fn unmake_null_move(&mut self) {
+        self.side_to_move = self.side_to_move.opposite();
+        self.state = self
+            .past_states
+            .pop()
+            .expect("unmake_null_move called without a previous state");
     }
 
     /// returns true if the given index is under attack by any piece of the given color
@@ -202,23 +606,286 @@ impl Board {
             return true;
         }
 
-        let index_bb = 1u64 << index;
         let pawns = self.pieces[ColoredPiece::from_parts(by_color, GenericPiece::Pawn) as usize];
-        let potential_pawn_attacks = match by_color {
-            Color::White => {
-                ((index_bb >> 9) & !game_constants::FILE_H)
-                    | ((index_bb >> 7) & !game_constants::FILE_A)
-            }
-            Color::Black => {
-                ((index_bb << 7) & !game_constants::FILE_H)
-                    | ((index_bb << 9) & !game_constants::FILE_A)
-            }
-        };
-
+        let potential_pawn_attacks = piece_move_gen::get_pawn_attackers_bb(index, by_color);
         if pawns.contains(potential_pawn_attacks) {
             return true;
         }
 
         false
     }
+
+    /// returns the pieces of the given color attacking the given index
+    /// does not consider en passant
+    pub fn attackers(&self, index: u32, by_color: Color) -> u64 {
+        let friendly = self.occupied[Color::opposite(by_color) as usize];
+        let enemy = self.occupied[by_color as usize];
+        let piece = |generic| self.pieces[ColoredPiece::from_parts(by_color, generic) as usize];
+        let queens = piece(GenericPiece::Queen);
+
+        (piece_move_gen::get_rook_moves_bb(index, friendly, enemy)
+            & (piece(GenericPiece::Rook) | queens))
+            | (piece_move_gen::get_bishop_moves_bb(index, friendly, enemy)
+                & (piece(GenericPiece::Bishop) | queens))
+            | (piece_move_gen::get_knight_moves_bb(index, friendly) & piece(GenericPiece::Knight))
+            | (piece_move_gen::get_king_moves_bb(index, friendly) & piece(GenericPiece::King))
+            | (piece_move_gen::get_pawn_attackers_bb(index, by_color) & piece(GenericPiece::Pawn))
+    }
+
+    /// returns the pieces giving check to the side to move
+    pub fn checkers(&self) -> u64 {
+        match self.king_square(self.side_to_move) {
+            Some(sq) => self.attackers(sq, self.side_to_move.opposite()),
+            None => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::fen::{board_to_fen, fen_to_board};
+
+    fn play(board: &mut Board, text: &str) {
+        let m = board
+            .legal_moves()
+            .into_iter()
+            .find(|m| m.to_uci() == text)
+            .expect("illegal move in test");
+        board.make_move(m).unwrap();
+    }
+
+    #[test]
+    fn repetition_scan_stops_at_null_move() {
+        crate::state::init();
+        let mut board = Board::default_setup();
+        board.make_null_move();
+        play(&mut board, "g8f6");
+        board.make_null_move();
+        play(&mut board, "f6g8");
+        // the start position recurs, but only across null moves
+        assert_eq!(board.repetitions(), 0);
+
+        play(&mut board, "g1f3");
+        play(&mut board, "g8f6");
+        play(&mut board, "f3g1");
+        play(&mut board, "f6g8");
+        assert_eq!(board.repetitions(), 1);
+    }
+
+    #[test]
+    fn null_move_keeps_the_fifty_move_count() {
+        crate::state::init();
+        let mut board = fen_to_board("8/8/4k3/8/8/4K3/8/7R w - - 99 80").unwrap();
+        board.make_null_move();
+        assert_eq!(board.state.halfmove_clock, 99);
+        let m = board.legal_moves()[0];
+        board.make_move(m).unwrap();
+        assert!(board.is_fifty_move_draw());
+        board.unmake_move(m);
+        board.unmake_null_move();
+        assert_eq!(board.state.halfmove_clock, 99);
+        assert!(!board.is_fifty_move_draw());
+    }
+
+    const POSITIONS: &[&str] = &[
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "2r3k1/pp3ppp/4p3/3pP3/3P4/P4N2/1P3PPP/2R3K1 b - - 0 25",
+    ];
+
+    const EN_PASSANT: &str = "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b Kq e3 0 3";
+
+    #[test]
+    fn attackers_agree_with_index_in_check() {
+        crate::state::init();
+        for fen in POSITIONS.iter().chain([&EN_PASSANT]) {
+            let board = fen_to_board(fen).unwrap();
+            for square in 0..64 {
+                for color in [Color::White, Color::Black] {
+                    assert_eq!(
+                        board.attackers(square, color) != 0,
+                        board.index_in_check(square, color),
+                        "attacks on {} by {:?} in {}",
+                        square,
+                        color,
+                        fen
+                    );
+                }
+            }
+        }
+        // pawns attack diagonally forward and never wrap around the board edge
+        let board = fen_to_board("4k3/8/8/p6P/P6p/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(board.attackers(33, Color::White), 1 << 24);
+        assert_eq!(board.attackers(46, Color::White), 1 << 39);
+        assert_eq!(board.attackers(25, Color::Black), 1 << 32);
+        assert_eq!(board.attackers(22, Color::Black), 1 << 31);
+        assert_eq!(board.attackers(31, Color::White), 0);
+        assert_eq!(board.attackers(48, Color::White), 0);
+        assert_eq!(board.attackers(23, Color::Black), 0);
+        assert_eq!(board.attackers(24, Color::Black), 0);
+    }
+
+    #[test]
+    fn flip_preserves_evaluation() {
+        crate::state::init();
+        for fen in POSITIONS.iter().chain([&EN_PASSANT]) {
+            let board = fen_to_board(fen).unwrap();
+            let flipped = board.flip_vertical();
+            assert_eq!(
+                crate::eval::evaluate(&board),
+                crate::eval::evaluate(&flipped),
+                "evaluation of {} changes when flipped",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn flip_twice_is_identity() {
+        crate::state::init();
+        for fen in POSITIONS.iter().chain([&EN_PASSANT]) {
+            let board = fen_to_board(fen).unwrap();
+            let flipped = board.flip_vertical();
+            // the keys of the flipped board match those of the same position parsed from FEN
+            let parsed = fen_to_board(&board_to_fen(&flipped)).unwrap();
+            assert_eq!(flipped.state.hash, parsed.state.hash);
+            assert_eq!(flipped.state.pawn_hash, parsed.state.pawn_hash);
+            assert_eq!(flipped.state.material_key, parsed.state.material_key);
+
+            let restored = flipped.flip_vertical();
+            assert_eq!(board_to_fen(&restored), *fen);
+            assert_eq!(restored.state.hash, board.state.hash);
+            assert_eq!(restored.state.pawn_hash, board.state.pawn_hash);
+            assert_eq!(restored.state.material_key, board.state.material_key);
+        }
+    }
+
+    #[test]
+    fn transforms_translate_en_passant_and_castling() {
+        crate::state::init();
+        let board = fen_to_board(EN_PASSANT).unwrap();
+        assert_eq!(
+            board_to_fen(&board.flip_vertical()),
+            "rnbqkbnr/pppp1ppp/8/3Pp3/8/8/PPP1PPPP/RNBQKBNR w Qk e6 0 3"
+        );
+        assert_eq!(
+            board_to_fen(&board.mirror_horizontal()),
+            "rnbkqbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBKQBNR b - d3 0 3"
+        );
+        assert_eq!(
+            board_to_fen(&board.rotate()),
+            "rnbkqbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBKQBNR w - d6 0 3"
+        );
+    }
+
+    #[test]
+    fn mirrored_positions_lose_their_castling_rights() {
+        crate::state::init();
+        let board = fen_to_board(POSITIONS[1]).unwrap();
+        for mut transformed in [board.mirror_horizontal(), board.rotate()] {
+            assert_eq!(transformed.state.castling_rights, castling_rights::NONE);
+            assert!(transformed.legal_moves().iter().all(|m| !m.is_castle()));
+            // the keys agree with the same position read back without rights
+            let parsed = fen_to_board(&board_to_fen(&transformed)).unwrap();
+            assert_eq!(transformed.state.hash, parsed.state.hash);
+        }
+    }
+
+    #[test]
+    fn chess960_castling_can_start_or_end_on_the_rook_square() {
+        crate::state::init();
+        for (fen, castle, after) in [
+            (
+                "4k3/8/8/8/8/8/8/5KR1 w K - 0 1",
+                "f1g1",
+                "4k3/8/8/8/8/8/8/5RK1 b - - 1 1",
+            ),
+            (
+                "4k3/8/8/8/8/8/8/1RK5 w Q - 0 1",
+                "c1b1",
+                "4k3/8/8/8/8/8/8/2KR4 b - - 1 1",
+            ),
+            (
+                "1r2k1r1/8/8/8/8/8/8/1R2K1R1 b KQkq - 0 1",
+                "e8b8",
+                "2kr2r1/8/8/8/8/8/8/1R2K1R1 w KQ - 1 2",
+            ),
+        ] {
+            let mut board = fen_to_board(fen).unwrap();
+            let m = board
+                .legal_moves()
+                .into_iter()
+                .find(|m| m.is_castle() && m.to_uci_chess960() == castle)
+                .expect("castle not generated");
+            board.make_move(m).unwrap();
+            assert_eq!(board_to_fen(&board), after);
+            assert_eq!(board.state.hash, fen_to_board(after).unwrap().state.hash);
+            board.unmake_move(m);
+            assert_eq!(board_to_fen(&board), fen);
+            assert_eq!(board.state.hash, fen_to_board(fen).unwrap().state.hash);
+
+            let flipped = board.flip_vertical();
+            assert_eq!(board_to_fen(&flipped.flip_vertical()), fen);
+        }
+    }
+
+    #[test]
+    fn material_key_follows_make_and_unmake() {
+        crate::state::init();
+        let check = |board: &Board, context: &str| {
+            assert_eq!(
+                board.state.material_key,
+                calculate_material_key(board),
+                "material key drifts {}",
+                context
+            );
+        };
+        // a capture promotion, a plain promotion and an en passant capture
+        for (fen, line) in [
+            (POSITIONS[4], &["d7c8q", "d8c8", "c4f7"][..]),
+            ("8/P6k/8/8/8/8/1p6/7K w - - 0 1", &["a7a8n", "b2b1r"][..]),
+            (EN_PASSANT, &["d4e3", "d2e3"][..]),
+        ] {
+            let mut board = fen_to_board(fen).unwrap();
+            let mut played = Vec::new();
+            for text in line {
+                let m = board
+                    .legal_moves()
+                    .into_iter()
+                    .find(|m| m.to_uci() == *text)
+                    .expect("illegal move in test");
+                board.make_move(m).unwrap();
+                played.push(m);
+                check(&board, &format!("after {}", text));
+            }
+            while let Some(m) = played.pop() {
+                board.unmake_move(m);
+                check(&board, &format!("after undoing {}", m.to_uci()));
+            }
+        }
+
+        let mut rng = crate::util::const_rand::XorShift64::new(0xd1b5_4a32_d192_ed03);
+        for fen in POSITIONS {
+            let mut board = fen_to_board(fen).unwrap();
+            let mut played = Vec::new();
+            for _ in 0..60 {
+                let moves = board.legal_moves();
+                if moves.is_empty() {
+                    break;
+                }
+                let m = moves[(rng.next() % moves.len() as u64) as usize];
+                board.make_move(m).unwrap();
+                played.push(m);
+                check(&board, &format!("after {} from {}", m.to_uci(), fen));
+            }
+            while let Some(m) = played.pop() {
+                board.unmake_move(m);
+            }
+            check(&board, fen);
+        }
+    }
 }
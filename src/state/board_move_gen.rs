@@ -5,7 +5,9 @@ use crate::{
 };
 
 /// 6 bits from, 6 bits to, 4 bits flags
-#[derive(Debug)]
+///
+/// castling is stored as the king taking its own rook, so it names the rook in Chess960 too
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Move {
     data: u16,
 }
@@ -35,6 +37,9 @@ const TO_MASK: u16 = 0b0000001111110000;
 const FLAGS_MASK: u16 = 0b0000000000001111;
 
 impl Move {
+    /// placeholder move (a1a1) used where no move is available
+    pub const NULL: Move = Move { data: 0 };
+
     pub fn from_parts(from: u8, to: u8, flags: u8) -> Move {
         Move {
             data: ((from as u16) << 10) | ((to as u16) << 4) | (flags as u16),
@@ -45,6 +50,10 @@ impl Move {
         Move { data }
     }
 
+    pub fn data(&self) -> u16 {
+        self.data
+    }
+
     pub fn extract_from(&self) -> u16 {
         (self.data & FROM_MASK) >> 10
     }
@@ -57,9 +66,59 @@ impl Move {
         self.data & FLAGS_MASK
     }
 
-    pub fn to_uci(&self) -> String {
+    pub fn is_capture(&self) -> bool {
+        self.extract_flags() as u8 & move_flags::CAPTURE != 0
+    }
+
+    pub fn is_promotion(&self) -> bool {
+        self.extract_flags() as u8 & move_flags::PROMO_FLAGS != 0
+    }
+
+    pub fn is_castle(&self) -> bool {
+        matches!(
+            self.extract_flags() as u8,
+            move_flags::SHORT_CASTLE | move_flags::LONG_CASTLE
+        )
+    }
+
+    /// returns the square the moving piece ends on, which for castling is the king's target
+    /// on the g or c file rather than the rook's square
+    pub fn destination(&self) -> u32 {
+        let rank = self.extract_from() as u32 / 8 * 8;
+        match self.extract_flags() as u8 {
+            move_flags::SHORT_CASTLE => rank + 6,
+            move_flags::LONG_CASTLE => rank + 2,
+            _ => self.extract_to() as u32,
+        }
+    }
+
+    /// returns the (from, to) squares of the rook for a castling move, or None for other moves
+    pub fn castling_rook_squares(&self) -> Option<(u32, u32)> {
+        let rank = self.extract_from() as u32 / 8 * 8;
+        let rook = self.extract_to() as u32;
+        match self.extract_flags() as u8 {
+            move_flags::SHORT_CASTLE => Some((rook, rank + 5)),
+            move_flags::LONG_CASTLE => Some((rook, rank + 3)),
+            _ => None,
+        }
+    }
+
+    /// returns the piece a pawn promotes to, if this move is a promotion
+    pub fn promotion_piece(&self) -> Option<GenericPiece> {
+        if !self.is_promotion() {
+            return None;
+        }
+        match self.extract_flags() as u8 & 0b11 {
+            0 => Some(GenericPiece::Knight),
+            1 => Some(GenericPiece::Bishop),
+            2 => Some(GenericPiece::Rook),
+            _ => Some(GenericPiece::Queen),
+        }
+    }
+
+    pub fn to_uci(self) -> String {
         let from = square_to_algebraic(self.extract_from() as u8);
-        let to = square_to_algebraic(self.extract_to() as u8);
+        let to = square_to_algebraic(self.destination() as u8);
         let mut out = format!("{}{}", from, to);
 
         match self.extract_flags() as u8 {
@@ -72,10 +131,102 @@ impl Move {
 
         out
     }
+
+    /// returns the move in UCI notation, with castling written as the king taking its own rook
+    /// the way Chess960 interfaces expect
+    pub fn to_uci_chess960(self) -> String {
+        if self.is_castle() {
+            let from = square_to_algebraic(self.extract_from() as u8);
+            format!("{}{}", from, square_to_algebraic(self.extract_to() as u8))
+        } else {
+            self.to_uci()
+        }
+    }
+
+    /// returns the move in standard algebraic notation, the board being the position before the
+    /// move
+    pub fn to_san(self, board: &mut Board) -> String {
+        let from = self.extract_from() as u32;
+        let to = self.extract_to() as u32;
+        let mut out = match self.extract_flags() as u8 {
+            move_flags::SHORT_CASTLE => "O-O".to_string(),
+            move_flags::LONG_CASTLE => "O-O-O".to_string(),
+            _ => {
+                let piece = board.piece_table[from as usize].expect("no piece to move");
+                let mut out = String::new();
+                if piece == GenericPiece::Pawn {
+                    if self.is_capture() {
+                        out.push((b'a' + (from % 8) as u8) as char);
+                    }
+                } else {
+                    out.push(match piece {
+                        GenericPiece::Knight => 'N',
+                        GenericPiece::Bishop => 'B',
+                        GenericPiece::Rook => 'R',
+                        GenericPiece::Queen => 'Q',
+                        _ => 'K',
+                    });
+                    out.push_str(&self.disambiguation(board, piece));
+                }
+                if self.is_capture() {
+                    out.push('x');
+                }
+                out.push_str(&square_to_algebraic(to as u8));
+                if let Some(promotion) = self.promotion_piece() {
+                    out.push('=');
+                    out.push(match promotion {
+                        GenericPiece::Knight => 'N',
+                        GenericPiece::Bishop => 'B',
+                        GenericPiece::Rook => 'R',
+                        _ => 'Q',
+                    });
+                }
+                out
+            }
+        };
+
+        if board.make_move(self).is_ok() {
+            if board.in_check() {
+                out.push(if board.legal_moves().is_empty() {
+                    '#'
+                } else {
+                    '+'
+                });
+            }
+            board.unmake_move(self);
+        }
+        out
+    }
+
+    /// returns the file, rank or square needed to tell the move apart from moves of other
+    /// pieces of the same kind to the same square
+    fn disambiguation(self, board: &mut Board, piece: GenericPiece) -> String {
+        let from = self.extract_from() as u32;
+        let others: Vec<u32> = board
+            .legal_moves()
+            .into_iter()
+            .filter(|m| {
+                m.extract_to() == self.extract_to() && m.extract_from() != self.extract_from()
+            })
+            .map(|m| m.extract_from() as u32)
+            .filter(|&other| board.piece_table[other as usize] == Some(piece))
+            .collect();
+
+        let square = square_to_algebraic(from as u8);
+        if others.is_empty() {
+            String::new()
+        } else if others.iter().all(|other| other % 8 != from % 8) {
+            square[..1].to_string()
+        } else if others.iter().all(|other| other / 8 != from / 8) {
+            square[1..].to_string()
+        } else {
+            square
+        }
+    }
 }
 
 /// returns the algebraic notation (e.g. "e4") for a given square index (0-63)
-fn square_to_algebraic(square: u8) -> String {
+pub fn square_to_algebraic(square: u8) -> String {
     debug_assert!(square < 64);
     let file = square % 8;
     let rank = square / 8;
@@ -106,11 +257,12 @@ fn generate_pawn_pushes(side: Color, pushes: u64, shift: u32, out: &mut Vec<Move
                 out.push(Move::from_parts(from_index as u8, to_index as u8, flag));
             }
         } else {
-            out.push(Move::from_parts(
-                from_index as u8,
-                to_index as u8,
-                move_flags::QUIET,
-            ));
+            let flag = if shift == 16 {
+                move_flags::DOUBLE_PAWN_PUSH
+            } else {
+                move_flags::QUIET
+            };
+            out.push(Move::from_parts(from_index as u8, to_index as u8, flag));
         }
     });
 }
@@ -144,6 +296,37 @@ fn generate_en_passant(board: &Board, out: &mut Vec<Move>) {
     });
 }
 
+/// for each set bit in captures, appends a corresponding pawn capture from the square shift
+/// indices behind to the square of the set bit
+fn generate_pawn_captures(side: Color, captures: u64, shift: u32, out: &mut Vec<Move>) {
+    captures.bit_for_each(|to_index| {
+        let from_index = match side {
+            Color::White => to_index - shift,
+            Color::Black => to_index + shift,
+        };
+
+        let is_promo = (game_constants::RANK_8 & (1u64 << to_index) != 0)
+            || (game_constants::RANK_1 & (1u64 << to_index) != 0);
+
+        if is_promo {
+            for flag in [
+                move_flags::KNIGHT_PROMO_CAPTURE,
+                move_flags::BISHOP_PROMO_CAPTURE,
+                move_flags::ROOK_PROMO_CAPTURE,
+                move_flags::QUEEN_PROMO_CAPTURE,
+            ] {
+                out.push(Move::from_parts(from_index as u8, to_index as u8, flag));
+            }
+        } else {
+            out.push(Move::from_parts(
+                from_index as u8,
+                to_index as u8,
+                move_flags::CAPTURE,
+            ));
+        }
+    });
+}
+
 /// generates all pseudo-legal pawn moves at given board state and appends them to provided vec
 fn generate_pawn_moves(board: &Board, out: &mut Vec<Move>) {
     let side = board.side_to_move;
@@ -170,74 +353,67 @@ fn generate_pawn_moves(board: &Board, out: &mut Vec<Move>) {
     };
     generate_pawn_pushes(side, double_dest, 16, out);
 
+    // white captures towards higher indices, black towards lower, so the shifts swap roles
+    let (left_dest, right_dest) = match side {
+        Color::White => (
+            (pawns << 7) & !game_constants::FILE_H & enemy,
+            (pawns << 9) & !game_constants::FILE_A & enemy,
+        ),
+        Color::Black => (
+            (pawns >> 9) & !game_constants::FILE_H & enemy,
+            (pawns >> 7) & !game_constants::FILE_A & enemy,
+        ),
+    };
+    match side {
+        Color::White => {
+            generate_pawn_captures(side, left_dest, 7, out);
+            generate_pawn_captures(side, right_dest, 9, out);
+        }
+        Color::Black => {
+            generate_pawn_captures(side, left_dest, 9, out);
+            generate_pawn_captures(side, right_dest, 7, out);
+        }
+    }
+
     generate_en_passant(board, out);
 }
 
+/// returns the squares from a to b inclusive, both being on the same rank
+fn rank_span(a: u32, b: u32) -> u64 {
+    let (low, high) = (a.min(b), a.max(b));
+    (u64::MAX >> (63 - high)) & (u64::MAX << low)
+}
+
+/// generates the castling moves of the side to move, which works for any Chess960 setup: the
+/// squares both pieces cross have to be empty but for the two of them, and the king must not
+/// be attacked on any square it crosses
 fn generate_castling(board: &Board, out: &mut Vec<Move>) {
     let side = board.side_to_move;
-    let friendly = board.occupied[side as usize];
-    let enemy = board.occupied[Color::opposite(side) as usize];
-    let occupied = friendly | enemy;
-
-    let rights = board.state.castling_rights;
-
-    let short_available = match side {
-        Color::White => rights.contains(castling_rights::WHITE_SHORT),
-        Color::Black => rights.contains(castling_rights::BLACK_SHORT),
+    let Some(king) = board.king_square(side) else {
+        return;
     };
+    let occupied = board.occupied[Color::White as usize] | board.occupied[Color::Black as usize];
+    let rank = king / 8 * 8;
 
-    if short_available {
-        let clearance_mask = match side {
-            Color::White => castling_rights::WHITE_SHORT_CLEARANCE_MASK,
-            Color::Black => castling_rights::BLACK_SHORT_CLEARANCE_MASK,
-        };
-
-        if !occupied.contains(clearance_mask) {
-            let check_mask = match side {
-                Color::White => castling_rights::WHITE_SHORT_CHECK_MASK,
-                Color::Black => castling_rights::BLACK_SHORT_CHECK_MASK,
-            };
-            if check_mask.bit_for_all(|sq| !board.index_in_check(sq, Color::opposite(side))) {
-                let (king_from, king_to) = match side {
-                    Color::White => (4u8, 6u8),
-                    Color::Black => (60u8, 62u8),
-                };
-                out.push(Move::from_parts(
-                    king_from,
-                    king_to,
-                    move_flags::SHORT_CASTLE,
-                ));
-            }
-        }
-    }
-
-    let long_available = match side {
-        Color::White => rights.contains(castling_rights::WHITE_LONG),
-        Color::Black => rights.contains(castling_rights::BLACK_LONG),
+    let (short, long) = match side {
+        Color::White => (castling_rights::WHITE_SHORT, castling_rights::WHITE_LONG),
+        Color::Black => (castling_rights::BLACK_SHORT, castling_rights::BLACK_LONG),
     };
-
-    if long_available {
-        let clearance_mask = match side {
-            Color::White => castling_rights::WHITE_LONG_CLEARANCE_MASK,
-            Color::Black => castling_rights::BLACK_LONG_CLEARANCE_MASK,
-        };
-
-        if !occupied.contains(clearance_mask) {
-            let check_mask = match side {
-                Color::White => castling_rights::WHITE_LONG_CHECK_MASK,
-                Color::Black => castling_rights::BLACK_LONG_CHECK_MASK,
-            };
-            if check_mask.bit_for_all(|sq| !board.index_in_check(sq, Color::opposite(side))) {
-                let (king_from, king_to) = match side {
-                    Color::White => (4u8, 2u8),
-                    Color::Black => (60u8, 58u8),
-                };
-                out.push(Move::from_parts(
-                    king_from,
-                    king_to,
-                    move_flags::LONG_CASTLE,
-                ));
-            }
+    for (right, flag, king_to, rook_to) in [
+        (short, move_flags::SHORT_CASTLE, rank + 6, rank + 5),
+        (long, move_flags::LONG_CASTLE, rank + 2, rank + 3),
+    ] {
+        if !board.state.castling_rights.contains(right) {
+            continue;
+        }
+        let rook = board.castling_rook(right);
+        let blockers = occupied & !(1u64 << king) & !(1u64 << rook);
+        let king_path = rank_span(king, king_to);
+        if blockers.contains(king_path | rank_span(rook, rook_to)) {
+            continue;
+        }
+        if king_path.bit_for_all(|sq| !board.index_in_check(sq, Color::opposite(side))) {
+            out.push(Move::from_parts(king as u8, rook as u8, flag));
         }
     }
 }
@@ -255,7 +431,7 @@ pub fn generate_moves(board: &Board) -> Vec<Move> {
             let knight_move_bb = piece_move_gen::get_knight_moves_bb(knight_index, friendly);
 
             knight_move_bb.bit_for_each(|to_index| {
-                let is_capture = (board.piece_table[to_index as usize].is_some());
+                let is_capture = board.piece_table[to_index as usize].is_some();
                 let flags = if is_capture {
                     move_flags::CAPTURE
                 } else {
@@ -273,7 +449,7 @@ pub fn generate_moves(board: &Board) -> Vec<Move> {
         let bishop_move_bb = piece_move_gen::get_bishop_moves_bb(bishop_index, friendly, enemy);
 
         bishop_move_bb.bit_for_each(|to_index| {
-            let is_capture = (board.piece_table[to_index as usize].is_some());
+            let is_capture = board.piece_table[to_index as usize].is_some();
             let flags = if is_capture {
                 move_flags::CAPTURE
             } else {
@@ -289,7 +465,7 @@ pub fn generate_moves(board: &Board) -> Vec<Move> {
         let rook_move_bb = piece_move_gen::get_rook_moves_bb(rook_index, friendly, enemy);
 
         rook_move_bb.bit_for_each(|to_index| {
-            let is_capture = (board.piece_table[to_index as usize].is_some());
+            let is_capture = board.piece_table[to_index as usize].is_some();
             let flags = if is_capture {
                 move_flags::CAPTURE
             } else {
@@ -304,7 +480,7 @@ pub fn generate_moves(board: &Board) -> Vec<Move> {
             let king_move_bb = piece_move_gen::get_king_moves_bb(king_index, friendly);
 
             king_move_bb.bit_for_each(|to_index| {
-                let is_capture = (board.piece_table[to_index as usize].is_some());
+                let is_capture = board.piece_table[to_index as usize].is_some();
                 let flags = if is_capture {
                     move_flags::CAPTURE
                 } else {
@@ -319,3 +495,93 @@ pub fn generate_moves(board: &Board) -> Vec<Move> {
 
     out
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::state::fen::fen_to_board;
+
+    /// returns the SAN of the move given in UCI notation
+    fn san(fen: &str, text: &str) -> String {
+        let mut board = fen_to_board(fen).unwrap();
+        let m = board
+            .legal_moves()
+            .into_iter()
+            .find(|m| m.to_uci() == text)
+            .expect("illegal move in test");
+        let san = m.to_san(&mut board);
+        assert_eq!(
+            crate::state::fen::board_to_fen(&board),
+            fen,
+            "to_san changes the board"
+        );
+        san
+    }
+
+    #[test]
+    fn san_disambiguates_by_file_rank_or_both() {
+        crate::state::init();
+        let knights = "4k3/8/8/8/8/8/8/N1N1K3 w - - 0 1";
+        assert_eq!(san(knights, "a1b3"), "Nab3");
+        assert_eq!(san(knights, "c1b3"), "Ncb3");
+        assert_eq!(san(knights, "c1d3"), "Nd3");
+
+        let rooks = "4k3/8/8/R7/8/8/8/R3K3 w - - 0 1";
+        assert_eq!(san(rooks, "a1a3"), "R1a3");
+        assert_eq!(san(rooks, "a5a3"), "R5a3");
+        assert_eq!(san(rooks, "a1b1"), "Rb1");
+
+        let queens = "4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1";
+        assert_eq!(san(queens, "a1b2"), "Qa1b2");
+        assert_eq!(san(queens, "a3b2"), "Q3b2");
+        assert_eq!(san(queens, "c1b2"), "Qcb2");
+    }
+
+    #[test]
+    fn san_writes_pawn_moves_and_promotions() {
+        crate::state::init();
+        let promotion = "3r1k2/4P3/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(san(promotion, "e7d8n"), "exd8=N");
+        assert_eq!(san(promotion, "e7e8q"), "e8=Q+");
+        assert_eq!(san(promotion, "e7e8r"), "e8=R+");
+        assert_eq!(
+            san(
+                "rnbqkbnr/ppp2ppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3",
+                "e5d6"
+            ),
+            "exd6"
+        );
+    }
+
+    #[test]
+    fn san_writes_castling() {
+        crate::state::init();
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(san(fen, "e1g1"), "O-O");
+        assert_eq!(san(fen, "e1c1"), "O-O-O");
+        // a Chess960 castle onto the rook's square
+        assert_eq!(san("4k3/8/8/8/8/8/8/5KR1 w K - 0 1", "f1g1"), "O-O");
+        // castling long checks the king on d8
+        assert_eq!(san("3k4/8/8/8/8/8/8/R3K3 w Q - 0 1", "e1c1"), "O-O-O+");
+    }
+
+    #[test]
+    fn san_marks_checks_and_mates() {
+        crate::state::init();
+        assert_eq!(
+            san(
+                "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+                "f1b5"
+            ),
+            "Bb5+"
+        );
+        assert_eq!(
+            san(
+                "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq g3 0 2",
+                "d8h4"
+            ),
+            "Qh4#"
+        );
+        assert_eq!(san("7k/8/6K1/8/8/8/8/R7 w - - 0 1", "a1a8"), "Ra8#");
+        assert_eq!(san("7k/8/8/8/8/8/8/R5K1 w - - 0 1", "a1a7"), "Ra7");
+    }
+}
@@ -1,8 +1,10 @@
-use crate::state::ColoredPiece;
+use crate::state::{ColoredPiece, GenericPiece};
+use crate::util::bithelpers::BitFunctions;
 
 use super::board::Board;
 use super::board_move_gen;
-use super::{Color, castling_rights};
+use super::hash::MAX_PIECES_PER_COLOR;
+use super::{Color, castling_rights, game_constants};
 
 /// parse a FEN string and return a Board representation
 pub fn fen_to_board(fen: &str) -> Result<Board, String> {
@@ -14,6 +16,22 @@ pub fn fen_to_board(fen: &str) -> Result<Board, String> {
     let mut board = Board::empty();
 
     parse_fen_pieces(&mut board, parts[0])?;
+    let pawns = board.pieces[ColoredPiece::WhitePawn as usize]
+        | board.pieces[ColoredPiece::BlackPawn as usize];
+    if pawns & (game_constants::RANK_1 | game_constants::RANK_8) != 0 {
+        return Err("Pawns cannot stand on the first or last rank".to_string());
+    }
+    for color in [Color::White, Color::Black] {
+        let pawns = ColoredPiece::from_parts(color, GenericPiece::Pawn);
+        if board.pieces[pawns as usize].count_set_bits() > 8
+            || board.occupied[color as usize].count_set_bits() > MAX_PIECES_PER_COLOR
+        {
+            return Err(format!(
+                "{:?} has more than 8 pawns or {} pieces",
+                color, MAX_PIECES_PER_COLOR
+            ));
+        }
+    }
     parse_fen_side_to_move(&mut board, parts[1])?;
     parse_fen_castling_rights(&mut board, parts[2])?;
     parse_fen_en_passant(&mut board, parts[3])?;
@@ -37,6 +55,7 @@ pub fn fen_to_board(fen: &str) -> Result<Board, String> {
     };
 
     board.recalculate_hash();
+    board.recalculate_psqt();
 
     board.check_representation();
     Ok(board)
@@ -96,21 +115,87 @@ fn parse_fen_side_to_move(board: &mut Board, side: &str) -> Result<(), String> {
     }
 }
 
-/// parse the castling rights part of a FEN string and update the board accordingly
+/// parse the castling rights part of a FEN string and update the board accordingly, taking
+/// KQkq for the outermost rook on that side of the king as X-FEN does and file letters for
+/// the rook on that file as Shredder-FEN does, so Chess960 positions can be set up
 fn parse_fen_castling_rights(board: &mut Board, rights: &str) -> Result<(), String> {
     for c in rights.chars() {
-        match c {
-            'K' => board.state.castling_rights |= castling_rights::WHITE_SHORT,
-            'Q' => board.state.castling_rights |= castling_rights::WHITE_LONG,
-            'k' => board.state.castling_rights |= castling_rights::BLACK_SHORT,
-            'q' => board.state.castling_rights |= castling_rights::BLACK_LONG,
-            '-' => {}
-            _ => return Err("Invalid castling rights in FEN string".to_string()),
+        if c == '-' {
+            continue;
         }
+        let color = if c.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+        let rank = match color {
+            Color::White => 0,
+            Color::Black => 56,
+        };
+        let (short, long) = match color {
+            Color::White => (castling_rights::WHITE_SHORT, castling_rights::WHITE_LONG),
+            Color::Black => (castling_rights::BLACK_SHORT, castling_rights::BLACK_LONG),
+        };
+        let king = board
+            .king_square(color)
+            .filter(|&king| king / 8 * 8 == rank);
+        let rooks = board.pieces[ColoredPiece::from_parts(color, GenericPiece::Rook) as usize]
+            & (0xFF << rank);
+
+        let (right, rook) = match c.to_ascii_lowercase() {
+            // without a king on the back rank the standard rook squares are assumed
+            'k' => {
+                let beyond = king.map_or(0, |king| rooks & (u64::MAX << king));
+                let rook = if beyond == 0 {
+                    rank + 7
+                } else {
+                    63 - beyond.leading_zeros()
+                };
+                (short, rook)
+            }
+            'q' => {
+                let beyond = king.map_or(0, |king| rooks & ((1u64 << king) - 1));
+                let rook = if beyond == 0 { rank } else { beyond.get_lsb() };
+                (long, rook)
+            }
+            file @ 'a'..='h' => {
+                let king = king.ok_or("Castling rook file without a king on the back rank")?;
+                let rook = rank + (file as u8 - b'a') as u32;
+                if rook == king {
+                    return Err("Invalid castling rights in FEN string".to_string());
+                }
+                (if rook > king { short } else { long }, rook)
+            }
+            _ => return Err("Invalid castling rights in FEN string".to_string()),
+        };
+        board.state.castling_rights |= right;
+        board.castling_rooks[right.trailing_zeros() as usize] = rook as u8;
     }
     Ok(())
 }
 
+/// returns the FEN letter of a castling right, KQkq when the rook is the outermost one on its
+/// side of the king and the file of the rook otherwise
+fn castling_char(board: &Board, right: u8) -> char {
+    let white = right & castling_rights::WHITE_ALL != 0;
+    let short = right & (castling_rights::WHITE_SHORT | castling_rights::BLACK_SHORT) != 0;
+    let color = if white { Color::White } else { Color::Black };
+    let rook = board.castling_rook(right);
+    let rank_rooks = board.pieces[ColoredPiece::from_parts(color, GenericPiece::Rook) as usize]
+        & (0xFF << (rook / 8 * 8));
+    let beyond = if short {
+        rank_rooks & (u64::MAX << rook << 1)
+    } else {
+        rank_rooks & ((1u64 << rook) - 1)
+    };
+    let c = match (beyond == 0, short) {
+        (true, true) => 'k',
+        (true, false) => 'q',
+        (false, _) => (b'a' + (rook % 8) as u8) as char,
+    };
+    if white { c.to_ascii_uppercase() } else { c }
+}
+
 /// parse the en passant target square part of a FEN string and update the board accordingly
 fn parse_fen_en_passant(board: &mut Board, en_passant: &str) -> Result<(), String> {
     if en_passant == "-" {
@@ -132,7 +217,10 @@ fn parse_fen_en_passant(board: &mut Board, en_passant: &str) -> Result<(), Strin
         .position(|&c| c == file)
         .ok_or("Invalid file in en passant")?;
     let rank_index = rank.to_digit(10).ok_or("Invalid rank in en passant")? as usize;
-    let square_index = rank_index * 8 + file_index;
+    if !(1..=8).contains(&rank_index) {
+        return Err("Invalid rank in en passant".to_string());
+    }
+    let square_index = (rank_index - 1) * 8 + file_index;
     if square_index > 63 {
         return Err("En passant square out of bounds".to_string());
     }
@@ -141,6 +229,137 @@ fn parse_fen_en_passant(board: &mut Board, en_passant: &str) -> Result<(), Strin
     Ok(())
 }
 
+/// returns the FEN character of a piece, uppercase for white
+pub fn piece_to_char(piece: ColoredPiece) -> char {
+    let c = match piece.generic() {
+        GenericPiece::Pawn => 'p',
+        GenericPiece::Knight => 'n',
+        GenericPiece::Bishop => 'b',
+        GenericPiece::Rook => 'r',
+        GenericPiece::Queen => 'q',
+        GenericPiece::King => 'k',
+    };
+    match piece.color() {
+        Color::White => c.to_ascii_uppercase(),
+        Color::Black => c,
+    }
+}
+
+/// return the FEN string of the board
 pub fn board_to_fen(board: &Board) -> String {
-    todo!();
+    let mut pieces = String::new();
+    for rank in (0..8).rev() {
+        let mut empty = 0;
+        for file in 0..8 {
+            match board.colored_piece_at(rank * 8 + file) {
+                Some(piece) => {
+                    if empty > 0 {
+                        pieces.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    pieces.push(piece_to_char(piece));
+                }
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            pieces.push_str(&empty.to_string());
+        }
+        if rank > 0 {
+            pieces.push('/');
+        }
+    }
+
+    let side_to_move = match board.side_to_move {
+        Color::White => "w",
+        Color::Black => "b",
+    };
+
+    let rights = board.state.castling_rights;
+    let castling: String = [
+        castling_rights::WHITE_SHORT,
+        castling_rights::WHITE_LONG,
+        castling_rights::BLACK_SHORT,
+        castling_rights::BLACK_LONG,
+    ]
+    .into_iter()
+    .filter(|&right| rights & right != 0)
+    .map(|right| castling_char(board, right))
+    .collect();
+    let castling = if castling.is_empty() {
+        "-".to_string()
+    } else {
+        castling
+    };
+
+    let en_passant = board
+        .state
+        .en_passant
+        .map_or("-".to_string(), board_move_gen::square_to_algebraic);
+
+    format!(
+        "{} {} {} {} {} {}",
+        pieces,
+        side_to_move,
+        castling,
+        en_passant,
+        board.state.halfmove_clock,
+        board.fullmove_clock
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        crate::state::init();
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b Kq - 3 17",
+        ] {
+            assert_eq!(board_to_fen(&fen_to_board(fen).unwrap()), fen);
+        }
+    }
+
+    #[test]
+    fn chess960_castling_rights() {
+        crate::state::init();
+        // outermost rooks keep their KQkq letters, inner rooks are written by file
+        for fen in [
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w Kk - 2 9",
+            "1r2k1rr/8/8/8/8/8/8/RR2K1R1 w KBgq - 0 1",
+        ] {
+            assert_eq!(board_to_fen(&fen_to_board(fen).unwrap()), fen);
+        }
+        // Shredder files name the same rooks as X-FEN letters
+        let shredder =
+            fen_to_board("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9")
+                .unwrap();
+        assert_eq!(
+            board_to_fen(&shredder),
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9"
+        );
+        assert_eq!(shredder.castling_rooks, [7, 5, 63, 61]);
+        assert!(fen_to_board("8/8/8/8/8/8/8/R3K2k w E - 0 1").is_err());
+        assert!(fen_to_board("4k3/8/8/8/8/8/8/R7 w A - 0 1").is_err());
+    }
+
+    #[test]
+    fn rejects_pawns_on_back_ranks() {
+        crate::state::init();
+        assert!(fen_to_board("8/8/8/8/4K3/8/8/k2P4 w - - 0 1").is_err());
+        assert!(fen_to_board("3p4/8/8/8/4K3/8/8/k7 w - - 0 1").is_err());
+    }
+
+    #[test]
+    fn rejects_impossible_piece_counts() {
+        crate::state::init();
+        // the material key holds at most 15 of one piece
+        assert!(fen_to_board("QQQQQQQQ/QQQQQQQQ/8/8/4K3/8/8/k7 w - - 0 1").is_err());
+        assert!(fen_to_board("k7/8/8/8/4K3/PPPPPPPP/P7/8 w - - 0 1").is_err());
+        assert!(fen_to_board("k7/8/8/8/8/QQQQQQQQ/QQQQQQQK/8 w - - 0 1").is_ok());
+    }
 }
@@ -2,7 +2,7 @@ use strum::IntoEnumIterator;
 
 use crate::util::{bithelpers::BitFunctions, const_rand};
 
-use super::{Color, ColoredPiece, GenericPiece, board::Board, castling_rights};
+use super::{Color, ColoredPiece, board::Board, castling_rights};
 
 pub const PIECE_HASH: [[u64; 12]; 64] = generate_piece_hash();
 pub const SIDE_HASH: u64 = generate_side_hash();
@@ -11,7 +11,7 @@ pub const EN_PASSANT_HASH: [u64; 8] = generate_en_passant_hash();
 
 const fn generate_piece_hash() -> [[u64; 12]; 64] {
     let mut table = [[0; 12]; 64];
-    let mut seed = 0x1b9013473f4957ca; // arbitrary
+    let seed = 0x1b9013473f4957ca; // arbitrary
 
     let mut rng = const_rand::XorShift64::new(seed);
 
@@ -37,7 +37,7 @@ const fn generate_side_hash() -> u64 {
 
 const fn generate_castle_hash() -> [u64; 4] {
     let mut table = [0; 4];
-    let mut seed = 0x3f30da02e189b20d; // arbitrary
+    let seed = 0x3f30da02e189b20d; // arbitrary
 
     let mut rng = const_rand::XorShift64::new(seed);
 
@@ -51,7 +51,7 @@ const fn generate_castle_hash() -> [u64; 4] {
 
 const fn generate_en_passant_hash() -> [u64; 8] {
     let mut table = [0; 8];
-    let mut seed = 0x65e4cba05505ca9c; // arbitrary
+    let seed = 0x65e4cba05505ca9c; // arbitrary
 
     let mut rng = const_rand::XorShift64::new(seed);
 
@@ -63,8 +63,49 @@ const fn generate_en_passant_hash() -> [u64; 8] {
     table
 }
 
+/// returns the Zobrist hash of only the pawns of the given board position
+pub fn calculate_pawn_hash(board: &Board) -> u64 {
+    let mut hash = 0;
+
+    for piece in [ColoredPiece::WhitePawn, ColoredPiece::BlackPawn] {
+        let mut bb = board.pieces[piece as usize];
+        while bb != 0 {
+            let lsb_index = bb.pop_lsb() as usize;
+            hash ^= PIECE_HASH[lsb_index][piece as usize];
+        }
+    }
+
+    hash
+}
+
+/// bits holding the count of one piece in the material key
+pub const MATERIAL_KEY_BITS: u32 = 4;
+/// most pieces of one color a position may have, so no count overflows into the next piece
+pub const MAX_PIECES_PER_COLOR: u32 = 16;
+const _: () = assert!(MAX_PIECES_PER_COLOR - 1 < 1 << MATERIAL_KEY_BITS);
+
+/// returns the amount the material key grows by when the piece is added to the board
+pub const fn material_key_unit(piece: ColoredPiece) -> u64 {
+    1 << (piece as u32 * MATERIAL_KEY_BITS)
+}
+
+/// returns how many of the piece the material key counts
+pub const fn material_count(key: u64, piece: ColoredPiece) -> u32 {
+    ((key >> (piece as u32 * MATERIAL_KEY_BITS)) & ((1 << MATERIAL_KEY_BITS) - 1)) as u32
+}
+
+/// returns the material key of the given board position, the count of every ColoredPiece
+/// packed into one integer
+pub fn calculate_material_key(board: &Board) -> u64 {
+    ColoredPiece::iter()
+        .map(|piece| {
+            board.pieces[piece as usize].count_set_bits() as u64 * material_key_unit(piece)
+        })
+        .sum()
+}
+
 /// returns the hash of the given castling right
-fn get_castling_hash(castling_rights: u8) -> u64 {
+pub fn get_castling_hash(castling_rights: u8) -> u64 {
     let mut hash = 0;
     if castling_rights.contains(castling_rights::WHITE_SHORT) {
         hash ^= CASTLE_HASH[0];
@@ -0,0 +1,146 @@
+use crate::state::{board::Board, board_move_gen::Move};
+
+/// counts the leaf nodes of the legal move tree to the given depth
+pub fn perft(board: &mut Board, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    if depth == 1 {
+        return board.legal_moves().len() as u64;
+    }
+
+    let mut nodes = 0;
+    for m in board.generate_moves() {
+        if board.make_move(m).is_ok() {
+            nodes += perft(board, depth - 1);
+            board.unmake_move(m);
+        }
+    }
+    nodes
+}
+
+/// returns the perft count below each legal move of the position
+pub fn divide(board: &mut Board, depth: u32) -> Vec<(Move, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    board
+        .legal_moves()
+        .into_iter()
+        .map(|m| {
+            board.make_move(m).expect("legal move could not be made");
+            let nodes = perft(board, depth - 1);
+            board.unmake_move(m);
+            (m, nodes)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{self, fen};
+
+    fn assert_perft(fen: &str, depth: u32, expected: u64) {
+        state::init();
+        let mut board = fen::fen_to_board(fen).unwrap();
+        assert_eq!(
+            perft(&mut board, depth),
+            expected,
+            "perft {} of {}",
+            depth,
+            fen
+        );
+    }
+
+    #[test]
+    fn start_position() {
+        assert_perft(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            4,
+            197_281,
+        );
+    }
+
+    #[test]
+    fn kiwipete() {
+        assert_perft(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            3,
+            97_862,
+        );
+    }
+
+    #[test]
+    fn position_3() {
+        assert_perft("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 4, 43_238);
+    }
+
+    #[test]
+    fn position_4() {
+        assert_perft(
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            3,
+            9_467,
+        );
+    }
+
+    #[test]
+    fn position_5() {
+        assert_perft(
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            3,
+            62_379,
+        );
+    }
+
+    #[test]
+    fn position_6() {
+        assert_perft(
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            3,
+            89_890,
+        );
+    }
+
+    #[test]
+    fn chess960_positions() {
+        assert_perft(
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            3,
+            12_189,
+        );
+        assert_perft(
+            "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+            3,
+            18_002,
+        );
+        assert_perft(
+            "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+            3,
+            10_471,
+        );
+        assert_perft(
+            "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+            3,
+            13_440,
+        );
+        assert_perft(
+            "1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9",
+            3,
+            14_569,
+        );
+    }
+
+    #[test]
+    fn divide_sums_to_perft() {
+        state::init();
+        let mut board = fen::fen_to_board(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        )
+        .unwrap();
+        let counts = divide(&mut board, 2);
+        assert_eq!(counts.len(), 48);
+        assert_eq!(counts.iter().map(|(_, nodes)| nodes).sum::<u64>(), 2_039);
+    }
+}
@@ -1,6 +1,6 @@
 use std::{array, sync::OnceLock};
 
-use crate::state::game_constants;
+use crate::state::{Color, game_constants};
 
 const KNIGHT_MOVES: [u64; 64] = generate_knight_moves();
 const KING_MOVES: [u64; 64] = generate_king_moves();
@@ -59,8 +59,8 @@ static BISHOP_MAGIC_INFO: OnceLock<[MagicInfo; 64]> = OnceLock::new();
 /// computes and returns the blocker mask for a rook on the given square
 fn compute_rook_mask(square: usize) -> u64 {
     debug_assert!(square < 64);
-    let rank = (square / 8);
-    let file = (square % 8);
+    let rank = square / 8;
+    let file = square % 8;
     let rank_bb = game_constants::RANK_1 << (rank * 8);
     let file_bb = game_constants::FILE_A << file;
 
@@ -85,8 +85,8 @@ fn compute_rook_mask(square: usize) -> u64 {
 /// computes and returns the blocker mask for a bishop on the given square
 fn compute_bishop_mask(square: usize) -> u64 {
     debug_assert!(square < 64);
-    let rank = (square / 8);
-    let file = (square % 8);
+    let rank = square / 8;
+    let file = square % 8;
     let directions = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
     let mut mask = 0;
     for (dr, df) in directions {
@@ -166,13 +166,13 @@ pub fn init_magic_info() {
     });
     rook_info.iter_mut().enumerate().for_each(|(square, info)| {
         debug_assert!(info.attacks.len() == (1 << (64 - info.shift)));
-        let mut mask = info.mask;
+        let mask = info.mask;
         let mut blockers: u64 = 0;
         loop {
             let index = blockers.wrapping_mul(info.magic) >> info.shift;
             info.attacks[index as usize] = compute_rook_attacks(square, blockers);
             blockers = blockers.wrapping_sub(mask) & mask;
-            if blockers != 0 {
+            if blockers == 0 {
                 break;
             }
         }
@@ -199,13 +199,13 @@ pub fn init_magic_info() {
         .iter_mut()
         .enumerate()
         .for_each(|(square, info)| {
-            let mut mask = info.mask;
+            let mask = info.mask;
             let mut blockers: u64 = 0;
             loop {
                 let index = blockers.wrapping_mul(info.magic) >> info.shift;
                 info.attacks[index as usize] = compute_bishop_attacks(square, blockers);
                 blockers = blockers.wrapping_sub(mask) & mask;
-                if blockers != 0 {
+                if blockers == 0 {
                     break;
                 }
             }
@@ -309,6 +309,22 @@ pub fn get_king_moves_bb(square: u32, friendly_occ: u64) -> u64 {
     KING_MOVES[square as usize] & !friendly_occ
 }
 
+/// returns the squares from which a pawn of the given color attacks the square
+pub fn get_pawn_attackers_bb(square: u32, color: Color) -> u64 {
+    debug_assert!(square < 64);
+    let square_bb = 1u64 << square;
+    match color {
+        Color::White => {
+            ((square_bb >> 9) & !game_constants::FILE_H)
+                | ((square_bb >> 7) & !game_constants::FILE_A)
+        }
+        Color::Black => {
+            ((square_bb << 7) & !game_constants::FILE_H)
+                | ((square_bb << 9) & !game_constants::FILE_A)
+        }
+    }
+}
+
 pub fn get_bishop_moves_bb(square: u32, friendly_occ: u64, enemy_occ: u64) -> u64 {
     debug_assert!(square < 64);
     let info = &BISHOP_MAGIC_INFO
@@ -319,7 +335,7 @@ pub fn get_bishop_moves_bb(square: u32, friendly_occ: u64, enemy_occ: u64) -> u6
     let index = blockers.wrapping_mul(info.magic) >> info.shift;
 
     debug_assert!(index < info.attacks.len() as u64);
-    info.attacks[index as usize]
+    info.attacks[index as usize] & !friendly_occ
 }
 
 pub fn get_rook_moves_bb(square: u32, friendly_occ: u64, enemy_occ: u64) -> u64 {
@@ -332,5 +348,5 @@ pub fn get_rook_moves_bb(square: u32, friendly_occ: u64, enemy_occ: u64) -> u64
     let index = blockers.wrapping_mul(info.magic) >> info.shift;
 
     debug_assert!(index < info.attacks.len() as u64);
-    info.attacks[index as usize]
+    info.attacks[index as usize] & !friendly_occ
 }
@@ -0,0 +1,588 @@
+pub mod debug;
+pub mod options;
+
+use std::io::{self, BufRead};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::eval;
+use crate::search::smp::SearchPool;
+use crate::search::tt::DEFAULT_HASH_MB;
+use crate::search::{IterationInfo, MATE, MATE_BOUND, SearchControl, SearchLimits};
+use crate::state::{board::Board, board_move_gen::Move, fen};
+use crate::xboard::XBoard;
+
+use options::OptionValue;
+
+pub const ENGINE_NAME: &str = "mautlie-rs";
+const ENGINE_AUTHOR: &str = "majorzangari";
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// words that start a new argument of the go command
+const GO_KEYWORDS: &[&str] = &[
+    "searchmoves",
+    "ponder",
+    "wtime",
+    "btime",
+    "winc",
+    "binc",
+    "movestogo",
+    "depth",
+    "nodes",
+    "mate",
+    "movetime",
+    "infinite",
+];
+
+/// returns the score as a UCI score, in centipawns or in moves to mate
+pub fn format_score(score: i32) -> String {
+    if score >= MATE_BOUND {
+        format!("mate {}", (MATE - score + 1) / 2)
+    } else if score <= -MATE_BOUND {
+        format!("mate {}", -(MATE + score) / 2)
+    } else {
+        format!("cp {}", score)
+    }
+}
+
+/// returns the legal move of the board written as the given UCI move, castling being accepted
+/// both as the king's step to the g or c file and as the king taking its own rook
+pub fn parse_move(board: &mut Board, text: &str) -> Result<Move, String> {
+    let moves = board.legal_moves();
+    // in Chess960 a king step can read like a castle, so plain moves are matched first
+    moves
+        .iter()
+        .find(|m| !m.is_castle() && m.to_uci() == text)
+        .or_else(|| {
+            moves
+                .iter()
+                .find(|m| m.is_castle() && (m.to_uci() == text || m.to_uci_chess960() == text))
+        })
+        .copied()
+        .ok_or(format!("Illegal move {}", text))
+}
+
+/// returns the expected wins, draws and losses per thousand games for the score, using a
+/// logistic model of the centipawn score
+pub fn wdl(score: i32) -> (u32, u32, u32) {
+    const OFFSET: f64 = 150.0;
+    const SCALE: f64 = 70.0;
+    if score >= MATE_BOUND {
+        return (1000, 0, 0);
+    }
+    if score <= -MATE_BOUND {
+        return (0, 0, 1000);
+    }
+    let score = score as f64;
+    let win = (1000.0 / (1.0 + ((OFFSET - score) / SCALE).exp())).round() as u32;
+    let loss = (1000.0 / (1.0 + ((OFFSET + score) / SCALE).exp())).round() as u32;
+    (win, 1000 - win - loss, loss)
+}
+
+/// signals the search running on the thread to stop until the thread finishes, since a stop
+/// sent before the search has started is cleared when it starts
+pub(crate) fn stop_search<T>(control: &SearchControl, search: &JoinHandle<T>) {
+    while !search.is_finished() {
+        control.stop();
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// settings that change how moves and scores are written, shared with the search thread
+#[derive(Default)]
+struct Output {
+    chess960: AtomicBool,
+    show_wdl: AtomicBool,
+}
+
+impl Output {
+    fn format_move(&self, m: Move) -> String {
+        if self.chess960.load(Ordering::Relaxed) {
+            m.to_uci_chess960()
+        } else {
+            m.to_uci()
+        }
+    }
+}
+
+/// prints the report of a finished iteration, one line per principal variation
+fn print_info(info: &IterationInfo, output: &Output) {
+    let millis = info.elapsed.as_millis() as u64;
+    let nps = info.nodes * 1000 / millis.max(1);
+    for (index, line) in info.lines.iter().enumerate() {
+        let multipv = if info.lines.len() > 1 {
+            format!(" multipv {}", index + 1)
+        } else {
+            String::new()
+        };
+        let wdl = if output.show_wdl.load(Ordering::Relaxed) {
+            let (win, draw, loss) = wdl(line.score);
+            format!(" wdl {} {} {}", win, draw, loss)
+        } else {
+            String::new()
+        };
+        let pv: Vec<String> = line.pv.iter().map(|&m| output.format_move(m)).collect();
+        println!(
+            "info depth {} seldepth {}{} score {}{} nodes {} nps {} hashfull {} time {} pv {}",
+            line.depth,
+            info.seldepth,
+            multipv,
+            format_score(line.score),
+            wdl,
+            info.nodes,
+            nps,
+            info.hashfull,
+            millis,
+            pv.join(" ")
+        );
+    }
+}
+
+/// parses the arguments of a go command
+fn parse_go(args: &[&str], board: &mut Board) -> Result<SearchLimits, String> {
+    fn value<T: std::str::FromStr>(args: &[&str], index: usize) -> Result<T, String> {
+        args.get(index + 1)
+            .and_then(|value| value.parse().ok())
+            .ok_or(format!("Invalid value for {}", args[index]))
+    }
+    fn millis(args: &[&str], index: usize) -> Result<Duration, String> {
+        // clocks can run negative when a GUI lets the engine overstep its time
+        let millis: i64 = value(args, index)?;
+        Ok(Duration::from_millis(millis.max(0) as u64))
+    }
+
+    let mut limits = SearchLimits::default();
+    let mut index = 0;
+    while index < args.len() {
+        match args[index] {
+            "searchmoves" => {
+                while let Some(text) = args.get(index + 1).filter(|t| !GO_KEYWORDS.contains(t)) {
+                    limits.search_moves.push(parse_move(board, text)?);
+                    index += 1;
+                }
+            }
+            "ponder" => limits.ponder = true,
+            "infinite" => limits.infinite = true,
+            keyword => {
+                match keyword {
+                    "wtime" => limits.wtime = Some(millis(args, index)?),
+                    "btime" => limits.btime = Some(millis(args, index)?),
+                    "winc" => limits.winc = Some(millis(args, index)?),
+                    "binc" => limits.binc = Some(millis(args, index)?),
+                    "movetime" => limits.movetime = Some(millis(args, index)?),
+                    "movestogo" => limits.moves_to_go = Some(value(args, index)?),
+                    "depth" => limits.depth = Some(value(args, index)?),
+                    "nodes" => limits.nodes = Some(value(args, index)?),
+                    "mate" => limits.mate = Some(value(args, index)?),
+                    other => return Err(format!("Unknown go argument {}", other)),
+                }
+                // skip the value
+                index += 1;
+            }
+        }
+        index += 1;
+    }
+    Ok(limits)
+}
+
+/// the engine side of the UCI protocol, searching on a background thread so that stop and
+/// ponderhit can arrive while it runs
+pub struct Uci {
+    board: Board,
+    /// the search pool, away on the search thread while a search runs
+    pool: Option<SearchPool>,
+    search: Option<JoinHandle<SearchPool>>,
+    control: SearchControl,
+    output: Arc<Output>,
+    /// whether the GUI lets the engine ponder, in which case bestmove names the expected reply
+    ponder: bool,
+}
+
+impl Uci {
+    pub fn new() -> Self {
+        let output = Arc::new(Output::default());
+        let mut pool = SearchPool::new(1, DEFAULT_HASH_MB);
+        let info_output = Arc::clone(&output);
+        pool.set_info_callback(Box::new(move |info| print_info(info, &info_output)));
+        Self {
+            board: Board::default_setup(),
+            control: pool.control(),
+            pool: Some(pool),
+            search: None,
+            output,
+            ponder: false,
+        }
+    }
+
+    /// reads commands from standard input until quit or the end of the input, handing over to
+    /// the xboard protocol if the interface asks for it
+    pub fn run(&mut self) {
+        let mut xboard = false;
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim() == "xboard" {
+                xboard = true;
+                break;
+            }
+            if !self.handle(&line) {
+                break;
+            }
+        }
+        self.stop();
+        // the lock on standard input is released by now, so the xboard loop can take it
+        if xboard {
+            XBoard::new().run();
+        }
+    }
+
+    /// executes one command, returning false once the engine should quit
+    pub fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = tokens.split_first() else {
+            return true;
+        };
+
+        let result = match command {
+            "uci" => {
+                println!("id name {} {}", ENGINE_NAME, env!("CARGO_PKG_VERSION"));
+                println!("id author {}", ENGINE_AUTHOR);
+                for option in options::OPTIONS {
+                    println!("{}", option.declaration());
+                }
+                println!("uciok");
+                Ok(())
+            }
+            "isready" => {
+                println!("readyok");
+                Ok(())
+            }
+            "ucinewgame" => {
+                // the running search belongs to the old game
+                self.stop();
+                self.pool_mut().clear();
+                Ok(())
+            }
+            "setoption" => self.set_option(args),
+            "position" => self.position(args),
+            "go" => self.go(args),
+            "stop" => {
+                self.stop();
+                Ok(())
+            }
+            "ponderhit" => {
+                self.control.ponderhit();
+                Ok(())
+            }
+            // debugging commands outside the protocol
+            "d" => self
+                .idle(command)
+                .map(|_| print!("{}", debug::display(&self.board))),
+            "perft" | "divide" => args
+                .first()
+                .and_then(|depth| depth.parse().ok())
+                .ok_or(format!("Usage: {} <depth>", command))
+                .and_then(|depth| {
+                    self.idle(command)?;
+                    debug::run_perft(&self.board, depth, command == "divide");
+                    Ok(())
+                }),
+            "eval" => self
+                .idle(command)
+                .map(|_| print!("{}", eval::eval_trace(&self.board))),
+            "flip" => self
+                .idle(command)
+                .map(|_| self.board = self.board.flip_vertical()),
+            "moves" => self.idle(command).map(|_| debug::print_moves(&self.board)),
+            "bench" => match args.first().map(|depth| depth.parse()) {
+                None => self
+                    .idle(command)
+                    .and_then(|_| debug::bench(debug::DEFAULT_BENCH_DEPTH)),
+                Some(Ok(depth)) => self.idle(command).and_then(|_| debug::bench(depth)),
+                Some(Err(_)) => Err("Usage: bench [depth]".to_string()),
+            },
+            "quit" => return false,
+            _ => Err(format!("Unknown command {}", command)),
+        };
+
+        if let Err(e) = result {
+            println!("info string {}", e);
+        }
+        true
+    }
+
+    /// waits for the running search to finish and returns the pool
+    fn wait(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.pool = Some(search.join().expect("search thread panicked"));
+        }
+    }
+
+    /// collects a finished search, failing if one is still running since the debugging
+    /// commands would otherwise wait on an infinite search or a ponder
+    fn idle(&mut self, command: &str) -> Result<(), String> {
+        if self
+            .search
+            .as_ref()
+            .is_some_and(|search| !search.is_finished())
+        {
+            return Err(format!("{} is not available while searching", command));
+        }
+        self.wait();
+        Ok(())
+    }
+
+    /// aborts the running search, which then reports its best move
+    fn stop(&mut self) {
+        if let Some(search) = &self.search {
+            stop_search(&self.control, search);
+        }
+        self.wait();
+    }
+
+    /// returns the search pool once no search is running
+    fn pool_mut(&mut self) -> &mut SearchPool {
+        self.wait();
+        self.pool.as_mut().expect("search pool missing")
+    }
+
+    /// handles setoption name <name> [value <value>], names and values may contain spaces
+    fn set_option(&mut self, args: &[&str]) -> Result<(), String> {
+        let usage = "Usage: setoption name <name> [value <value>]";
+        if args.first() != Some(&"name") {
+            return Err(usage.to_string());
+        }
+        let value_start = args.iter().position(|&arg| arg == "value");
+        let name = args[1..value_start.unwrap_or(args.len())].join(" ");
+        let value = value_start.map(|start| args[start + 1..].join(" "));
+        let option = options::find(&name).ok_or(format!("Unknown option {}", name))?;
+        let value = option.parse(value.as_deref())?;
+        // options are only sent while the engine is idle, a search keeps the settings it
+        // started with
+        self.idle("setoption")?;
+        self.apply_option(option.name, value)
+    }
+
+    /// changes the component the option controls
+    fn apply_option(&mut self, name: &str, value: OptionValue) -> Result<(), String> {
+        match (name, value) {
+            (options::HASH, OptionValue::Int(mb)) => self.pool_mut().resize_hash(mb as usize),
+            (options::THREADS, OptionValue::Int(threads)) => {
+                self.pool_mut().set_threads(threads as usize)
+            }
+            (options::MULTI_PV, OptionValue::Int(lines)) => {
+                let pool = self.pool_mut();
+                let mut config = pool.config().clone();
+                config.multi_pv = lines as usize;
+                pool.set_config(config);
+            }
+            (options::MOVE_OVERHEAD, OptionValue::Int(millis)) => {
+                let pool = self.pool_mut();
+                let mut config = pool.config().clone();
+                config.move_overhead = Duration::from_millis(millis as u64);
+                pool.set_config(config);
+            }
+            (options::PONDER, OptionValue::Bool(ponder)) => self.ponder = ponder,
+            (options::CHESS960, OptionValue::Bool(chess960)) => {
+                self.output.chess960.store(chess960, Ordering::Relaxed)
+            }
+            (options::SHOW_WDL, OptionValue::Bool(show)) => {
+                self.output.show_wdl.store(show, Ordering::Relaxed)
+            }
+            #[cfg(feature = "nnue")]
+            (options::EVAL_FILE, OptionValue::Text(path)) => {
+                let network = if path.is_empty() {
+                    None
+                } else {
+                    Some(Arc::new(eval::nnue::Network::load(&path)?))
+                };
+                self.pool_mut().set_network(network);
+            }
+            #[cfg(not(feature = "nnue"))]
+            (options::EVAL_FILE, OptionValue::Text(path)) => {
+                if !path.is_empty() {
+                    return Err("EvalFile needs a build with the nnue feature".to_string());
+                }
+            }
+            (options::EVAL_PARAMS, OptionValue::Text(path)) => {
+                eval::params::load_current(&path)?;
+                self.board.set_eval_params(eval::params::current());
+            }
+            (options::SYZYGY_PATH, OptionValue::Text(path)) => {
+                if !path.is_empty() {
+                    let missing = path
+                        .split(if cfg!(windows) { ';' } else { ':' })
+                        .find(|dir| !std::path::Path::new(dir).is_dir());
+                    if let Some(dir) = missing {
+                        return Err(format!("Tablebase directory {} not found", dir));
+                    }
+                    // the directories are checked so a wrong path is reported, but the search
+                    // has no tablebase prober yet
+                    println!(
+                        "info string tablebase probing is not supported, ignoring {}",
+                        path
+                    );
+                }
+            }
+            (options::CLEAR_HASH, OptionValue::Pressed) => self.pool_mut().tt().clear(),
+            (name, _) => return Err(format!("Option {} is not supported", name)),
+        }
+        Ok(())
+    }
+
+    /// sets up a new position, stopping a search of the old one
+    fn position(&mut self, args: &[&str]) -> Result<(), String> {
+        self.stop();
+        let moves_start = args.iter().position(|&arg| arg == "moves");
+        let setup = &args[..moves_start.unwrap_or(args.len())];
+        let mut board = match setup.split_first() {
+            Some((&"startpos", _)) => fen::fen_to_board(START_FEN)?,
+            Some((&"fen", fen)) => fen::fen_to_board(&fen.join(" "))?,
+            _ => return Err("Usage: position startpos|fen <fen> [moves ...]".to_string()),
+        };
+
+        if let Some(start) = moves_start {
+            for text in &args[start + 1..] {
+                let m = parse_move(&mut board, text)?;
+                board.make_move(m)?;
+            }
+        }
+        self.board = board;
+        Ok(())
+    }
+
+    fn go(&mut self, args: &[&str]) -> Result<(), String> {
+        self.stop();
+        let limits = parse_go(args, &mut self.board)?;
+        let mut pool = self.pool.take().expect("search pool missing");
+        let board = self.board.clone();
+        let output = Arc::clone(&self.output);
+        let ponder = self.ponder;
+
+        self.search = Some(thread::spawn(move || {
+            let result = pool.search(&board, limits);
+            let ponder_move = pool.ponder_move(&board, &result).filter(|_| ponder);
+            match (result.best_move, ponder_move) {
+                (Some(best), Some(ponder)) => println!(
+                    "bestmove {} ponder {}",
+                    output.format_move(best),
+                    output.format_move(ponder)
+                ),
+                (Some(best), None) => println!("bestmove {}", output.format_move(best)),
+                // without legal moves there is nothing to play
+                (None, _) => println!("bestmove 0000"),
+            }
+            pool
+        }));
+        Ok(())
+    }
+}
+
+impl Default for Uci {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    /// runs the commands on a fresh engine in another thread, failing if they hang
+    fn run_commands(commands: &'static [&'static str]) -> Uci {
+        crate::state::init();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut uci = Uci::new();
+            for command in commands {
+                assert!(uci.handle(command));
+            }
+            sender.send(uci).unwrap();
+        });
+        receiver
+            .recv_timeout(Duration::from_secs(30))
+            .expect("engine hangs on a command sent while searching")
+    }
+
+    #[test]
+    fn position_stops_an_infinite_search() {
+        let mut uci = run_commands(&[
+            "position startpos",
+            "go infinite",
+            "position startpos moves e2e4",
+            "stop",
+        ]);
+        assert!(uci.search.is_none());
+        let mut expected = Board::default_setup();
+        let m = parse_move(&mut expected, "e2e4").unwrap();
+        expected.make_move(m).unwrap();
+        assert_eq!(fen::board_to_fen(&uci.board), fen::board_to_fen(&expected));
+        assert!(uci.idle("test").is_ok());
+    }
+
+    #[test]
+    fn new_game_stops_a_ponder_search() {
+        let uci = run_commands(&[
+            "setoption name Ponder value true",
+            "position startpos moves e2e4",
+            "go ponder wtime 1000 btime 1000",
+            "ucinewgame",
+        ]);
+        assert!(uci.search.is_none());
+        assert!(uci.pool.is_some());
+    }
+
+    #[test]
+    fn options_are_rejected_while_searching() {
+        let mut uci = run_commands(&[
+            "position startpos",
+            "go infinite",
+            "setoption name MultiPV value 3",
+            "stop",
+        ]);
+        assert_eq!(uci.pool_mut().config().multi_pv, 1);
+    }
+
+    #[test]
+    fn unknown_options_are_rejected() {
+        let mut uci = run_commands(&[]);
+        for line in [
+            "name Contempt value 10",
+            "name Hash value 0",
+            "name UCI_Chess960 value maybe",
+            "name SyzygyPath value /no/such/tablebases",
+            "Hash value 16",
+        ] {
+            let args: Vec<&str> = line.split_whitespace().collect();
+            assert!(uci.set_option(&args).is_err(), "{} accepted", line);
+        }
+        assert!(
+            uci.set_option(&["name", "uci_chess960", "value", "true"])
+                .is_ok()
+        );
+        assert!(uci.output.chess960.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn castling_is_read_in_both_notations() {
+        crate::state::init();
+        let fen = "4k3/8/8/8/8/8/8/4K2R w K - 0 1";
+        for text in ["e1g1", "e1h1"] {
+            let mut board = fen::fen_to_board(fen).unwrap();
+            assert!(parse_move(&mut board, text).unwrap().is_castle());
+        }
+        // with the king on f1 the step to g1 is a plain move, the castle takes the rook
+        let fen = "4k3/8/8/8/8/8/8/5K1R w K - 0 1";
+        let mut board = fen::fen_to_board(fen).unwrap();
+        assert!(!parse_move(&mut board, "f1g1").unwrap().is_castle());
+        let castle = parse_move(&mut board, "f1h1").unwrap();
+        assert!(castle.is_castle());
+        assert_eq!(castle.to_uci_chess960(), "f1h1");
+        assert_eq!(castle.to_uci(), "f1g1");
+    }
+}
@@ -0,0 +1,112 @@
+use std::time::Instant;
+
+use crate::search::SearchLimits;
+use crate::search::smp::SearchPool;
+use crate::search::tt::DEFAULT_HASH_MB;
+use crate::state::board_move_gen::square_to_algebraic;
+use crate::state::{board::Board, fen, perft};
+use crate::util::bithelpers::BitFunctions;
+
+/// depth bench searches to unless told otherwise
+pub const DEFAULT_BENCH_DEPTH: u8 = 8;
+
+/// positions searched by bench, covering openings, middlegames and endgames
+const BENCH_FENS: &[&str] = &[
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+    "r2q1rk1/pp2bppp/2n1pn2/2bp4/3P4/2NBPN2/PP3PPP/R1BQ1RK1 w - - 0 9",
+    "r1bq1rk1/pp3ppp/2nbpn2/3p4/2PP4/2N1PN2/PP2BPPP/R2QKB1R w KQ - 2 8",
+    "2r3k1/pp3ppp/4p3/3pP3/3P4/P4N2/1P3PPP/2R3K1 w - - 0 25",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "8/8/4k3/3p4/3P4/4K3/8/8 w - - 0 1",
+    "6k1/5ppp/8/8/8/8/1R3PPP/6K1 w - - 0 1",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+];
+
+/// returns the board as a diagram followed by its FEN, hash key and checkers
+pub fn display(board: &Board) -> String {
+    let separator = " +---+---+---+---+---+---+---+---+\n";
+    let mut out = String::from(separator);
+    for rank in (0..8).rev() {
+        for file in 0..8 {
+            let c = board
+                .colored_piece_at(rank * 8 + file)
+                .map_or(' ', fen::piece_to_char);
+            out.push_str(&format!(" | {}", c));
+        }
+        out.push_str(&format!(" | {}\n{}", rank + 1, separator));
+    }
+    out.push_str("   a   b   c   d   e   f   g   h\n\n");
+
+    let mut checkers = Vec::new();
+    board
+        .checkers()
+        .bit_for_each(|square| checkers.push(square_to_algebraic(square as u8)));
+    out.push_str(&format!("Fen: {}\n", fen::board_to_fen(board)));
+    out.push_str(&format!("Key: {:016X}\n", board.state.hash));
+    out.push_str(&format!("Checkers: {}\n", checkers.join(" ")));
+    out
+}
+
+/// prints the perft count of the position, split by root move if divide is set
+pub fn run_perft(board: &Board, depth: u32, divide: bool) {
+    let mut board = board.clone();
+    let start = Instant::now();
+    let nodes = if divide {
+        let counts = perft::divide(&mut board, depth);
+        for (m, nodes) in &counts {
+            println!("{}: {}", m.to_uci(), nodes);
+        }
+        println!();
+        counts.iter().map(|(_, nodes)| nodes).sum()
+    } else {
+        perft::perft(&mut board, depth)
+    };
+    let millis = start.elapsed().as_millis() as u64;
+    println!("Nodes searched: {}", nodes);
+    println!("Time (ms): {}", millis);
+    println!("Nodes/second: {}", nodes * 1000 / millis.max(1));
+}
+
+/// prints the legal moves of the position in UCI and standard algebraic notation
+pub fn print_moves(board: &Board) {
+    let mut board = board.clone();
+    let moves = board.legal_moves();
+    let uci: Vec<String> = moves.iter().map(|m| m.to_uci()).collect();
+    let san: Vec<String> = moves.iter().map(|m| m.to_san(&mut board)).collect();
+    println!("{} legal moves", moves.len());
+    println!("uci: {}", uci.join(" "));
+    println!("san: {}", san.join(" "));
+}
+
+/// searches every bench position to the given depth on a fresh single threaded pool, so the
+/// node count is the same on every run and identifies the search
+pub fn bench(depth: u8) -> Result<(), String> {
+    let mut pool = SearchPool::new(1, DEFAULT_HASH_MB);
+    let mut total = 0;
+    let start = Instant::now();
+    for (index, fen) in BENCH_FENS.iter().enumerate() {
+        let board = fen::fen_to_board(fen)?;
+        pool.clear();
+        let limits = SearchLimits {
+            depth: Some(depth),
+            ..Default::default()
+        };
+        let result = pool.search(&board, limits);
+        println!(
+            "Position {}/{}: {} nodes, best move {}",
+            index + 1,
+            BENCH_FENS.len(),
+            result.nodes,
+            result.best_move.map_or("none".to_string(), |m| m.to_uci())
+        );
+        total += result.nodes;
+    }
+    let millis = start.elapsed().as_millis() as u64;
+    println!();
+    println!("Total time (ms): {}", millis);
+    println!("Nodes searched: {}", total);
+    println!("Nodes/second: {}", total * 1000 / millis.max(1));
+    Ok(())
+}
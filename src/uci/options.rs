@@ -0,0 +1,265 @@
+use crate::search::time::DEFAULT_MOVE_OVERHEAD_MS;
+use crate::search::tt::DEFAULT_HASH_MB;
+
+/// value UCI uses for an empty string
+const EMPTY: &str = "<empty>";
+
+/// the type of an option and its constraints
+#[derive(Debug, Clone, Copy)]
+pub enum OptionKind {
+    Spin {
+        default: i64,
+        min: i64,
+        max: i64,
+    },
+    Check {
+        default: bool,
+    },
+    Combo {
+        default: &'static str,
+        choices: &'static [&'static str],
+    },
+    String {
+        default: &'static str,
+    },
+    Button,
+}
+
+/// a checked value set through setoption
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionValue {
+    Int(i64),
+    Bool(bool),
+    Text(String),
+    Pressed,
+}
+
+/// an option the engine announces on uci
+#[derive(Debug, Clone, Copy)]
+pub struct UciOption {
+    pub name: &'static str,
+    pub kind: OptionKind,
+}
+
+pub const HASH: &str = "Hash";
+pub const THREADS: &str = "Threads";
+pub const MULTI_PV: &str = "MultiPV";
+pub const PONDER: &str = "Ponder";
+pub const MOVE_OVERHEAD: &str = "Move Overhead";
+pub const CHESS960: &str = "UCI_Chess960";
+pub const SHOW_WDL: &str = "UCI_ShowWDL";
+pub const EVAL_FILE: &str = "EvalFile";
+pub const EVAL_PARAMS: &str = "EvalParams";
+pub const SYZYGY_PATH: &str = "SyzygyPath";
+pub const CLEAR_HASH: &str = "Clear Hash";
+
+/// every option of the engine, in the order they are announced
+pub const OPTIONS: &[UciOption] = &[
+    UciOption {
+        name: HASH,
+        kind: OptionKind::Spin {
+            default: DEFAULT_HASH_MB as i64,
+            min: 1,
+            max: 65536,
+        },
+    },
+    UciOption {
+        name: THREADS,
+        kind: OptionKind::Spin {
+            default: 1,
+            min: 1,
+            max: 512,
+        },
+    },
+    UciOption {
+        name: MULTI_PV,
+        kind: OptionKind::Spin {
+            default: 1,
+            min: 1,
+            max: 256,
+        },
+    },
+    UciOption {
+        name: PONDER,
+        kind: OptionKind::Check { default: false },
+    },
+    UciOption {
+        name: MOVE_OVERHEAD,
+        kind: OptionKind::Spin {
+            default: DEFAULT_MOVE_OVERHEAD_MS as i64,
+            min: 0,
+            max: 5000,
+        },
+    },
+    UciOption {
+        name: CHESS960,
+        kind: OptionKind::Check { default: false },
+    },
+    UciOption {
+        name: SHOW_WDL,
+        kind: OptionKind::Check { default: false },
+    },
+    UciOption {
+        name: EVAL_FILE,
+        kind: OptionKind::String { default: EMPTY },
+    },
+    UciOption {
+        name: EVAL_PARAMS,
+        kind: OptionKind::String { default: EMPTY },
+    },
+    UciOption {
+        name: SYZYGY_PATH,
+        kind: OptionKind::String { default: EMPTY },
+    },
+    UciOption {
+        name: CLEAR_HASH,
+        kind: OptionKind::Button,
+    },
+];
+
+/// returns the option with the given name, which UCI compares without regard to case
+pub fn find(name: &str) -> Option<&'static UciOption> {
+    OPTIONS
+        .iter()
+        .find(|option| option.name.eq_ignore_ascii_case(name))
+}
+
+impl UciOption {
+    /// returns the line announcing the option
+    pub fn declaration(&self) -> String {
+        let kind = match self.kind {
+            OptionKind::Spin { default, min, max } => {
+                format!("spin default {} min {} max {}", default, min, max)
+            }
+            OptionKind::Check { default } => format!("check default {}", default),
+            OptionKind::Combo { default, choices } => {
+                let vars: Vec<String> = choices.iter().map(|c| format!("var {}", c)).collect();
+                format!("combo default {} {}", default, vars.join(" "))
+            }
+            OptionKind::String { default } => format!("string default {}", default),
+            OptionKind::Button => "button".to_string(),
+        };
+        format!("option name {} type {}", self.name, kind)
+    }
+
+    /// checks a value sent by setoption against the type and range of the option
+    pub fn parse(&self, value: Option<&str>) -> Result<OptionValue, String> {
+        let value = value.map(str::trim);
+        match self.kind {
+            OptionKind::Button => Ok(OptionValue::Pressed),
+            OptionKind::String { .. } => Ok(OptionValue::Text(match value {
+                None | Some(EMPTY) => String::new(),
+                Some(text) => text.to_string(),
+            })),
+            _ => {
+                let value = value.ok_or(format!("Missing value for {}", self.name))?;
+                self.parse_value(value)
+            }
+        }
+    }
+
+    fn parse_value(&self, value: &str) -> Result<OptionValue, String> {
+        match self.kind {
+            OptionKind::Spin { min, max, .. } => {
+                let number: i64 = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value {} for {}", value, self.name))?;
+                if !(min..=max).contains(&number) {
+                    return Err(format!("{} must be between {} and {}", self.name, min, max));
+                }
+                Ok(OptionValue::Int(number))
+            }
+            OptionKind::Check { .. } => match value.to_ascii_lowercase().as_str() {
+                "true" => Ok(OptionValue::Bool(true)),
+                "false" => Ok(OptionValue::Bool(false)),
+                _ => Err(format!("Invalid value {} for {}", value, self.name)),
+            },
+            OptionKind::Combo { choices, .. } => choices
+                .iter()
+                .find(|choice| choice.eq_ignore_ascii_case(value))
+                .map(|choice| OptionValue::Text(choice.to_string()))
+                .ok_or(format!("Invalid value {} for {}", value, self.name)),
+            OptionKind::String { .. } | OptionKind::Button => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(name: &str, value: Option<&str>) -> Result<OptionValue, String> {
+        find(name).expect("option missing").parse(value)
+    }
+
+    #[test]
+    fn spin_values_must_be_in_range() {
+        crate::state::init();
+        assert_eq!(parse(HASH, Some("64")), Ok(OptionValue::Int(64)));
+        assert_eq!(parse(THREADS, Some(" 512 ")), Ok(OptionValue::Int(512)));
+        assert!(parse(HASH, Some("0")).is_err());
+        assert!(parse(THREADS, Some("513")).is_err());
+        assert!(parse(MULTI_PV, Some("-1")).is_err());
+        assert!(parse(MOVE_OVERHEAD, Some("fast")).is_err());
+        assert!(parse(HASH, None).is_err());
+    }
+
+    #[test]
+    fn check_and_combo_values_must_be_known() {
+        crate::state::init();
+        assert_eq!(parse(PONDER, Some("TRUE")), Ok(OptionValue::Bool(true)));
+        assert_eq!(parse(CHESS960, Some("false")), Ok(OptionValue::Bool(false)));
+        assert!(parse(SHOW_WDL, Some("yes")).is_err());
+        assert!(parse(PONDER, Some("1")).is_err());
+        assert!(parse(CHESS960, None).is_err());
+
+        let style = UciOption {
+            name: "Style",
+            kind: OptionKind::Combo {
+                default: "Normal",
+                choices: &["Solid", "Normal", "Risky"],
+            },
+        };
+        assert_eq!(
+            style.parse(Some("risky")),
+            Ok(OptionValue::Text("Risky".to_string()))
+        );
+        assert!(style.parse(Some("Reckless")).is_err());
+        assert!(style.parse(None).is_err());
+    }
+
+    #[test]
+    fn strings_and_buttons_take_any_value() {
+        crate::state::init();
+        assert_eq!(
+            parse(SYZYGY_PATH, None),
+            Ok(OptionValue::Text(String::new()))
+        );
+        assert_eq!(
+            parse(EVAL_FILE, Some(EMPTY)),
+            Ok(OptionValue::Text(String::new()))
+        );
+        assert_eq!(
+            parse(EVAL_PARAMS, Some("my params.txt")),
+            Ok(OptionValue::Text("my params.txt".to_string()))
+        );
+        assert_eq!(parse(CLEAR_HASH, None), Ok(OptionValue::Pressed));
+    }
+
+    #[test]
+    fn names_are_found_without_regard_to_case() {
+        crate::state::init();
+        assert_eq!(find("hash").map(|option| option.name), Some(HASH));
+        assert_eq!(
+            find("uci_chess960").map(|option| option.name),
+            Some(CHESS960)
+        );
+        assert_eq!(
+            find("move overhead").map(|option| option.name),
+            Some(MOVE_OVERHEAD)
+        );
+        assert!(find("Contempt").is_none());
+        assert!(find("Hash Size").is_none());
+        assert!(find("").is_none());
+    }
+}
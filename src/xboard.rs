@@ -0,0 +1,593 @@
+use std::io::{self, BufRead};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::search::smp::SearchPool;
+use crate::search::tt::DEFAULT_HASH_MB;
+use crate::search::{IterationInfo, MATE, MATE_BOUND, SearchControl, SearchLimits};
+use crate::state::{Color, board::Board, board_move_gen::Move, fen};
+use crate::uci::{ENGINE_NAME, parse_move, stop_search};
+
+/// xboard reports mate in n moves as this plus n
+const MATE_SCORE: i32 = 100_000;
+
+/// time per move when go is sent before any level, st or time command, so the engine still
+/// answers instead of searching to the maximum depth
+const DEFAULT_MOVE_TIME: Duration = Duration::from_secs(5);
+
+/// commands the engine accepts but has nothing to do for
+const IGNORED: &[&str] = &[
+    "accepted", "rejected", "random", "hard", "easy", "computer", "name", "rating", "ics", "draw",
+    ".", "variant", "hint", "bk", "nps", "option",
+];
+
+/// returns the score the way xboard expects it in thinking output
+pub fn format_score(score: i32) -> i32 {
+    if score >= MATE_BOUND {
+        MATE_SCORE + (MATE - score + 1) / 2
+    } else if score <= -MATE_BOUND {
+        -MATE_SCORE - (MATE + score) / 2
+    } else {
+        score
+    }
+}
+
+/// returns the result message if the side to move has no legal moves
+fn game_result(board: &mut Board) -> Option<&'static str> {
+    if !board.legal_moves().is_empty() {
+        return None;
+    }
+    Some(match (board.in_check(), board.side_to_move) {
+        (true, Color::White) => "0-1 {Black mates}",
+        (true, Color::Black) => "1-0 {White mates}",
+        (false, _) => "1/2-1/2 {Stalemate}",
+    })
+}
+
+/// settings shared with the search thread
+#[derive(Default)]
+struct Output {
+    /// print thinking output while playing
+    post: AtomicBool,
+    analyzing: AtomicBool,
+    /// the move of the running search is no longer wanted
+    abandoned: AtomicBool,
+}
+
+/// prints the best line of a finished iteration as thinking output
+fn print_thinking(info: &IterationInfo, output: &Output) {
+    if !output.post.load(Ordering::Relaxed) && !output.analyzing.load(Ordering::Relaxed) {
+        return;
+    }
+    let Some(line) = info.lines.first() else {
+        return;
+    };
+    let pv: Vec<String> = line.pv.iter().map(|m| m.to_uci()).collect();
+    println!(
+        "{} {} {} {} {}",
+        line.depth,
+        format_score(line.score),
+        info.elapsed.as_millis() / 10,
+        info.nodes,
+        pv.join(" ")
+    );
+}
+
+/// the time control set by level and st, and the clocks reported by time and otim
+#[derive(Debug, Clone, Copy, Default)]
+struct Clock {
+    /// moves per session, 0 for the whole game
+    moves_per_session: u32,
+    increment: Duration,
+    /// fixed time per move
+    move_time: Option<Duration>,
+    depth: Option<u8>,
+    engine: Option<Duration>,
+    opponent: Option<Duration>,
+}
+
+/// parses a level base time, given in minutes or as minutes:seconds
+fn parse_base(text: &str) -> Option<Duration> {
+    let (minutes, seconds) = text.split_once(':').unwrap_or((text, "0"));
+    let seconds = minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+/// parses a clock given in centiseconds, which can run negative when the engine oversteps
+fn parse_centis(text: Option<&&str>) -> Result<Duration, String> {
+    let centis: i64 = text
+        .and_then(|t| t.parse().ok())
+        .ok_or("Invalid time".to_string())?;
+    Ok(Duration::from_millis(centis.max(0) as u64 * 10))
+}
+
+/// the engine side of the xboard protocol, sharing the search with the UCI front end
+pub struct XBoard {
+    board: Board,
+    /// moves played since the last new or setboard, for undo
+    history: Vec<Move>,
+    /// the search pool, away on the search thread while a search runs
+    pool: Option<SearchPool>,
+    /// the running search, returning the move it played
+    search: Option<JoinHandle<(SearchPool, Option<Move>)>>,
+    control: SearchControl,
+    output: Arc<Output>,
+    /// the engine only records moves in force mode
+    force: bool,
+    engine_color: Color,
+    clock: Clock,
+}
+
+impl XBoard {
+    pub fn new() -> Self {
+        let output = Arc::new(Output::default());
+        let mut pool = SearchPool::new(1, DEFAULT_HASH_MB);
+        let info_output = Arc::clone(&output);
+        pool.set_info_callback(Box::new(move |info| print_thinking(info, &info_output)));
+        Self {
+            board: Board::default_setup(),
+            history: Vec::new(),
+            control: pool.control(),
+            pool: Some(pool),
+            search: None,
+            output,
+            force: false,
+            engine_color: Color::Black,
+            clock: Clock::default(),
+        }
+    }
+
+    /// reads commands from standard input until quit or the end of the input
+    pub fn run(&mut self) {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if !self.handle(&line) {
+                break;
+            }
+        }
+        self.abandon();
+    }
+
+    /// executes one command, returning false once the engine should quit
+    pub fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = tokens.split_first() else {
+            return true;
+        };
+
+        let result = match command {
+            "xboard" => Ok(()),
+            "protover" => {
+                println!(
+                    "feature myname=\"{} {}\" ping=1 setboard=1 usermove=1 time=1 draw=0 \
+                     sigint=0 sigterm=0 analyze=1 colors=0 memory=1 smp=1 variants=\"normal\" \
+                     done=1",
+                    ENGINE_NAME,
+                    env!("CARGO_PKG_VERSION")
+                );
+                Ok(())
+            }
+            "ping" => {
+                println!("pong {}", args.first().unwrap_or(&""));
+                Ok(())
+            }
+            "new" => {
+                self.abandon();
+                self.set_board(Board::default_setup());
+                self.pool_mut().clear();
+                self.force = false;
+                self.engine_color = Color::Black;
+                self.clock.depth = None;
+                Ok(())
+            }
+            "setboard" => fen::fen_to_board(&args.join(" ")).map(|board| {
+                self.abandon();
+                self.set_board(board);
+                self.restart_analysis();
+            }),
+            "usermove" => match args.first() {
+                Some(text) => self.user_move(text),
+                None => Err("Usage: usermove <move>".to_string()),
+            },
+            "go" => {
+                self.abandon();
+                self.force = false;
+                self.engine_color = self.board.side_to_move;
+                self.think();
+                Ok(())
+            }
+            "force" => {
+                self.abandon();
+                self.force = true;
+                Ok(())
+            }
+            "?" => {
+                if let Some(search) = &self.search {
+                    stop_search(&self.control, search);
+                }
+                Ok(())
+            }
+            "level" => self.level(args),
+            "st" => args
+                .first()
+                .and_then(|seconds| seconds.parse::<f64>().ok())
+                .map(|seconds| self.clock.move_time = Some(Duration::from_secs_f64(seconds)))
+                .ok_or("Usage: st <seconds>".to_string()),
+            "sd" => args
+                .first()
+                .and_then(|depth| depth.parse().ok())
+                .map(|depth| self.clock.depth = Some(depth))
+                .ok_or("Usage: sd <depth>".to_string()),
+            "time" => parse_centis(args.first()).map(|time| self.clock.engine = Some(time)),
+            "otim" => parse_centis(args.first()).map(|time| self.clock.opponent = Some(time)),
+            "undo" => self.undo(1),
+            "remove" => self.undo(2),
+            "post" => {
+                self.output.post.store(true, Ordering::Relaxed);
+                Ok(())
+            }
+            "nopost" => {
+                self.output.post.store(false, Ordering::Relaxed);
+                Ok(())
+            }
+            "analyze" => {
+                self.abandon();
+                self.output.analyzing.store(true, Ordering::Relaxed);
+                self.restart_analysis();
+                Ok(())
+            }
+            "exit" => {
+                self.abandon();
+                self.output.analyzing.store(false, Ordering::Relaxed);
+                Ok(())
+            }
+            "result" => {
+                self.abandon();
+                self.force = true;
+                Ok(())
+            }
+            "memory" => args
+                .first()
+                .and_then(|mb| mb.parse::<usize>().ok())
+                .filter(|&mb| mb > 0)
+                .map(|mb| self.configure_pool(|pool| pool.resize_hash(mb)))
+                .ok_or("Usage: memory <mb>".to_string()),
+            "cores" => args
+                .first()
+                .and_then(|cores| cores.parse::<usize>().ok())
+                .map(|cores| self.configure_pool(|pool| pool.set_threads(cores)))
+                .ok_or("Usage: cores <n>".to_string()),
+            "quit" => return false,
+            ignored if IGNORED.contains(&ignored) => Ok(()),
+            // old interfaces send moves without usermove
+            text => {
+                // a finished search may still have a move to play, but waiting on a running
+                // one would block until it ends, which an analysis never does
+                if self.search.as_ref().is_some_and(JoinHandle::is_finished) {
+                    self.wait();
+                }
+                if parse_move(&mut self.board, text).is_ok() {
+                    self.user_move(text)
+                } else {
+                    println!("Error (unknown command): {}", command);
+                    Ok(())
+                }
+            }
+        };
+
+        if let Err(e) = result {
+            println!("Error ({}): {}", e, line);
+        }
+        true
+    }
+
+    /// waits for the running search to finish, playing the move it chose
+    fn wait(&mut self) {
+        if let Some(search) = self.search.take() {
+            let (pool, played) = search.join().expect("search thread panicked");
+            self.pool = Some(pool);
+            if let Some(m) = played {
+                self.play(m);
+            }
+        }
+    }
+
+    /// stops the running search without playing its move
+    fn abandon(&mut self) {
+        self.output.abandoned.store(true, Ordering::Relaxed);
+        if let Some(search) = &self.search {
+            stop_search(&self.control, search);
+        }
+        self.wait();
+    }
+
+    /// returns the search pool once no search is running
+    fn pool_mut(&mut self) -> &mut SearchPool {
+        self.wait();
+        self.pool.as_mut().expect("search pool missing")
+    }
+
+    /// changes the search pool, stopping an analysis for the change and resuming it afterwards
+    fn configure_pool(&mut self, change: impl FnOnce(&mut SearchPool)) {
+        if self.output.analyzing.load(Ordering::Relaxed) {
+            self.abandon();
+        }
+        change(self.pool_mut());
+        self.restart_analysis();
+    }
+
+    fn set_board(&mut self, board: Board) {
+        self.board = board;
+        self.history.clear();
+    }
+
+    fn play(&mut self, m: Move) {
+        self.board
+            .make_move(m)
+            .expect("searched move could not be played");
+        self.history.push(m);
+    }
+
+    fn user_move(&mut self, text: &str) -> Result<(), String> {
+        self.abandon();
+        let Ok(m) = parse_move(&mut self.board, text) else {
+            println!("Illegal move: {}", text);
+            return Ok(());
+        };
+        self.play(m);
+
+        if self.output.analyzing.load(Ordering::Relaxed) {
+            self.restart_analysis();
+        } else if !self.force && self.board.side_to_move == self.engine_color {
+            self.think();
+        }
+        Ok(())
+    }
+
+    /// takes back the given number of moves
+    fn undo(&mut self, count: usize) -> Result<(), String> {
+        self.abandon();
+        if self.history.len() < count {
+            return Err("no moves to undo".to_string());
+        }
+        for _ in 0..count {
+            let m = self.history.pop().expect("undo without history");
+            self.board.unmake_move(m);
+        }
+        self.restart_analysis();
+        Ok(())
+    }
+
+    /// handles level <moves per session> <base> <increment>
+    fn level(&mut self, args: &[&str]) -> Result<(), String> {
+        let usage = "Usage: level <moves> <minutes[:seconds]> <increment>";
+        let [moves, base, increment] = args else {
+            return Err(usage.to_string());
+        };
+        let moves = moves.parse().map_err(|_| usage.to_string())?;
+        let base = parse_base(base).ok_or(usage.to_string())?;
+        let increment: f64 = increment.parse().map_err(|_| usage.to_string())?;
+        self.clock = Clock {
+            moves_per_session: moves,
+            increment: Duration::from_secs_f64(increment),
+            move_time: None,
+            engine: Some(base),
+            opponent: Some(base),
+            ..self.clock
+        };
+        Ok(())
+    }
+
+    /// returns the limits of a search for the side to move under the current time control
+    fn limits(&self) -> SearchLimits {
+        let mut limits = SearchLimits {
+            depth: self.clock.depth,
+            ..Default::default()
+        };
+        if let Some(move_time) = self.clock.move_time {
+            limits.movetime = Some(move_time);
+        } else if let Some(engine) = self.clock.engine {
+            let side = self.board.side_to_move;
+            let opponent = self.clock.opponent.unwrap_or(engine);
+            let (white, black) = match side {
+                Color::White => (engine, opponent),
+                Color::Black => (opponent, engine),
+            };
+            limits.wtime = Some(white);
+            limits.btime = Some(black);
+            limits.winc = Some(self.clock.increment);
+            limits.binc = Some(self.clock.increment);
+            if self.clock.moves_per_session > 0 {
+                let played = (self.board.fullmove_clock.max(1) - 1) as u32;
+                limits.moves_to_go =
+                    Some(self.clock.moves_per_session - played % self.clock.moves_per_session);
+            }
+        } else {
+            limits.movetime = Some(DEFAULT_MOVE_TIME);
+        }
+        limits
+    }
+
+    /// searches the position on a background thread, playing the best move unless analyzing
+    fn start(&mut self, limits: SearchLimits, play: bool) {
+        let mut pool = self.pool.take().expect("search pool missing");
+        let mut board = self.board.clone();
+        let output = Arc::clone(&self.output);
+        output.abandoned.store(false, Ordering::Relaxed);
+
+        self.search = Some(thread::spawn(move || {
+            let result = pool.search(&board, limits);
+            let played = result
+                .best_move
+                .filter(|_| play && !output.abandoned.load(Ordering::Relaxed));
+            if let Some(m) = played {
+                println!("move {}", m.to_uci());
+                if board.make_move(m).is_ok()
+                    && let Some(message) = game_result(&mut board)
+                {
+                    println!("{}", message);
+                }
+            }
+            (pool, played)
+        }));
+    }
+
+    fn think(&mut self) {
+        self.wait();
+        if let Some(message) = game_result(&mut self.board) {
+            println!("{}", message);
+            return;
+        }
+        let limits = self.limits();
+        self.start(limits, true);
+    }
+
+    /// starts analysing the current position if in analyze mode
+    fn restart_analysis(&mut self) {
+        if !self.output.analyzing.load(Ordering::Relaxed) {
+            return;
+        }
+        self.wait();
+        let limits = SearchLimits {
+            infinite: true,
+            ..Default::default()
+        };
+        self.start(limits, false);
+    }
+}
+
+impl Default for XBoard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    /// runs the commands on a fresh engine in another thread and waits for the move of the
+    /// last search, failing if they hang
+    fn run_commands(commands: &'static [&'static str]) -> XBoard {
+        crate::state::init();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut xboard = XBoard::new();
+            for command in commands {
+                assert!(xboard.handle(command));
+            }
+            xboard.wait();
+            sender.send(xboard).unwrap();
+        });
+        receiver
+            .recv_timeout(Duration::from_secs(30))
+            .expect("engine hangs on a command")
+    }
+
+    fn fen_after(moves: &[&str]) -> String {
+        let mut board = Board::default_setup();
+        for text in moves {
+            let m = parse_move(&mut board, text).unwrap();
+            board.make_move(m).unwrap();
+        }
+        fen::board_to_fen(&board)
+    }
+
+    #[test]
+    fn user_moves_are_answered_unless_forced() {
+        let xboard = run_commands(&["new", "st 0.05", "usermove e2e4"]);
+        assert_eq!(xboard.history.len(), 2);
+        assert_eq!(xboard.board.side_to_move, Color::White);
+
+        // old interfaces send the move without usermove
+        let xboard = run_commands(&["new", "force", "usermove e2e4", "e7e5"]);
+        assert!(xboard.search.is_none());
+        assert_eq!(
+            fen::board_to_fen(&xboard.board),
+            fen_after(&["e2e4", "e7e5"])
+        );
+
+        let xboard = run_commands(&["new", "force", "usermove e2e5", "usermove"]);
+        assert!(xboard.history.is_empty());
+    }
+
+    #[test]
+    fn go_plays_for_the_side_to_move() {
+        let xboard = run_commands(&["new", "force", "usermove e2e4", "st 0.05", "go"]);
+        assert_eq!(xboard.engine_color, Color::Black);
+        assert!(!xboard.force);
+        assert_eq!(xboard.history.len(), 2);
+        assert_eq!(xboard.history[0].to_uci(), "e2e4");
+
+        // a mated side has nothing to play
+        let xboard = run_commands(&[
+            "setboard rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+            "st 0.05",
+            "go",
+        ]);
+        assert!(xboard.history.is_empty());
+    }
+
+    #[test]
+    fn undo_and_remove_take_moves_back() {
+        let mut xboard = run_commands(&[
+            "new",
+            "force",
+            "usermove e2e4",
+            "usermove e7e5",
+            "usermove g1f3",
+            "undo",
+        ]);
+        assert_eq!(
+            fen::board_to_fen(&xboard.board),
+            fen_after(&["e2e4", "e7e5"])
+        );
+        assert!(xboard.handle("remove"));
+        assert_eq!(fen::board_to_fen(&xboard.board), fen_after(&[]));
+        assert!(xboard.undo(1).is_err());
+        assert!(xboard.history.is_empty());
+    }
+
+    #[test]
+    fn level_sets_the_clocks() {
+        let mut xboard = run_commands(&["level 40 5 0"]);
+        let limits = xboard.limits();
+        assert_eq!(limits.wtime, Some(Duration::from_secs(300)));
+        assert_eq!(limits.btime, Some(Duration::from_secs(300)));
+        assert_eq!(limits.winc, Some(Duration::ZERO));
+        assert_eq!(limits.moves_to_go, Some(40));
+        assert_eq!(limits.movetime, None);
+
+        assert!(xboard.handle("level 0 2:30 3"));
+        assert!(xboard.handle("time 12000"));
+        assert!(xboard.handle("otim 9000"));
+        let limits = xboard.limits();
+        assert_eq!(limits.wtime, Some(Duration::from_secs(120)));
+        assert_eq!(limits.btime, Some(Duration::from_secs(90)));
+        assert_eq!(limits.binc, Some(Duration::from_secs(3)));
+        assert_eq!(limits.moves_to_go, None);
+
+        // a malformed level keeps the clock
+        assert!(xboard.handle("level 40 five 0"));
+        assert_eq!(xboard.limits().wtime, Some(Duration::from_secs(120)));
+    }
+
+    #[test]
+    fn go_without_a_time_control_uses_a_default_move_time() {
+        crate::state::init();
+        let mut xboard = XBoard::new();
+        assert_eq!(xboard.limits().movetime, Some(DEFAULT_MOVE_TIME));
+        assert!(xboard.handle("sd 3"));
+        let limits = xboard.limits();
+        assert_eq!(limits.movetime, Some(DEFAULT_MOVE_TIME));
+        assert_eq!(limits.depth, Some(3));
+        assert!(xboard.handle("st 2"));
+        assert_eq!(xboard.limits().movetime, Some(Duration::from_secs(2)));
+    }
+}